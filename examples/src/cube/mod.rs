@@ -289,6 +289,7 @@ impl crate::framework::Example for Example {
                                 dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
                             },
                             alpha: wgpu::BlendComponent::REPLACE,
+                            advanced: None,
                         }),
                         write_mask: wgpu::ColorWrites::ALL,
                     })],
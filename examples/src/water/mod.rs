@@ -544,6 +544,7 @@ impl crate::framework::Example for Example {
                             dst_factor: wgpu::BlendFactor::One,
                             operation: wgpu::BlendOperation::Max,
                         },
+                        advanced: None,
                     }),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
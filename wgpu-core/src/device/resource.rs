@@ -2821,6 +2821,7 @@ impl Device {
         let mut total_attributes = 0;
         let mut shader_expects_dual_source_blending = false;
         let mut pipeline_expects_dual_source_blending = false;
+        let mut advanced_blend_state = None;
         for (i, vb_state) in desc.vertex.buffers.iter().enumerate() {
             let mut last_stride = 0;
             for attribute in vb_state.attributes.iter() {
@@ -3006,6 +3007,26 @@ impl Device {
                                 }
                             }
                         }
+
+                        if let Some(advanced) = blend_mode.advanced {
+                            self.require_features(wgt::Features::BLEND_OPERATION_ADVANCED)?;
+                            // All targets share a single set of advanced blend parameters.
+                            // Whether the operations may differ per target depends on the
+                            // device, and is checked by the backend.
+                            let shared = (
+                                advanced.src_premultiplied,
+                                advanced.dst_premultiplied,
+                                advanced.overlap,
+                            );
+                            match advanced_blend_state {
+                                None => advanced_blend_state = Some(shared),
+                                Some(first) if first != shared => {
+                                    return Err(pipeline::CreateRenderPipelineError
+                                        ::MismatchedAdvancedBlendState { target: i as u32 });
+                                }
+                                Some(_) => {}
+                            }
+                        }
                     }
 
                     break 'error None;
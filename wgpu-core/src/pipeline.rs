@@ -541,6 +541,8 @@ pub enum CreateRenderPipelineError {
         factor: wgt::BlendFactor,
         target: u32,
     },
+    #[error("Advanced blend state for render target {target} differs from the one used by previous targets. All targets using advanced blending must use the same premultiplication and overlap parameters.")]
+    MismatchedAdvancedBlendState { target: u32 },
     #[error("Pipeline expects the shader entry point to make use of dual-source blending.")]
     PipelineExpectsShaderToUseDualSourceBlending,
     #[error("Shader entry point expects the pipeline to make use of dual-source blending.")]
@@ -58,7 +58,10 @@ impl crate::CopyExtent {
     pub fn map_extent_to_copy_size(extent: &wgt::Extent3d, dim: wgt::TextureDimension) -> Self {
         Self {
             width: extent.width,
-            height: extent.height,
+            height: match dim {
+                wgt::TextureDimension::D1 => 1,
+                wgt::TextureDimension::D2 | wgt::TextureDimension::D3 => extent.height,
+            },
             depth: match dim {
                 wgt::TextureDimension::D1 | wgt::TextureDimension::D2 => 1,
                 wgt::TextureDimension::D3 => extent.depth_or_array_layers,
@@ -66,6 +69,11 @@ impl crate::CopyExtent {
         }
     }
 
+    /// Returns `true` if the extent covers no texels.
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0 || self.depth == 0
+    }
+
     pub fn min(&self, other: &Self) -> Self {
         Self {
             width: self.width.min(other.width),
@@ -1263,6 +1263,11 @@ pub trait CommandEncoder: WasmNotSendSync + fmt::Debug {
     );
     unsafe fn end_render_pass(&mut self);
 
+    /// Sets the render pipeline.
+    ///
+    /// If the layout of `pipeline` differs from the one the bind groups were
+    /// set with, the groups from the first one with an incompatible layout
+    /// on may be unbound, and must be set again before use.
     unsafe fn set_render_pipeline(&mut self, pipeline: &<Self::A as Api>::RenderPipeline);
 
     unsafe fn set_index_buffer<'a>(
@@ -1333,6 +1338,11 @@ pub trait CommandEncoder: WasmNotSendSync + fmt::Debug {
     );
     unsafe fn end_compute_pass(&mut self);
 
+    /// Sets the compute pipeline.
+    ///
+    /// If the layout of `pipeline` differs from the one the bind groups were
+    /// set with, the groups from the first one with an incompatible layout
+    /// on may be unbound, and must be set again before use.
     unsafe fn set_compute_pipeline(&mut self, pipeline: &<Self::A as Api>::ComputePipeline);
 
     unsafe fn dispatch(&mut self, count: [u32; 3]);
@@ -1386,6 +1396,10 @@ bitflags!(
     pub struct BindGroupLayoutFlags: u32 {
         /// Allows for bind group binding arrays to be shorter than the array in the BGL.
         const PARTIALLY_BOUND = 1 << 0;
+        /// Allows binding arrays of textures, samplers and storage buffers to be
+        /// updated while bind groups using them are bound or in use. Only
+        /// supported on Vulkan, see `vulkan::Device::update_bind_group`.
+        const UPDATE_AFTER_BIND = 1 << 1;
     }
 );
 
@@ -1542,12 +1556,20 @@ bitflags::bitflags! {
         const ACCELERATION_STRUCTURE_SCRATCH = 1 << 11;
         const BOTTOM_LEVEL_ACCELERATION_STRUCTURE_INPUT = 1 << 12;
         const TOP_LEVEL_ACCELERATION_STRUCTURE_INPUT = 1 << 13;
+        /// The predicate of conditional rendering. Only supported by Vulkan.
+        const CONDITIONAL_RENDERING = 1 << 14;
+        /// A buffer captured into by transform feedback. Only supported by Vulkan.
+        const TRANSFORM_FEEDBACK = 1 << 15;
+        /// The counter buffer of transform feedback. Only supported by Vulkan.
+        const TRANSFORM_FEEDBACK_COUNTER = 1 << 16;
         /// The combination of states that a buffer may be in _at the same time_.
         const INCLUSIVE = Self::MAP_READ.bits() | Self::COPY_SRC.bits() |
             Self::INDEX.bits() | Self::VERTEX.bits() | Self::UNIFORM.bits() |
-            Self::STORAGE_READ.bits() | Self::INDIRECT.bits() | Self::BOTTOM_LEVEL_ACCELERATION_STRUCTURE_INPUT.bits() | Self::TOP_LEVEL_ACCELERATION_STRUCTURE_INPUT.bits();
+            Self::STORAGE_READ.bits() | Self::INDIRECT.bits() | Self::BOTTOM_LEVEL_ACCELERATION_STRUCTURE_INPUT.bits() | Self::TOP_LEVEL_ACCELERATION_STRUCTURE_INPUT.bits() |
+            Self::CONDITIONAL_RENDERING.bits();
         /// The combination of states that a buffer must exclusively be in.
-        const EXCLUSIVE = Self::MAP_WRITE.bits() | Self::COPY_DST.bits() | Self::STORAGE_READ_WRITE.bits() | Self::ACCELERATION_STRUCTURE_SCRATCH.bits();
+        const EXCLUSIVE = Self::MAP_WRITE.bits() | Self::COPY_DST.bits() | Self::STORAGE_READ_WRITE.bits() | Self::ACCELERATION_STRUCTURE_SCRATCH.bits() |
+            Self::TRANSFORM_FEEDBACK.bits() | Self::TRANSFORM_FEEDBACK_COUNTER.bits();
         /// The combination of all usages that the are guaranteed to be be ordered by the hardware.
         /// If a usage is ordered, then if the buffer state doesn't change between draw calls, there
         /// are no barriers needed for synchronization.
@@ -111,6 +111,41 @@ pub struct PhysicalDeviceFeatures {
 
     /// Features provided by `VK_EXT_subgroup_size_control`, promoted to Vulkan 1.3.
     subgroup_size_control: Option<vk::PhysicalDeviceSubgroupSizeControlFeatures<'static>>,
+
+    /// Features provided by `VK_EXT_blend_operation_advanced`.
+    blend_operation_advanced: Option<vk::PhysicalDeviceBlendOperationAdvancedFeaturesEXT<'static>>,
+
+    /// Features provided by `VK_EXT_conditional_rendering`.
+    conditional_rendering: Option<vk::PhysicalDeviceConditionalRenderingFeaturesEXT<'static>>,
+
+    /// Features provided by `VK_EXT_transform_feedback`.
+    transform_feedback: Option<vk::PhysicalDeviceTransformFeedbackFeaturesEXT<'static>>,
+
+    /// Features provided by `VK_EXT_extended_dynamic_state`, promoted to
+    /// Vulkan 1.3 without a feature bit.
+    extended_dynamic_state: Option<vk::PhysicalDeviceExtendedDynamicStateFeaturesEXT<'static>>,
+
+    /// Features provided by `VK_EXT_extended_dynamic_state3`.
+    extended_dynamic_state3: Option<vk::PhysicalDeviceExtendedDynamicState3FeaturesEXT<'static>>,
+
+    /// Features provided by `VK_EXT_mesh_shader`.
+    mesh_shader: Option<vk::PhysicalDeviceMeshShaderFeaturesEXT<'static>>,
+
+    /// Features provided by `VK_KHR_maintenance5`.
+    maintenance5: Option<vk::PhysicalDeviceMaintenance5FeaturesKHR<'static>>,
+
+    /// Features provided by `VK_KHR_synchronization2`, promoted to Vulkan 1.3.
+    synchronization2: Option<vk::PhysicalDeviceSynchronization2FeaturesKHR<'static>>,
+
+    /// Features provided by `VK_KHR_fragment_shading_rate`.
+    fragment_shading_rate: Option<vk::PhysicalDeviceFragmentShadingRateFeaturesKHR<'static>>,
+
+    /// Features provided by `VK_EXT_color_write_enable`.
+    color_write_enable: Option<vk::PhysicalDeviceColorWriteEnableFeaturesEXT<'static>>,
+
+    /// Features provided by `VK_EXT_vertex_input_dynamic_state`.
+    vertex_input_dynamic_state:
+        Option<vk::PhysicalDeviceVertexInputDynamicStateFeaturesEXT<'static>>,
 }
 
 impl PhysicalDeviceFeatures {
@@ -135,6 +170,39 @@ impl PhysicalDeviceFeatures {
         if let Some(ref mut feature) = self.robustness2 {
             info = info.push_next(feature);
         }
+        if let Some(ref mut feature) = self.blend_operation_advanced {
+            info = info.push_next(feature);
+        }
+        if let Some(ref mut feature) = self.conditional_rendering {
+            info = info.push_next(feature);
+        }
+        if let Some(ref mut feature) = self.transform_feedback {
+            info = info.push_next(feature);
+        }
+        if let Some(ref mut feature) = self.extended_dynamic_state {
+            info = info.push_next(feature);
+        }
+        if let Some(ref mut feature) = self.extended_dynamic_state3 {
+            info = info.push_next(feature);
+        }
+        if let Some(ref mut feature) = self.mesh_shader {
+            info = info.push_next(feature);
+        }
+        if let Some(ref mut feature) = self.maintenance5 {
+            info = info.push_next(feature);
+        }
+        if let Some(ref mut feature) = self.synchronization2 {
+            info = info.push_next(feature);
+        }
+        if let Some(ref mut feature) = self.fragment_shading_rate {
+            info = info.push_next(feature);
+        }
+        if let Some(ref mut feature) = self.color_write_enable {
+            info = info.push_next(feature);
+        }
+        if let Some(ref mut feature) = self.vertex_input_dynamic_state {
+            info = info.push_next(feature);
+        }
         if let Some(ref mut feature) = self.astc_hdr {
             info = info.push_next(feature);
         }
@@ -239,9 +307,11 @@ impl PhysicalDeviceFeatures {
                 .fill_mode_non_solid(requested_features.intersects(
                     wgt::Features::POLYGON_MODE_LINE | wgt::Features::POLYGON_MODE_POINT,
                 ))
-                //.depth_bounds(requested_features.contains(wgt::Features::DEPTH_BOUNDS))
+                .depth_bounds(private_caps.depth_bounds)
                 //.alpha_to_one(requested_features.contains(wgt::Features::ALPHA_TO_ONE))
-                //.multi_viewport(requested_features.contains(wgt::Features::MULTI_VIEWPORTS))
+                .multi_viewport(private_caps.max_viewports > 1)
+                .wide_lines(private_caps.line_width_range.is_some())
+                .depth_bias_clamp(private_caps.depth_bias_clamp)
                 .sampler_anisotropy(
                     downlevel_flags.contains(wgt::DownlevelFlags::ANISOTROPIC_FILTERING),
                 )
@@ -289,7 +359,10 @@ impl PhysicalDeviceFeatures {
                 .shader_int16(requested_features.contains(wgt::Features::SHADER_I16))
                 //.shader_resource_residency(requested_features.contains(wgt::Features::SHADER_RESOURCE_RESIDENCY))
                 .geometry_shader(requested_features.contains(wgt::Features::SHADER_PRIMITIVE_INDEX))
-                .depth_clamp(requested_features.contains(wgt::Features::DEPTH_CLIP_CONTROL))
+                .depth_clamp(
+                    requested_features.contains(wgt::Features::DEPTH_CLIP_CONTROL)
+                        || private_caps.dynamic_depth_clamp,
+                )
                 .dual_src_blend(requested_features.contains(wgt::Features::DUAL_SOURCE_BLENDING)),
             descriptor_indexing: if requested_features.intersects(indexing_features()) {
                 Some(
@@ -306,7 +379,16 @@ impl PhysicalDeviceFeatures {
                         .shader_storage_buffer_array_non_uniform_indexing(
                             needs_storage_buffer_non_uniform,
                         )
-                        .descriptor_binding_partially_bound(needs_partially_bound),
+                        .descriptor_binding_partially_bound(needs_partially_bound)
+                        .descriptor_binding_sampled_image_update_after_bind(
+                            private_caps.update_after_bind,
+                        )
+                        .descriptor_binding_storage_image_update_after_bind(
+                            private_caps.update_after_bind,
+                        )
+                        .descriptor_binding_storage_buffer_update_after_bind(
+                            private_caps.update_after_bind,
+                        ),
                 )
             } else {
                 None
@@ -451,6 +533,91 @@ impl PhysicalDeviceFeatures {
             } else {
                 None
             },
+            blend_operation_advanced: if enabled_extensions
+                .contains(&ext::blend_operation_advanced::NAME)
+            {
+                Some(
+                    vk::PhysicalDeviceBlendOperationAdvancedFeaturesEXT::default()
+                        .advanced_blend_coherent_operations(
+                            requested_features.contains(wgt::Features::BLEND_OPERATION_ADVANCED),
+                        ),
+                )
+            } else {
+                None
+            },
+            conditional_rendering: if enabled_extensions.contains(&ext::conditional_rendering::NAME)
+            {
+                Some(
+                    vk::PhysicalDeviceConditionalRenderingFeaturesEXT::default()
+                        .conditional_rendering(true),
+                )
+            } else {
+                None
+            },
+            transform_feedback: if enabled_extensions.contains(&ext::transform_feedback::NAME) {
+                Some(
+                    vk::PhysicalDeviceTransformFeedbackFeaturesEXT::default()
+                        .transform_feedback(true),
+                )
+            } else {
+                None
+            },
+            extended_dynamic_state: if enabled_extensions
+                .contains(&ext::extended_dynamic_state::NAME)
+            {
+                Some(
+                    vk::PhysicalDeviceExtendedDynamicStateFeaturesEXT::default()
+                        .extended_dynamic_state(true),
+                )
+            } else {
+                None
+            },
+            extended_dynamic_state3: if private_caps.dynamic_depth_clamp {
+                Some(
+                    vk::PhysicalDeviceExtendedDynamicState3FeaturesEXT::default()
+                        .extended_dynamic_state3_depth_clamp_enable(true),
+                )
+            } else {
+                None
+            },
+            mesh_shader: if private_caps.mesh_shader {
+                Some(vk::PhysicalDeviceMeshShaderFeaturesEXT::default().mesh_shader(true))
+            } else {
+                None
+            },
+            maintenance5: if private_caps.bind_index_buffer2 {
+                Some(vk::PhysicalDeviceMaintenance5FeaturesKHR::default().maintenance5(true))
+            } else {
+                None
+            },
+            fragment_shading_rate: private_caps.fragment_shading_rate.map(|caps| {
+                vk::PhysicalDeviceFragmentShadingRateFeaturesKHR::default()
+                    .pipeline_fragment_shading_rate(true)
+                    .attachment_fragment_shading_rate(caps.attachment_texel_sizes.is_some())
+            }),
+            synchronization2: if private_caps.synchronization2 {
+                Some(
+                    vk::PhysicalDeviceSynchronization2FeaturesKHR::default().synchronization2(true),
+                )
+            } else {
+                None
+            },
+            color_write_enable: if private_caps.color_write_enable {
+                Some(
+                    vk::PhysicalDeviceColorWriteEnableFeaturesEXT::default()
+                        .color_write_enable(true),
+                )
+            } else {
+                None
+            },
+            vertex_input_dynamic_state: if private_caps.vertex_input_dynamic_state {
+                Some(
+                    vk::PhysicalDeviceVertexInputDynamicStateFeaturesEXT::default()
+                        .vertex_input_dynamic_state(true),
+                )
+            } else {
+                None
+            },
         }
     }
 
@@ -646,6 +813,15 @@ impl PhysicalDeviceFeatures {
         features.set(F::DEPTH_CLIP_CONTROL, self.core.depth_clamp != 0);
         features.set(F::DUAL_SOURCE_BLENDING, self.core.dual_src_blend != 0);
 
+        // We only expose coherent advanced blending, so that blending behaves like
+        // the regular blend operations without requiring blend barriers.
+        if let Some(ref blend_operation_advanced) = self.blend_operation_advanced {
+            features.set(
+                F::BLEND_OPERATION_ADVANCED,
+                blend_operation_advanced.advanced_blend_coherent_operations != 0,
+            );
+        }
+
         if let Some(ref multiview) = self.multiview {
             features.set(F::MULTIVIEW, multiview.multiview != 0);
         }
@@ -837,6 +1013,23 @@ pub struct PhysicalDeviceProperties {
     /// `VK_EXT_subgroup_size_control` extension, promoted to Vulkan 1.3.
     subgroup_size_control: Option<vk::PhysicalDeviceSubgroupSizeControlProperties<'static>>,
 
+    /// Additional `vk::PhysicalDevice` properties from the
+    /// `VK_EXT_blend_operation_advanced` extension.
+    blend_operation_advanced:
+        Option<vk::PhysicalDeviceBlendOperationAdvancedPropertiesEXT<'static>>,
+
+    /// Additional `vk::PhysicalDevice` properties from the
+    /// `VK_EXT_sample_locations` extension.
+    sample_locations: Option<vk::PhysicalDeviceSampleLocationsPropertiesEXT<'static>>,
+
+    /// Additional `vk::PhysicalDevice` properties from the
+    /// `VK_KHR_depth_stencil_resolve` extension, promoted to Vulkan 1.2.
+    depth_stencil_resolve: Option<vk::PhysicalDeviceDepthStencilResolveProperties<'static>>,
+
+    /// Additional `vk::PhysicalDevice` properties from the
+    /// `VK_KHR_fragment_shading_rate` extension.
+    fragment_shading_rate: Option<vk::PhysicalDeviceFragmentShadingRatePropertiesKHR<'static>>,
+
     /// The device API version.
     ///
     /// Which is the version of Vulkan supported for device-level functionality.
@@ -964,6 +1157,96 @@ impl PhysicalDeviceProperties {
             extensions.push(ext::robustness2::NAME);
         }
 
+        // Optional `VK_EXT_conditional_rendering`
+        if self.supports_extension(ext::conditional_rendering::NAME) {
+            extensions.push(ext::conditional_rendering::NAME);
+        }
+
+        // Optional `VK_EXT_transform_feedback`
+        if self.supports_extension(ext::transform_feedback::NAME) {
+            extensions.push(ext::transform_feedback::NAME);
+        }
+
+        // Optional `VK_KHR_push_descriptor`
+        if self.supports_extension(khr::push_descriptor::NAME) {
+            extensions.push(khr::push_descriptor::NAME);
+        }
+
+        // Optional `VK_EXT_load_store_op_none`
+        if self.supports_extension(ext::load_store_op_none::NAME) {
+            extensions.push(ext::load_store_op_none::NAME);
+        }
+
+        // Optional `VK_EXT_extended_dynamic_state`, promoted to Vulkan 1.3.
+        if self.device_api_version >= vk::API_VERSION_1_1
+            && self.device_api_version < vk::API_VERSION_1_3
+            && self.supports_extension(ext::extended_dynamic_state::NAME)
+        {
+            extensions.push(ext::extended_dynamic_state::NAME);
+        }
+
+        // Optional `VK_EXT_extended_dynamic_state3`
+        if self.supports_extension(ext::extended_dynamic_state3::NAME) {
+            extensions.push(ext::extended_dynamic_state3::NAME);
+        }
+
+        // Optional `VK_EXT_mesh_shader`, which requires `VK_KHR_spirv_1_4`,
+        // promoted to Vulkan 1.2.
+        if self.device_api_version >= vk::API_VERSION_1_2
+            && self.supports_extension(ext::mesh_shader::NAME)
+        {
+            extensions.push(ext::mesh_shader::NAME);
+        }
+
+        // Optional `VK_KHR_maintenance5`, which requires
+        // `VK_KHR_dynamic_rendering`, promoted to Vulkan 1.3.
+        if self.device_api_version >= vk::API_VERSION_1_3
+            && self.supports_extension(khr::maintenance5::NAME)
+        {
+            extensions.push(khr::maintenance5::NAME);
+        }
+
+        // Optional `VK_KHR_synchronization2`, promoted to Vulkan 1.3.
+        if self.device_api_version >= vk::API_VERSION_1_1
+            && self.device_api_version < vk::API_VERSION_1_3
+            && self.supports_extension(khr::synchronization2::NAME)
+        {
+            extensions.push(khr::synchronization2::NAME);
+        }
+
+        // Optional `VK_KHR_fragment_shading_rate`, which requires
+        // `VK_KHR_create_renderpass2`, promoted to Vulkan 1.2.
+        if self.device_api_version >= vk::API_VERSION_1_2
+            && self.supports_extension(khr::fragment_shading_rate::NAME)
+        {
+            extensions.push(khr::fragment_shading_rate::NAME);
+        }
+
+        // Optional `VK_EXT_color_write_enable`
+        if self.supports_extension(ext::color_write_enable::NAME) {
+            extensions.push(ext::color_write_enable::NAME);
+        }
+
+        // Optional `VK_EXT_vertex_input_dynamic_state`
+        if self.supports_extension(ext::vertex_input_dynamic_state::NAME) {
+            extensions.push(ext::vertex_input_dynamic_state::NAME);
+        }
+
+        // Optional `VK_EXT_sample_locations`
+        if self.supports_extension(ext::sample_locations::NAME) {
+            extensions.push(ext::sample_locations::NAME);
+        }
+
+        // Optional `VK_EXT_depth_range_unrestricted`
+        if self.supports_extension(ext::depth_range_unrestricted::NAME) {
+            extensions.push(ext::depth_range_unrestricted::NAME);
+        }
+
+        // Require `VK_EXT_blend_operation_advanced` if the associated feature was requested
+        if requested_features.contains(wgt::Features::BLEND_OPERATION_ADVANCED) {
+            extensions.push(ext::blend_operation_advanced::NAME);
+        }
+
         // Require `VK_KHR_draw_indirect_count` if the associated feature was requested
         // Even though Vulkan 1.2 has promoted the extension to core, we must require the extension to avoid
         // large amounts of spaghetti involved with using PhysicalDeviceVulkan12Features.
@@ -1170,6 +1453,37 @@ impl super::InstanceShared {
                     properties2 = properties2.push_next(next);
                 }
 
+                if capabilities.supports_extension(ext::blend_operation_advanced::NAME) {
+                    let next = capabilities
+                        .blend_operation_advanced
+                        .insert(vk::PhysicalDeviceBlendOperationAdvancedPropertiesEXT::default());
+                    properties2 = properties2.push_next(next);
+                }
+
+                if capabilities.supports_extension(ext::sample_locations::NAME) {
+                    let next = capabilities
+                        .sample_locations
+                        .insert(vk::PhysicalDeviceSampleLocationsPropertiesEXT::default());
+                    properties2 = properties2.push_next(next);
+                }
+
+                // Only used with `vkCreateRenderPass2`, which is also from 1.2.
+                if capabilities.device_api_version >= vk::API_VERSION_1_2 {
+                    let next = capabilities
+                        .depth_stencil_resolve
+                        .insert(vk::PhysicalDeviceDepthStencilResolveProperties::default());
+                    properties2 = properties2.push_next(next);
+                }
+
+                if capabilities.device_api_version >= vk::API_VERSION_1_2
+                    && capabilities.supports_extension(khr::fragment_shading_rate::NAME)
+                {
+                    let next = capabilities
+                        .fragment_shading_rate
+                        .insert(vk::PhysicalDeviceFragmentShadingRatePropertiesKHR::default());
+                    properties2 = properties2.push_next(next);
+                }
+
                 unsafe {
                     get_device_properties.get_physical_device_properties2(phd, &mut properties2)
                 };
@@ -1260,6 +1574,74 @@ impl super::InstanceShared {
                     .insert(vk::PhysicalDeviceRobustness2FeaturesEXT::default());
                 features2 = features2.push_next(next);
             }
+            if capabilities.supports_extension(ext::blend_operation_advanced::NAME) {
+                let next = features
+                    .blend_operation_advanced
+                    .insert(vk::PhysicalDeviceBlendOperationAdvancedFeaturesEXT::default());
+                features2 = features2.push_next(next);
+            }
+            if capabilities.device_api_version >= vk::API_VERSION_1_3
+                && capabilities.supports_extension(khr::maintenance5::NAME)
+            {
+                let next = features
+                    .maintenance5
+                    .insert(vk::PhysicalDeviceMaintenance5FeaturesKHR::default());
+                features2 = features2.push_next(next);
+            }
+            if capabilities.device_api_version >= vk::API_VERSION_1_2
+                && capabilities.supports_extension(khr::fragment_shading_rate::NAME)
+            {
+                let next = features
+                    .fragment_shading_rate
+                    .insert(vk::PhysicalDeviceFragmentShadingRateFeaturesKHR::default());
+                features2 = features2.push_next(next);
+            }
+            if capabilities.supports_extension(ext::color_write_enable::NAME) {
+                let next = features
+                    .color_write_enable
+                    .insert(vk::PhysicalDeviceColorWriteEnableFeaturesEXT::default());
+                features2 = features2.push_next(next);
+            }
+            if capabilities.supports_extension(ext::vertex_input_dynamic_state::NAME) {
+                let next = features
+                    .vertex_input_dynamic_state
+                    .insert(vk::PhysicalDeviceVertexInputDynamicStateFeaturesEXT::default());
+                features2 = features2.push_next(next);
+            }
+            // Same conditions as enabling the extension, or core 1.3.
+            if capabilities.device_api_version >= vk::API_VERSION_1_3
+                || (capabilities.device_api_version >= vk::API_VERSION_1_1
+                    && capabilities.supports_extension(khr::synchronization2::NAME))
+            {
+                let next = features
+                    .synchronization2
+                    .insert(vk::PhysicalDeviceSynchronization2FeaturesKHR::default());
+                features2 = features2.push_next(next);
+            }
+            if capabilities.device_api_version >= vk::API_VERSION_1_2
+                && capabilities.supports_extension(ext::mesh_shader::NAME)
+            {
+                let next = features
+                    .mesh_shader
+                    .insert(vk::PhysicalDeviceMeshShaderFeaturesEXT::default());
+                features2 = features2.push_next(next);
+            }
+            // Same conditions as enabling the extension, which is core in 1.3.
+            if capabilities.device_api_version >= vk::API_VERSION_1_1
+                && capabilities.device_api_version < vk::API_VERSION_1_3
+                && capabilities.supports_extension(ext::extended_dynamic_state::NAME)
+            {
+                let next = features
+                    .extended_dynamic_state
+                    .insert(vk::PhysicalDeviceExtendedDynamicStateFeaturesEXT::default());
+                features2 = features2.push_next(next);
+            }
+            if capabilities.supports_extension(ext::extended_dynamic_state3::NAME) {
+                let next = features
+                    .extended_dynamic_state3
+                    .insert(vk::PhysicalDeviceExtendedDynamicState3FeaturesEXT::default());
+                features2 = features2.push_next(next);
+            }
             if capabilities.supports_extension(ext::texture_compression_astc_hdr::NAME) {
                 let next = features
                     .astc_hdr
@@ -1494,6 +1876,102 @@ impl super::Instance {
                 }),
             image_format_list: phd_capabilities.device_api_version >= vk::API_VERSION_1_2
                 || phd_capabilities.supports_extension(khr::image_format_list::NAME),
+            max_per_set_descriptors: phd_capabilities
+                .maintenance_3
+                .map(|maintenance_3| maintenance_3.max_per_set_descriptors),
+            blend_operation_advanced: phd_capabilities.blend_operation_advanced.map(|props| {
+                super::AdvancedBlendCapabilities {
+                    max_color_attachments: props.advanced_blend_max_color_attachments,
+                    independent_blend: props.advanced_blend_independent_blend == vk::TRUE,
+                    non_premultiplied_src_color: props.advanced_blend_non_premultiplied_src_color
+                        == vk::TRUE,
+                    non_premultiplied_dst_color: props.advanced_blend_non_premultiplied_dst_color
+                        == vk::TRUE,
+                    correlated_overlap: props.advanced_blend_correlated_overlap == vk::TRUE,
+                }
+            }),
+            max_viewports: if phd_features.core.multi_viewport != 0 {
+                phd_capabilities
+                    .properties
+                    .limits
+                    .max_viewports
+                    .min(super::MAX_VIEWPORTS as u32)
+            } else {
+                1
+            },
+            bind_vertex_buffers2: phd_capabilities.device_api_version >= vk::API_VERSION_1_3,
+            line_width_range: if phd_features.core.wide_lines != 0 {
+                Some(phd_capabilities.properties.limits.line_width_range)
+            } else {
+                None
+            },
+            depth_bias_clamp: phd_features.core.depth_bias_clamp != 0,
+            update_after_bind: phd_features.descriptor_indexing.map_or(false, |di| {
+                di.descriptor_binding_sampled_image_update_after_bind != 0
+                    && di.descriptor_binding_storage_image_update_after_bind != 0
+                    && di.descriptor_binding_storage_buffer_update_after_bind != 0
+            }),
+            store_op_none: phd_capabilities.device_api_version >= vk::API_VERSION_1_3
+                || phd_capabilities.supports_extension(ext::load_store_op_none::NAME),
+            depth_range_unrestricted: phd_capabilities
+                .supports_extension(ext::depth_range_unrestricted::NAME),
+            dynamic_depth_clamp: phd_features.core.depth_clamp != 0
+                && phd_features.extended_dynamic_state3.map_or(false, |eds3| {
+                    eds3.extended_dynamic_state3_depth_clamp_enable != 0
+                }),
+            depth_bounds: phd_features.core.depth_bounds != 0,
+            mesh_shader: phd_features
+                .mesh_shader
+                .map_or(false, |mesh| mesh.mesh_shader != 0),
+            bind_index_buffer2: phd_features
+                .maintenance5
+                .map_or(false, |maintenance5| maintenance5.maintenance5 != 0),
+            // Locations are only changed within render passes, and pipelines
+            // fall back to the standard ones, so both are needed.
+            sample_location_sample_counts: match phd_capabilities.sample_locations {
+                Some(props)
+                    if props.variable_sample_locations != 0
+                        && phd_capabilities.properties.limits.standard_sample_locations != 0 =>
+                {
+                    props.sample_location_sample_counts
+                }
+                _ => vk::SampleCountFlags::empty(),
+            },
+            depth_stencil_resolve: phd_capabilities.depth_stencil_resolve.map(|props| {
+                super::DepthStencilResolveCapabilities {
+                    depth_modes: props.supported_depth_resolve_modes,
+                    stencil_modes: props.supported_stencil_resolve_modes,
+                    independent_resolve: props.independent_resolve == vk::TRUE,
+                }
+            }),
+            dispatch_base: phd_capabilities.device_api_version >= vk::API_VERSION_1_1,
+            synchronization2: phd_features
+                .synchronization2
+                .map_or(false, |sync2| sync2.synchronization2 != 0),
+            fragment_shading_rate: match (
+                phd_features.fragment_shading_rate,
+                phd_capabilities.fragment_shading_rate,
+            ) {
+                (Some(features), Some(props)) if features.pipeline_fragment_shading_rate != 0 => {
+                    let min = props.min_fragment_shading_rate_attachment_texel_size;
+                    let max = props.max_fragment_shading_rate_attachment_texel_size;
+                    Some(super::FragmentShadingRateCapabilities {
+                        attachment_texel_sizes: (features.attachment_fragment_shading_rate != 0)
+                            .then_some([[min.width, min.height], [max.width, max.height]]),
+                    })
+                }
+                _ => None,
+            },
+            extended_dynamic_state: phd_capabilities.device_api_version >= vk::API_VERSION_1_3
+                || phd_features
+                    .extended_dynamic_state
+                    .map_or(false, |eds| eds.extended_dynamic_state != 0),
+            color_write_enable: phd_features
+                .color_write_enable
+                .map_or(false, |cwe| cwe.color_write_enable != 0),
+            vertex_input_dynamic_state: phd_features
+                .vertex_input_dynamic_state
+                .map_or(false, |vids| vids.vertex_input_dynamic_state != 0),
         };
         let capabilities = crate::Capabilities {
             limits: phd_capabilities.to_wgpu_limits(),
@@ -1651,6 +2129,120 @@ impl super::Adapter {
         } else {
             None
         };
+        let conditional_rendering_fn =
+            if enabled_extensions.contains(&ext::conditional_rendering::NAME) {
+                Some(ext::conditional_rendering::Device::new(
+                    &self.instance.raw,
+                    &raw_device,
+                ))
+            } else {
+                None
+            };
+        let transform_feedback_fn = if enabled_extensions.contains(&ext::transform_feedback::NAME) {
+            Some(ext::transform_feedback::Device::new(
+                &self.instance.raw,
+                &raw_device,
+            ))
+        } else {
+            None
+        };
+        let extended_dynamic_state_fn =
+            if enabled_extensions.contains(&ext::extended_dynamic_state::NAME) {
+                Some(super::ExtensionFn::Extension(
+                    ext::extended_dynamic_state::Device::new(&self.instance.raw, &raw_device),
+                ))
+            } else if self.phd_capabilities.device_api_version >= vk::API_VERSION_1_3 {
+                Some(super::ExtensionFn::Promoted)
+            } else {
+                None
+            };
+        let extended_dynamic_state3_fn =
+            if enabled_extensions.contains(&ext::extended_dynamic_state3::NAME) {
+                Some(ext::extended_dynamic_state3::Device::new(
+                    &self.instance.raw,
+                    &raw_device,
+                ))
+            } else {
+                None
+            };
+        let synchronization2_fn = if enabled_extensions.contains(&khr::synchronization2::NAME) {
+            Some(super::ExtensionFn::Extension(
+                khr::synchronization2::Device::new(&self.instance.raw, &raw_device),
+            ))
+        } else if self.phd_capabilities.device_api_version >= vk::API_VERSION_1_3
+            && self.private_caps.synchronization2
+        {
+            Some(super::ExtensionFn::Promoted)
+        } else {
+            None
+        };
+        let maintenance5_fn = if enabled_extensions.contains(&khr::maintenance5::NAME) {
+            Some(khr::maintenance5::Device::new(
+                &self.instance.raw,
+                &raw_device,
+            ))
+        } else {
+            None
+        };
+        let mesh_shader_fn = if enabled_extensions.contains(&ext::mesh_shader::NAME) {
+            Some(ext::mesh_shader::Device::new(
+                &self.instance.raw,
+                &raw_device,
+            ))
+        } else {
+            None
+        };
+        let fragment_shading_rate_fn =
+            if enabled_extensions.contains(&khr::fragment_shading_rate::NAME) {
+                Some(khr::fragment_shading_rate::Device::new(
+                    &self.instance.raw,
+                    &raw_device,
+                ))
+            } else {
+                None
+            };
+        let color_write_enable_fn = if enabled_extensions.contains(&ext::color_write_enable::NAME) {
+            Some(ext::color_write_enable::Device::new(
+                &self.instance.raw,
+                &raw_device,
+            ))
+        } else {
+            None
+        };
+        let vertex_input_dynamic_state_fn =
+            if enabled_extensions.contains(&ext::vertex_input_dynamic_state::NAME) {
+                Some(ext::vertex_input_dynamic_state::Device::new(
+                    &self.instance.raw,
+                    &raw_device,
+                ))
+            } else {
+                None
+            };
+        let sample_locations_fn = if enabled_extensions.contains(&ext::sample_locations::NAME) {
+            Some(ext::sample_locations::Device::new(
+                &self.instance.raw,
+                &raw_device,
+            ))
+        } else {
+            None
+        };
+        let push_descriptor_fn = if enabled_extensions.contains(&khr::push_descriptor::NAME) {
+            Some(khr::push_descriptor::Device::new(
+                &self.instance.raw,
+                &raw_device,
+            ))
+        } else {
+            None
+        };
+        let maintenance3_fn = if enabled_extensions.contains(&khr::maintenance3::NAME) {
+            Some(super::ExtensionFn::Extension(
+                khr::maintenance3::Device::new(&self.instance.raw, &raw_device),
+            ))
+        } else if self.phd_capabilities.device_api_version >= vk::API_VERSION_1_1 {
+            Some(super::ExtensionFn::Promoted)
+        } else {
+            None
+        };
         let ray_tracing_fns = if enabled_extensions.contains(&khr::acceleration_structure::NAME)
             && enabled_extensions.contains(&khr::buffer_device_address::NAME)
         {
@@ -1812,6 +2404,14 @@ impl super::Adapter {
             0, 0, 0, 0,
         ];
 
+        let timestamp_valid_bits = unsafe {
+            self.instance
+                .raw
+                .get_physical_device_queue_family_properties(self.raw)
+        }
+        .get(family_index as usize)
+        .map_or(64, |family| family.timestamp_valid_bits);
+
         let shared = Arc::new(super::DeviceShared {
             raw: raw_device,
             family_index,
@@ -1825,11 +2425,26 @@ impl super::Adapter {
                 debug_utils: debug_utils_fn,
                 draw_indirect_count: indirect_count_fn,
                 timeline_semaphore: timeline_semaphore_fn,
+                maintenance3: maintenance3_fn,
+                conditional_rendering: conditional_rendering_fn,
+                push_descriptor: push_descriptor_fn,
+                transform_feedback: transform_feedback_fn,
+                extended_dynamic_state: extended_dynamic_state_fn,
+                extended_dynamic_state3: extended_dynamic_state3_fn,
+                mesh_shader: mesh_shader_fn,
+                maintenance5: maintenance5_fn,
+                synchronization2: synchronization2_fn,
+                sample_locations: sample_locations_fn,
+                fragment_shading_rate: fragment_shading_rate_fn,
+                color_write_enable: color_write_enable_fn,
+                vertex_input_dynamic_state: vertex_input_dynamic_state_fn,
                 ray_tracing: ray_tracing_fns,
             },
             pipeline_cache_validation_key,
             vendor_id: self.phd_capabilities.properties.vendor_id,
             timestamp_period: self.phd_capabilities.properties.limits.timestamp_period,
+            timestamp_valid_bits,
+            limits: self.phd_capabilities.properties.limits,
             private_caps: self.private_caps.clone(),
             features,
             workarounds: self.workarounds,
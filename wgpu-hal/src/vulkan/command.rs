@@ -1,7 +1,7 @@
 use super::conv;
 
 use arrayvec::ArrayVec;
-use ash::vk;
+use ash::{ext, khr, vk};
 
 use std::{
     mem::{self, size_of},
@@ -10,9 +10,13 @@ use std::{
 };
 
 const ALLOCATION_GRANULARITY: u32 = 16;
+/// The largest `data` accepted by `vkCmdUpdateBuffer`, in bytes.
+const MAX_UPDATE_BUFFER_SIZE: usize = 65536;
 const DST_IMAGE_LAYOUT: vk::ImageLayout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
 
 impl super::Texture {
+    /// Maps `regions`, skipping those that copy no texels, and splitting
+    /// copies of all planes of multi-planar formats into one per plane.
     fn map_buffer_copies<T>(&self, regions: T) -> impl Iterator<Item = vk::BufferImageCopy>
     where
         T: Iterator<Item = crate::BufferTextureCopy>,
@@ -20,61 +24,91 @@ impl super::Texture {
         let (block_width, block_height) = self.format.block_dimensions();
         let format = self.format;
         let copy_size = self.copy_size;
-        regions.map(move |r| {
-            let extent = r.texture_base.max_copy_size(&copy_size).min(&r.size);
-            let (image_subresource, image_offset) = conv::map_subresource_layers(&r.texture_base);
-            vk::BufferImageCopy {
-                buffer_offset: r.buffer_layout.offset,
-                buffer_row_length: r.buffer_layout.bytes_per_row.map_or(0, |bpr| {
-                    let block_size = format
-                        .block_copy_size(Some(r.texture_base.aspect.map()))
-                        .unwrap();
-                    block_width * (bpr / block_size)
-                }),
-                buffer_image_height: r
-                    .buffer_layout
-                    .rows_per_image
-                    .map_or(0, |rpi| rpi * block_height),
-                image_subresource,
-                image_offset,
-                image_extent: conv::map_copy_extent(&extent),
-            }
-        })
+        regions
+            .flat_map(move |r| split_plane_copies(format, r))
+            .filter_map(move |r| {
+                check_copy_region(format, &copy_size, &r.texture_base, &r.size, true);
+                let extent = r.texture_base.max_copy_size(&copy_size).min(&r.size);
+                if extent.is_empty() {
+                    return None;
+                }
+                let (image_subresource, image_offset) =
+                    conv::map_subresource_layers(&r.texture_base);
+                let block_size = format.block_copy_size(Some(r.texture_base.aspect.map()));
+                if let (true, Some(block_size)) = (cfg!(debug_assertions), block_size) {
+                    // Depth/stencil copies need 4 byte aligned offsets, all others
+                    // need offsets aligned to the texel block size.
+                    let offset_alignment = if format.is_depth_stencil_format() {
+                        4
+                    } else {
+                        block_size
+                    };
+                    if r.buffer_layout.offset % offset_alignment as wgt::BufferAddress != 0 {
+                        super::hal_usage_error(format!(
+                            "buffer offset {} of a copy with texture format {format:?} \
+                             is not a multiple of {offset_alignment}",
+                            r.buffer_layout.offset
+                        ));
+                    }
+                }
+                Some(vk::BufferImageCopy {
+                    buffer_offset: r.buffer_layout.offset,
+                    buffer_row_length: r
+                        .buffer_layout
+                        .bytes_per_row
+                        .map_or(0, |bpr| block_width * (bpr / block_size.unwrap())),
+                    buffer_image_height: r
+                        .buffer_layout
+                        .rows_per_image
+                        .map_or(0, |rpi| rpi * block_height),
+                    image_subresource,
+                    image_offset,
+                    image_extent: conv::map_copy_extent(&extent),
+                })
+            })
     }
 }
 
 impl super::CommandEncoder {
-    fn write_pass_end_timestamp_if_requested(&mut self) {
-        if let Some((query_set, index)) = self.end_of_pass_timer_query.take() {
-            unsafe {
-                self.device.raw.cmd_write_timestamp(
-                    self.active,
-                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
-                    query_set,
-                    index,
-                );
-            }
+    unsafe fn begin_encoding_impl(
+        &mut self,
+        label: crate::Label,
+        inheritance: Option<&super::CommandBufferInheritance>,
+        external: Option<vk::CommandBuffer>,
+    ) -> Result<(), crate::DeviceError> {
+        // Starting over would leak the command buffer being recorded, which
+        // is never ended nor returned to a free list.
+        if cfg!(debug_assertions) && self.active != vk::CommandBuffer::null() {
+            super::hal_usage_error(
+                "begin_encoding called while the previous encoding was not ended or discarded",
+            );
         }
-    }
-}
 
-impl crate::CommandEncoder for super::CommandEncoder {
-    type A = super::Api;
-
-    unsafe fn begin_encoding(&mut self, label: crate::Label) -> Result<(), crate::DeviceError> {
-        if self.free.is_empty() {
+        let (level, free) = match inheritance {
+            Some(_) => (vk::CommandBufferLevel::SECONDARY, &mut self.free_secondary),
+            None => (vk::CommandBufferLevel::PRIMARY, &mut self.free),
+        };
+        if free.is_empty() && external.is_none() {
             let vk_info = vk::CommandBufferAllocateInfo::default()
                 .command_pool(self.raw)
+                .level(level)
                 .command_buffer_count(ALLOCATION_GRANULARITY);
+            // Allocation is all-or-nothing, so there is nothing to recover
+            // on failure.
             let cmd_buf_vec = unsafe {
                 self.device
                     .raw
                     .allocate_command_buffers(&vk_info)
                     .map_err(super::map_host_device_oom_err)?
             };
-            self.free.extend(cmd_buf_vec);
+            free.extend(cmd_buf_vec);
         }
-        let raw = self.free.pop().unwrap();
+        // Vulkan allocates all of the requested buffers or fails, but don't
+        // trust a driver returning none without an error.
+        let raw = match external {
+            Some(raw) => raw,
+            None => free.pop().ok_or(crate::DeviceError::OutOfMemory)?,
+        };
 
         // Set the name unconditionally, since there might be a
         // previous name assigned to this.
@@ -82,330 +116,989 @@ impl crate::CommandEncoder for super::CommandEncoder {
 
         // Reset this in case the last renderpass was never ended.
         self.rpass_debug_marker_active = false;
+        self.reset_bound_pipelines();
+        self.pending_bind_groups.clear();
+        self.signal = None;
+        // Secondary command buffers continue the render pass they inherit.
+        self.active_pass = inheritance.map(|_| vk::PipelineBindPoint::GRAPHICS);
+        self.encoding = self.encoding.begin();
 
-        let vk_info = vk::CommandBufferBeginInfo::default()
+        let vk_inheritance_info;
+        let mut vk_info = vk::CommandBufferBeginInfo::default()
             .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-        unsafe { self.device.raw.begin_command_buffer(raw, &vk_info) }
-            .map_err(super::map_host_device_oom_err)?;
+        if let Some(inheritance) = inheritance {
+            vk_inheritance_info = vk::CommandBufferInheritanceInfo::default()
+                .render_pass(inheritance.render_pass)
+                .subpass(inheritance.subpass)
+                .framebuffer(inheritance.framebuffer);
+            // Bundles are replayed across frames, possibly while a previous
+            // submission executing them is still pending.
+            vk_info = vk_info
+                .flags(
+                    vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE
+                        | vk::CommandBufferUsageFlags::SIMULTANEOUS_USE,
+                )
+                .inheritance_info(&vk_inheritance_info);
+        }
+        if let Err(err) = unsafe { self.device.raw.begin_command_buffer(raw, &vk_info) } {
+            // Hand the buffer back, so that it's recycled with the others when
+            // the pool is reset. External buffers stay with the caller.
+            match (external, inheritance) {
+                (Some(_), _) => {}
+                (None, Some(_)) => self.discarded_secondary.push(raw),
+                (None, None) => self.discarded.push(raw),
+            }
+            return Err(super::map_host_device_oom_err(err));
+        }
         self.active = raw;
+        self.active_is_secondary = inheritance.is_some();
+        self.active_is_external = external.is_some();
+
+        if let Some(inheritance) = inheritance {
+            // Dynamic state isn't inherited from the primary command buffer,
+            // so re-establish what `begin_render_pass` sets up.
+            let vk_viewports = [self.make_pass_viewport(&inheritance.extent)];
+            let render_area = vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: vk::Extent2D {
+                    width: inheritance.extent.width,
+                    height: inheritance.extent.height,
+                },
+            };
+            unsafe {
+                self.device.raw.cmd_set_viewport(raw, 0, &vk_viewports);
+                self.device.raw.cmd_set_scissor(raw, 0, &[render_area]);
+            }
+            self.viewport_count = 1;
+            self.scissor_count = 1;
+            self.bind_point = vk::PipelineBindPoint::GRAPHICS;
+        }
 
         Ok(())
     }
 
-    unsafe fn end_encoding(&mut self) -> Result<super::CommandBuffer, crate::DeviceError> {
-        let raw = self.active;
-        self.active = vk::CommandBuffer::null();
-        unsafe { self.device.raw.end_command_buffer(raw) }.map_err(map_err)?;
-        fn map_err(err: vk::Result) -> crate::DeviceError {
-            // We don't use VK_KHR_video_encode_queue
-            // VK_ERROR_INVALID_VIDEO_STD_PARAMETERS_KHR
-            super::map_host_device_oom_err(err)
+    /// Returns the full viewport of a pass with the given `extent`, flipping Y.
+    fn make_pass_viewport(&self, extent: &wgt::Extent3d) -> vk::Viewport {
+        vk::Viewport {
+            x: 0.0,
+            y: if self.device.private_caps.flip_y_requires_shift {
+                extent.height as f32
+            } else {
+                0.0
+            },
+            width: extent.width as f32,
+            height: -(extent.height as f32),
+            min_depth: 0.0,
+            max_depth: 1.0,
         }
-        Ok(super::CommandBuffer { raw })
     }
 
-    unsafe fn discard_encoding(&mut self) {
-        // Safe use requires this is not called in the "closed" state, so the buffer
-        // shouldn't be null. Assert this to make sure we're not pushing null
-        // buffers to the discard pile.
-        assert_ne!(self.active, vk::CommandBuffer::null());
+    /// Begins encoding a secondary command buffer continuing the render pass
+    /// described by `inheritance`.
+    ///
+    /// The resulting [`super::CommandBuffer`] can't be submitted directly, it
+    /// has to be executed with [`Self::execute_bundles`] inside a render pass
+    /// begun with [`Self::begin_render_pass_with_bundles`]. Only render pass
+    /// commands may be recorded.
+    ///
+    /// # Safety
+    ///
+    /// - The encoder must not be recording.
+    /// - The render pass and framebuffer of `inheritance` must be alive.
+    pub unsafe fn begin_secondary_encoding(
+        &mut self,
+        label: crate::Label,
+        inheritance: &super::CommandBufferInheritance,
+    ) -> Result<(), crate::DeviceError> {
+        unsafe { self.begin_encoding_impl(label, Some(inheritance), None) }
+    }
 
-        self.discarded.push(self.active);
-        self.active = vk::CommandBuffer::null();
+    /// Like [`crate::CommandEncoder::begin_encoding`], but records into `raw`
+    /// instead of a command buffer of the encoder's own pool.
+    ///
+    /// The resulting [`super::CommandBuffer`] still goes through
+    /// [`crate::CommandEncoder::reset_all`] like the others, but `raw` isn't
+    /// recycled: the caller keeps owning it, and has to reset or free it.
+    ///
+    /// # Safety
+    ///
+    /// - The encoder must not be recording.
+    /// - `raw` must be a primary command buffer allocated from `self`'s
+    ///   device, in the Vulkan "initial" state.
+    /// - The pool of `raw` must not be used by another thread while this
+    ///   encoder is recording.
+    /// - `raw` must stay valid until the resulting [`super::CommandBuffer`]
+    ///   is passed to [`crate::CommandEncoder::reset_all`].
+    pub unsafe fn begin_encoding_with(
+        &mut self,
+        raw: vk::CommandBuffer,
+        label: crate::Label,
+    ) -> Result<(), crate::DeviceError> {
+        unsafe { self.begin_encoding_impl(label, None, Some(raw)) }
     }
 
-    unsafe fn reset_all<I>(&mut self, cmd_bufs: I)
-    where
-        I: Iterator<Item = super::CommandBuffer>,
-    {
-        self.temp.clear();
-        self.free
-            .extend(cmd_bufs.into_iter().map(|cmd_buf| cmd_buf.raw));
-        self.free.append(&mut self.discarded);
-        let _ = unsafe {
-            self.device
-                .raw
-                .reset_command_pool(self.raw, vk::CommandPoolResetFlags::default())
-        };
+    /// Begins a render pass whose contents are provided by secondary
+    /// command buffers, see [`Self::execute_bundles`].
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::CommandEncoder::begin_render_pass`]. No commands other
+    /// than [`Self::execute_bundles`] may be recorded until the pass ends.
+    pub unsafe fn begin_render_pass_with_bundles(
+        &mut self,
+        desc: &crate::RenderPassDescriptor<super::QuerySet, super::TextureView>,
+    ) {
+        self.encoding.next_subpass_contents = vk::SubpassContents::SECONDARY_COMMAND_BUFFERS;
+        unsafe { crate::CommandEncoder::begin_render_pass(self, desc) };
     }
 
-    unsafe fn transition_buffers<'a, T>(&mut self, barriers: T)
-    where
-        T: Iterator<Item = crate::BufferBarrier<'a, super::Buffer>>,
-    {
-        //Note: this is done so that we never end up with empty stage flags
-        let mut src_stages = vk::PipelineStageFlags::TOP_OF_PIPE;
-        let mut dst_stages = vk::PipelineStageFlags::BOTTOM_OF_PIPE;
-        let vk_barriers = &mut self.temp.buffer_barriers;
-        vk_barriers.clear();
+    /// Begins a render pass whose attachments are in other layouts before or
+    /// after the pass than those of their usage in it.
+    ///
+    /// `color_layouts` apply to the color attachments of `desc` with the
+    /// same index, and `depth_stencil_layouts` to its depth-stencil
+    /// attachment. The render pass then transitions the attachments, which
+    /// spares the barriers around it, e.g. when a color target is sampled
+    /// right after the pass.
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`crate::CommandEncoder::begin_render_pass`], except that
+    ///   attachments must be in the layouts of their `before` usage.
+    /// - The attachments are left in the layouts of their `after` usage.
+    pub unsafe fn begin_render_pass_with_layouts(
+        &mut self,
+        desc: &crate::RenderPassDescriptor<super::QuerySet, super::TextureView>,
+        color_layouts: &[super::AttachmentLayouts],
+        depth_stencil_layouts: super::AttachmentLayouts,
+    ) {
+        if color_layouts.len() > desc.color_attachments.len() {
+            super::hal_usage_error(format!(
+                "{} color attachment layouts for {} color attachments",
+                color_layouts.len(),
+                desc.color_attachments.len()
+            ));
+        }
+        self.encoding.next_attachment_layouts = Some((
+            color_layouts.iter().copied().collect(),
+            depth_stencil_layouts,
+        ));
+        unsafe { crate::CommandEncoder::begin_render_pass(self, desc) };
+    }
 
-        for bar in barriers {
-            let (src_stage, src_access) = conv::map_buffer_usage_to_barrier(bar.usage.start);
-            src_stages |= src_stage;
-            let (dst_stage, dst_access) = conv::map_buffer_usage_to_barrier(bar.usage.end);
-            dst_stages |= dst_stage;
+    /// Returns `true` if [`Self::begin_render_pass_with_depth_stencil_resolve`]
+    /// is supported.
+    pub fn supports_depth_stencil_resolve(&self) -> bool {
+        self.device.private_caps.depth_stencil_resolve.is_some()
+    }
 
-            vk_barriers.push(
-                vk::BufferMemoryBarrier::default()
-                    .buffer(bar.buffer.raw)
-                    .size(vk::WHOLE_SIZE)
-                    .src_access_mask(src_access)
-                    .dst_access_mask(dst_access),
-            )
+    /// Begins a render pass that resolves its multisampled depth-stencil
+    /// attachment into `resolve_target` at the end of the pass.
+    ///
+    /// `mode` is used for each aspect of the format that supports it, and
+    /// `SAMPLE_ZERO`, which is always supported, otherwise.
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`crate::CommandEncoder::begin_render_pass`].
+    /// - [`Self::supports_depth_stencil_resolve`] must be `true`.
+    /// - `resolve_target` must be single-sampled, with the format and
+    ///   extent of the depth-stencil attachment of `desc`.
+    pub unsafe fn begin_render_pass_with_depth_stencil_resolve(
+        &mut self,
+        desc: &crate::RenderPassDescriptor<super::QuerySet, super::TextureView>,
+        resolve_target: &crate::Attachment<'_, super::TextureView>,
+        mode: vk::ResolveModeFlags,
+    ) {
+        let caps = &self.device.private_caps;
+        let Some(resolve_caps) = caps.depth_stencil_resolve else {
+            super::hal_usage_error("depth-stencil resolve is not supported");
+        };
+        let format = resolve_target.view.attachment.view_format;
+        let pick_mode = |supported: vk::ResolveModeFlags, has_aspect: bool| {
+            if !has_aspect {
+                vk::ResolveModeFlags::NONE
+            } else if supported.contains(mode) {
+                mode
+            } else {
+                log::warn!("Resolve mode {mode:?} is not supported, using SAMPLE_ZERO");
+                vk::ResolveModeFlags::SAMPLE_ZERO
+            }
+        };
+        let mut depth_mode = pick_mode(resolve_caps.depth_modes, format.has_depth_aspect());
+        let mut stencil_mode = pick_mode(resolve_caps.stencil_modes, format.has_stencil_aspect());
+        if format.has_depth_aspect()
+            && format.has_stencil_aspect()
+            && depth_mode != stencil_mode
+            && !resolve_caps.independent_resolve
+        {
+            depth_mode = vk::ResolveModeFlags::SAMPLE_ZERO;
+            stencil_mode = vk::ResolveModeFlags::SAMPLE_ZERO;
         }
 
-        if !vk_barriers.is_empty() {
-            unsafe {
-                self.device.raw.cmd_pipeline_barrier(
-                    self.active,
-                    src_stages,
-                    dst_stages,
-                    vk::DependencyFlags::empty(),
-                    &[],
-                    vk_barriers,
-                    &[],
-                )
-            };
-        }
+        let key = super::DepthStencilResolveKey {
+            base: resolve_target.make_attachment_key(crate::AttachmentOps::STORE, caps),
+            depth_mode,
+            stencil_mode,
+        };
+        self.encoding.next_depth_stencil_resolve = Some((
+            key,
+            resolve_target.view.raw,
+            resolve_target.view.attachment.clone(),
+        ));
+        unsafe { crate::CommandEncoder::begin_render_pass(self, desc) };
     }
 
-    unsafe fn transition_textures<'a, T>(&mut self, barriers: T)
-    where
-        T: Iterator<Item = crate::TextureBarrier<'a, super::Texture>>,
-    {
-        let mut src_stages = vk::PipelineStageFlags::empty();
-        let mut dst_stages = vk::PipelineStageFlags::empty();
-        let vk_barriers = &mut self.temp.image_barriers;
-        vk_barriers.clear();
+    /// Returns `true` if [`Self::set_fragment_shading_rate`] is supported.
+    pub fn supports_fragment_shading_rate(&self) -> bool {
+        self.device.private_caps.fragment_shading_rate.is_some()
+    }
 
-        for bar in barriers {
-            let range = conv::map_subresource_range_combined_aspect(
-                &bar.range,
-                bar.texture.format,
-                &self.device.private_caps,
-            );
-            let (src_stage, src_access) = conv::map_texture_usage_to_barrier(bar.usage.start);
-            let src_layout = conv::derive_image_layout(bar.usage.start, bar.texture.format);
-            src_stages |= src_stage;
-            let (dst_stage, dst_access) = conv::map_texture_usage_to_barrier(bar.usage.end);
-            let dst_layout = conv::derive_image_layout(bar.usage.end, bar.texture.format);
-            dst_stages |= dst_stage;
+    /// Returns the minimum and maximum texel sizes of shading rate
+    /// attachments, if [`Self::begin_render_pass_with_shading_rate_attachment`]
+    /// is supported.
+    pub fn shading_rate_attachment_texel_sizes(&self) -> Option<[[u32; 2]; 2]> {
+        self.device
+            .private_caps
+            .fragment_shading_rate
+            .and_then(|caps| caps.attachment_texel_sizes)
+    }
 
-            vk_barriers.push(
-                vk::ImageMemoryBarrier::default()
-                    .image(bar.texture.raw)
-                    .subresource_range(range)
-                    .src_access_mask(src_access)
-                    .dst_access_mask(dst_access)
-                    .old_layout(src_layout)
-                    .new_layout(dst_layout),
-            );
+    /// Sets the size of the pixel areas shaded by a single fragment shader
+    /// invocation in the following draws, e.g. `[2, 2]` to shade each 2x2
+    /// area once. Each dimension is 1, 2 or 4.
+    ///
+    /// `combiners` are how the rate is combined with the rate of the
+    /// primitive, then with the rate of the shading rate attachment.
+    /// The rate applies until the end of the encoding, across render
+    /// passes, to pipelines created with
+    /// [`super::RenderPipelineOptions::dynamic_fragment_shading_rate`].
+    ///
+    /// # Safety
+    ///
+    /// - [`Self::supports_fragment_shading_rate`] must be `true`.
+    /// - Combiners other than `KEEP` must be supported by the device.
+    pub unsafe fn set_fragment_shading_rate(
+        &mut self,
+        rate: [u32; 2],
+        combiners: [vk::FragmentShadingRateCombinerOpKHR; 2],
+    ) {
+        self.expect_active();
+        if !self.supports_fragment_shading_rate() {
+            super::hal_usage_error("fragment shading rates require `VK_KHR_fragment_shading_rate`");
         }
-
-        if !vk_barriers.is_empty() {
-            unsafe {
-                self.device.raw.cmd_pipeline_barrier(
-                    self.active,
-                    src_stages,
-                    dst_stages,
-                    vk::DependencyFlags::empty(),
-                    &[],
-                    &[],
-                    vk_barriers,
-                )
-            };
+        let Some(extent) = conv::map_fragment_shading_rate(rate) else {
+            super::hal_usage_error(format!("invalid fragment shading rate {rate:?}"));
+        };
+        self.encoding.fragment_shading_rate = (extent, combiners);
+        if self.active_pass == Some(vk::PipelineBindPoint::GRAPHICS) {
+            unsafe { self.apply_fragment_shading_rate() };
         }
     }
 
-    unsafe fn clear_buffer(&mut self, buffer: &super::Buffer, range: crate::MemoryRange) {
-        let range_size = range.end - range.start;
-        if self.device.workarounds.contains(
-            super::Workarounds::FORCE_FILL_BUFFER_WITH_SIZE_GREATER_4096_ALIGNED_OFFSET_16,
-        ) && range_size >= 4096
-            && range.start % 16 != 0
-        {
-            let rounded_start = wgt::math::align_to(range.start, 16);
-            let prefix_size = rounded_start - range.start;
-
-            unsafe {
-                self.device.raw.cmd_fill_buffer(
-                    self.active,
-                    buffer.raw,
-                    range.start,
-                    prefix_size,
-                    0,
-                )
-            };
-
-            // This will never be zero, as rounding can only add up to 12 bytes, and the total size is 4096.
-            let suffix_size = range.end - rounded_start;
-
-            unsafe {
-                self.device.raw.cmd_fill_buffer(
-                    self.active,
-                    buffer.raw,
-                    rounded_start,
-                    suffix_size,
-                    0,
-                )
-            };
-        } else {
-            unsafe {
-                self.device
-                    .raw
-                    .cmd_fill_buffer(self.active, buffer.raw, range.start, range_size, 0)
-            };
-        }
+    unsafe fn apply_fragment_shading_rate(&self) {
+        let (extent, combiners) = self.encoding.fragment_shading_rate;
+        unsafe {
+            self.fragment_shading_rate_fns()
+                .cmd_set_fragment_shading_rate(self.active, &extent, &combiners)
+        };
     }
 
-    unsafe fn copy_buffer_to_buffer<T>(
+    /// Begins a render pass whose shading rates are also read from `view`,
+    /// each of its texels covering an area of `texel_size` pixels.
+    ///
+    /// The attachment is combined with the other rates by the second
+    /// combiner of [`Self::set_fragment_shading_rate`]. It is read in the
+    /// `GENERAL` layout, so it must have been last transitioned to a
+    /// storage usage, e.g. after being written by a compute shader.
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`crate::CommandEncoder::begin_render_pass`].
+    /// - `view` must be a single-sampled `R8Uint` view of a texture created
+    ///   with `VK_IMAGE_USAGE_FRAGMENT_SHADING_RATE_ATTACHMENT_BIT_KHR`,
+    ///   covering the render pass extent.
+    /// - Pipelines used in the pass must be created with
+    ///   [`super::RenderPipelineOptions::shading_rate_attachment_texel_size`]
+    ///   set to `texel_size`.
+    pub unsafe fn begin_render_pass_with_shading_rate_attachment(
         &mut self,
-        src: &super::Buffer,
-        dst: &super::Buffer,
-        regions: T,
-    ) where
-        T: Iterator<Item = crate::BufferCopy>,
-    {
-        let vk_regions_iter = regions.map(|r| vk::BufferCopy {
-            src_offset: r.src_offset,
-            dst_offset: r.dst_offset,
-            size: r.size.get(),
-        });
+        desc: &crate::RenderPassDescriptor<super::QuerySet, super::TextureView>,
+        view: &super::TextureView,
+        texel_size: [u32; 2],
+    ) {
+        self.expect_active();
+        let Some([min, max]) = self.shading_rate_attachment_texel_sizes() else {
+            super::hal_usage_error("shading rate attachments are not supported");
+        };
+        if let Err(err) = check_shading_rate_texel_size(texel_size, min, max) {
+            super::hal_usage_error(err);
+        }
 
+        // Render passes don't wait for prior writes to the shading rate
+        // attachment on their own.
+        let vk_barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE | vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::FRAGMENT_SHADING_RATE_ATTACHMENT_READ_KHR);
         unsafe {
-            self.device.raw.cmd_copy_buffer(
+            self.device.raw.cmd_pipeline_barrier(
                 self.active,
-                src.raw,
-                dst.raw,
-                &smallvec::SmallVec::<[vk::BufferCopy; 32]>::from_iter(vk_regions_iter),
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::FRAGMENT_SHADING_RATE_ATTACHMENT_KHR,
+                vk::DependencyFlags::empty(),
+                &[vk_barrier],
+                &[],
+                &[],
             )
         };
+
+        let key = super::ShadingRateAttachmentKey {
+            format: self
+                .device
+                .private_caps
+                .map_texture_format(view.attachment.view_format),
+            texel_size,
+        };
+        self.encoding.next_shading_rate_attachment = Some((key, view.raw, view.attachment.clone()));
+        unsafe { crate::CommandEncoder::begin_render_pass(self, desc) };
     }
 
-    unsafe fn copy_texture_to_texture<T>(
+    /// Returns what secondary command buffers need to continue the
+    /// currently open render pass, if any.
+    pub fn render_pass_inheritance(&self) -> Option<super::CommandBufferInheritance> {
+        self.render_pass_inheritance
+    }
+
+    /// Executes secondary command buffers in the current render pass.
+    ///
+    /// The bundles aren't consumed: they can be executed again, in this or
+    /// later command buffers, until the encoder that recorded them is reset
+    /// with [`crate::CommandEncoder::reset_all`], which resets all of its
+    /// command buffers. Bundles kept across frames are best recorded with a
+    /// dedicated encoder.
+    ///
+    /// # Safety
+    ///
+    /// - `bundles` must have been encoded with inheritance compatible with
+    ///   the current render pass.
+    /// - The encoders that recorded `bundles` must not have been reset since.
+    pub unsafe fn execute_bundles(&mut self, bundles: &[&super::CommandBuffer]) {
+        self.expect_active();
+        if cfg!(debug_assertions)
+            && self.subpass_contents != vk::SubpassContents::SECONDARY_COMMAND_BUFFERS
+        {
+            super::hal_usage_error(
+                "bundles can only be executed in a render pass begun with `begin_render_pass_with_bundles`",
+            );
+        }
+        let vk_cmd_buffers = bundles
+            .iter()
+            .map(|bundle| {
+                if cfg!(debug_assertions) && !bundle.secondary {
+                    super::hal_usage_error("only secondary command buffers can be executed");
+                }
+                bundle.raw
+            })
+            .collect::<smallvec::SmallVec<[vk::CommandBuffer; 8]>>();
+        if !vk_cmd_buffers.is_empty() {
+            unsafe {
+                self.device
+                    .raw
+                    .cmd_execute_commands(self.active, &vk_cmd_buffers)
+            };
+            // The bound state is undefined after executing secondary command buffers.
+            self.reset_bound_pipelines();
+        }
+    }
+
+    /// Copies regions of `src` into `dst`, scaling them with `filter`
+    /// where the source and destination sizes differ.
+    ///
+    /// Unlike [`crate::CommandEncoder::copy_texture_to_texture`], the formats
+    /// don't need to match, as long as neither or both are integer formats.
+    /// Depth-stencil textures can only be blitted to textures of the same
+    /// format, with nearest filtering: use
+    /// [`crate::CommandEncoder::copy_texture_to_texture`] to copy them
+    /// exactly, or a multisample resolve to average them.
+    ///
+    /// # Safety
+    ///
+    /// - The source subresources must be in `src_usage`, and the destination
+    ///   subresources in `COPY_DST` usage.
+    /// - The formats must support `BLIT_SRC` and `BLIT_DST` respectively, and
+    ///   `SAMPLED_IMAGE_FILTER_LINEAR` for linear filtering.
+    pub unsafe fn blit_texture<T>(
         &mut self,
         src: &super::Texture,
         src_usage: crate::TextureUses,
         dst: &super::Texture,
         regions: T,
+        filter: wgt::FilterMode,
     ) where
-        T: Iterator<Item = crate::TextureCopy>,
+        T: Iterator<Item = super::TextureBlit>,
     {
+        self.expect_active();
+        if let Err(message) = check_blit_formats(src.format, dst.format, filter) {
+            super::hal_usage_error(message);
+        }
         let src_layout = conv::derive_image_layout(src_usage, src.format);
 
         let vk_regions_iter = regions.map(|r| {
-            let (src_subresource, src_offset) = conv::map_subresource_layers(&r.src_base);
-            let (dst_subresource, dst_offset) = conv::map_subresource_layers(&r.dst_base);
-            let extent = r
-                .size
-                .min(&r.src_base.max_copy_size(&src.copy_size))
-                .min(&r.dst_base.max_copy_size(&dst.copy_size));
-            vk::ImageCopy {
+            let (src_subresource, src_offsets) = conv::map_blit_region(&r.src_base, &r.src_size);
+            let (dst_subresource, dst_offsets) = conv::map_blit_region(&r.dst_base, &r.dst_size);
+            // Each subresource can only be in one layout at a time.
+            if src.raw == dst.raw
+                && src_subresource.mip_level == dst_subresource.mip_level
+                && src_subresource.base_array_layer == dst_subresource.base_array_layer
+            {
+                super::hal_usage_error("blit source and destination are the same subresource");
+            }
+            vk::ImageBlit {
                 src_subresource,
-                src_offset,
+                src_offsets,
                 dst_subresource,
-                dst_offset,
-                extent: conv::map_copy_extent(&extent),
+                dst_offsets,
             }
         });
 
         unsafe {
-            self.device.raw.cmd_copy_image(
+            self.device.raw.cmd_blit_image(
                 self.active,
                 src.raw,
                 src_layout,
                 dst.raw,
                 DST_IMAGE_LAYOUT,
-                &smallvec::SmallVec::<[vk::ImageCopy; 32]>::from_iter(vk_regions_iter),
+                &smallvec::SmallVec::<[vk::ImageBlit; 32]>::from_iter(vk_regions_iter),
+                conv::map_filter_mode(filter),
             )
         };
     }
 
-    unsafe fn copy_buffer_to_texture<T>(
+    /// Fills each of the mip levels after `base_mip_level` by downsampling
+    /// the level before it.
+    ///
+    /// Every level is transitioned to `COPY_SRC` right after it's written,
+    /// so that it can be read for the next one.
+    ///
+    /// # Safety
+    ///
+    /// - All `mip_level_count` levels starting at `base_mip_level` must be in
+    ///   `COPY_DST` usage for the given `array_layers`. They are all left in
+    ///   `COPY_SRC` usage.
+    /// - The format must support `BLIT_SRC` and `BLIT_DST`, and
+    ///   `SAMPLED_IMAGE_FILTER_LINEAR` for linear filtering.
+    pub unsafe fn generate_mipmaps(
         &mut self,
-        src: &super::Buffer,
+        texture: &super::Texture,
+        base_mip_level: u32,
+        mip_level_count: u32,
+        array_layers: Range<u32>,
+        filter: wgt::FilterMode,
+    ) {
+        self.expect_active();
+        let aspect_mask = conv::map_aspects(crate::FormatAspects::new(
+            texture.format,
+            wgt::TextureAspect::All,
+        ));
+        let layer_count = array_layers.end - array_layers.start;
+        let subresource_layers = |mip_level| vk::ImageSubresourceLayers {
+            aspect_mask,
+            mip_level,
+            base_array_layer: array_layers.start,
+            layer_count,
+        };
+        let full_offsets = |mip_level| {
+            let size = texture.copy_size.at_mip_level(mip_level);
+            [
+                vk::Offset3D::default(),
+                vk::Offset3D {
+                    x: size.width as i32,
+                    y: size.height as i32,
+                    z: size.depth as i32,
+                },
+            ]
+        };
+        let transition_to_src = |mip_level| {
+            vk::ImageMemoryBarrier::default()
+                .image(texture.raw)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level: mip_level,
+                    level_count: 1,
+                    base_array_layer: array_layers.start,
+                    layer_count,
+                })
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .old_layout(DST_IMAGE_LAYOUT)
+                .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        };
+        let vk_filter = conv::map_filter_mode(filter);
+
+        for level in base_mip_level..base_mip_level + mip_level_count {
+            unsafe {
+                self.device.raw.cmd_pipeline_barrier(
+                    self.active,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[transition_to_src(level)],
+                )
+            };
+
+            let next_level = level + 1;
+            if next_level == base_mip_level + mip_level_count {
+                break;
+            }
+            let blit = vk::ImageBlit {
+                src_subresource: subresource_layers(level),
+                src_offsets: full_offsets(level),
+                dst_subresource: subresource_layers(next_level),
+                dst_offsets: full_offsets(next_level),
+            };
+            unsafe {
+                self.device.raw.cmd_blit_image(
+                    self.active,
+                    texture.raw,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    texture.raw,
+                    DST_IMAGE_LAYOUT,
+                    &[blit],
+                    vk_filter,
+                )
+            };
+        }
+    }
+
+    /// Resolves regions of the multisampled `src` into the single-sampled `dst`.
+    ///
+    /// The regions are transitioned from `src_usage` to `COPY_SRC`, and from
+    /// `dst_usage` to `COPY_DST` before resolving, and are left in those usages.
+    ///
+    /// # Safety
+    ///
+    /// - The source and destination regions must be in `src_usage` and
+    ///   `dst_usage` respectively.
+    /// - The format must support `COLOR_ATTACHMENT` on `dst`.
+    pub unsafe fn resolve_texture<T>(
+        &mut self,
+        src: &super::Texture,
+        src_usage: crate::TextureUses,
         dst: &super::Texture,
+        dst_usage: crate::TextureUses,
         regions: T,
     ) where
-        T: Iterator<Item = crate::BufferTextureCopy>,
+        T: Iterator<Item = crate::TextureCopy>,
     {
-        let vk_regions_iter = dst.map_buffer_copies(regions);
+        self.expect_active();
+        if cfg!(debug_assertions) && (src.sample_count == 1 || dst.sample_count != 1) {
+            super::hal_usage_error(format!(
+                "resolve requires a multisampled source and a single-sampled destination, \
+                 got {} and {} samples",
+                src.sample_count, dst.sample_count
+            ));
+        }
+        if cfg!(debug_assertions) && src.format != dst.format {
+            super::hal_usage_error(format!(
+                "resolve requires matching formats, got {:?} and {:?}",
+                src.format, dst.format
+            ));
+        }
+
+        let regions = smallvec::SmallVec::<[crate::TextureCopy; 4]>::from_iter(regions);
+        let subresource_range = |base: &crate::TextureCopyBase| wgt::ImageSubresourceRange {
+            aspect: base.aspect.map(),
+            base_mip_level: base.mip_level,
+            mip_level_count: Some(1),
+            base_array_layer: base.array_layer,
+            array_layer_count: Some(1),
+        };
+        unsafe {
+            crate::CommandEncoder::transition_textures(
+                self,
+                regions.iter().map(|r| crate::TextureBarrier {
+                    texture: src,
+                    range: subresource_range(&r.src_base),
+                    usage: src_usage..crate::TextureUses::COPY_SRC,
+                }),
+            );
+            crate::CommandEncoder::transition_textures(
+                self,
+                regions.iter().map(|r| crate::TextureBarrier {
+                    texture: dst,
+                    range: subresource_range(&r.dst_base),
+                    usage: dst_usage..crate::TextureUses::COPY_DST,
+                }),
+            );
+        }
+
+        let vk_regions_iter = regions.iter().map(|r| {
+            let (src_subresource, src_offset) = conv::map_subresource_layers(&r.src_base);
+            let (dst_subresource, dst_offset) = conv::map_subresource_layers(&r.dst_base);
+            let extent = r
+                .size
+                .min(&r.src_base.max_copy_size(&src.copy_size))
+                .min(&r.dst_base.max_copy_size(&dst.copy_size));
+            vk::ImageResolve {
+                src_subresource,
+                src_offset,
+                dst_subresource,
+                dst_offset,
+                extent: conv::map_copy_extent(&extent),
+            }
+        });
 
         unsafe {
-            self.device.raw.cmd_copy_buffer_to_image(
+            self.device.raw.cmd_resolve_image(
                 self.active,
                 src.raw,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
                 dst.raw,
                 DST_IMAGE_LAYOUT,
-                &smallvec::SmallVec::<[vk::BufferImageCopy; 32]>::from_iter(vk_regions_iter),
+                &smallvec::SmallVec::<[vk::ImageResolve; 4]>::from_iter(vk_regions_iter),
             )
         };
     }
 
-    unsafe fn copy_texture_to_buffer<T>(
+    /// Clears `range` of `texture` to `value` outside of a render pass.
+    ///
+    /// Color formats need a [`TextureClearValue::Color`], depth and stencil
+    /// formats a [`TextureClearValue::DepthStencil`].
+    ///
+    /// # Safety
+    ///
+    /// - The caller must transition `range` to `COPY_DST` beforehand, so
+    ///   that it is in the `TRANSFER_DST_OPTIMAL` layout.
+    ///
+    /// [`TextureClearValue::Color`]: super::TextureClearValue::Color
+    /// [`TextureClearValue::DepthStencil`]: super::TextureClearValue::DepthStencil
+    pub unsafe fn clear_texture(
         &mut self,
-        src: &super::Texture,
-        src_usage: crate::TextureUses,
-        dst: &super::Buffer,
-        regions: T,
-    ) where
-        T: Iterator<Item = crate::BufferTextureCopy>,
-    {
-        let src_layout = conv::derive_image_layout(src_usage, src.format);
-        let vk_regions_iter = src.map_buffer_copies(regions);
+        texture: &super::Texture,
+        range: &wgt::ImageSubresourceRange,
+        value: super::TextureClearValue,
+    ) {
+        self.expect_active();
+        let vk_range = conv::map_subresource_range_combined_aspect(
+            range,
+            texture.format,
+            &self.device.private_caps,
+        );
+        match value {
+            super::TextureClearValue::Color(color) if !texture.format.is_depth_stencil_format() => {
+                let vk_color = conv::map_clear_color(texture.format, &color);
+                unsafe {
+                    self.device.raw.cmd_clear_color_image(
+                        self.active,
+                        texture.raw,
+                        DST_IMAGE_LAYOUT,
+                        &vk_color,
+                        &[vk_range],
+                    )
+                };
+            }
+            super::TextureClearValue::DepthStencil { depth, stencil }
+                if texture.format.is_depth_stencil_format() =>
+            {
+                let vk_value = vk::ClearDepthStencilValue { depth, stencil };
+                unsafe {
+                    self.device.raw.cmd_clear_depth_stencil_image(
+                        self.active,
+                        texture.raw,
+                        DST_IMAGE_LAYOUT,
+                        &vk_value,
+                        &[vk_range],
+                    )
+                };
+            }
+            _ => super::hal_usage_error(format!(
+                "clear value {value:?} does not match texture format {:?}",
+                texture.format
+            )),
+        }
+    }
 
+    /// Returns `true` if commands gated on `features` can be recorded.
+    ///
+    /// Commands gated on `wgt::Features` have `try_*` counterparts returning
+    /// an [`super::UnsupportedFeatureError`]. Commands gated on Vulkan
+    /// extensions instead have a `supports_*` query each.
+    pub fn supports_features(&self, features: wgt::Features) -> bool {
+        self.device.features.contains(features)
+    }
+
+    /// Like [`crate::CommandEncoder::draw_indirect_count`], but returns an
+    /// error instead of panicking if `MULTI_DRAW_INDIRECT_COUNT` is not enabled.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::CommandEncoder::draw_indirect_count`].
+    pub unsafe fn try_draw_indirect_count(
+        &mut self,
+        buffer: &super::Buffer,
+        offset: wgt::BufferAddress,
+        count_buffer: &super::Buffer,
+        count_offset: wgt::BufferAddress,
+        max_count: u32,
+    ) -> Result<(), super::UnsupportedFeatureError> {
+        check_features(
+            self.device.features,
+            wgt::Features::MULTI_DRAW_INDIRECT_COUNT,
+        )?;
         unsafe {
-            self.device.raw.cmd_copy_image_to_buffer(
-                self.active,
-                src.raw,
-                src_layout,
-                dst.raw,
-                &smallvec::SmallVec::<[vk::BufferImageCopy; 32]>::from_iter(vk_regions_iter),
+            crate::CommandEncoder::draw_indirect_count(
+                self,
+                buffer,
+                offset,
+                count_buffer,
+                count_offset,
+                max_count,
             )
         };
+        Ok(())
     }
 
-    unsafe fn begin_query(&mut self, set: &super::QuerySet, index: u32) {
+    /// Like [`crate::CommandEncoder::draw_indexed_indirect_count`], but returns
+    /// an error instead of panicking if `MULTI_DRAW_INDIRECT_COUNT` is not enabled.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::CommandEncoder::draw_indexed_indirect_count`].
+    pub unsafe fn try_draw_indexed_indirect_count(
+        &mut self,
+        buffer: &super::Buffer,
+        offset: wgt::BufferAddress,
+        count_buffer: &super::Buffer,
+        count_offset: wgt::BufferAddress,
+        max_count: u32,
+    ) -> Result<(), super::UnsupportedFeatureError> {
+        check_features(
+            self.device.features,
+            wgt::Features::MULTI_DRAW_INDIRECT_COUNT,
+        )?;
         unsafe {
-            self.device.raw.cmd_begin_query(
-                self.active,
-                set.raw,
-                index,
-                vk::QueryControlFlags::empty(),
+            crate::CommandEncoder::draw_indexed_indirect_count(
+                self,
+                buffer,
+                offset,
+                count_buffer,
+                count_offset,
+                max_count,
             )
         };
+        Ok(())
     }
-    unsafe fn end_query(&mut self, set: &super::QuerySet, index: u32) {
-        unsafe { self.device.raw.cmd_end_query(self.active, set.raw, index) };
-    }
-    unsafe fn write_timestamp(&mut self, set: &super::QuerySet, index: u32) {
+
+    /// Like [`crate::CommandEncoder::build_acceleration_structures`], but
+    /// returns an error instead of panicking if
+    /// `RAY_TRACING_ACCELERATION_STRUCTURE` is not enabled.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::CommandEncoder::build_acceleration_structures`].
+    pub unsafe fn try_build_acceleration_structures<'a, T>(
+        &mut self,
+        descriptor_count: u32,
+        descriptors: T,
+    ) -> Result<(), super::UnsupportedFeatureError>
+    where
+        super::Api: 'a,
+        T: IntoIterator<
+            Item = crate::BuildAccelerationStructureDescriptor<
+                'a,
+                super::Buffer,
+                super::AccelerationStructure,
+            >,
+        >,
+    {
+        check_features(
+            self.device.features,
+            wgt::Features::RAY_TRACING_ACCELERATION_STRUCTURE,
+        )?;
         unsafe {
-            self.device.raw.cmd_write_timestamp(
-                self.active,
-                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
-                set.raw,
-                index,
+            crate::CommandEncoder::build_acceleration_structures(
+                self,
+                descriptor_count,
+                descriptors,
             )
         };
+        Ok(())
     }
-    unsafe fn reset_queries(&mut self, set: &super::QuerySet, range: Range<u32>) {
+
+    /// Like [`crate::CommandEncoder::place_acceleration_structure_barrier`],
+    /// but returns an error instead of recording the barrier if
+    /// `RAY_TRACING_ACCELERATION_STRUCTURE` is not enabled.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::CommandEncoder::place_acceleration_structure_barrier`].
+    pub unsafe fn try_place_acceleration_structure_barrier(
+        &mut self,
+        barrier: crate::AccelerationStructureBarrier,
+    ) -> Result<(), super::UnsupportedFeatureError> {
+        check_features(
+            self.device.features,
+            wgt::Features::RAY_TRACING_ACCELERATION_STRUCTURE,
+        )?;
+        unsafe { crate::CommandEncoder::place_acceleration_structure_barrier(self, barrier) };
+        Ok(())
+    }
+
+    /// Like [`crate::CommandEncoder::set_push_constants`], but returns an
+    /// error instead of recording the update if `PUSH_CONSTANTS` is not enabled.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::CommandEncoder::set_push_constants`].
+    pub unsafe fn try_set_push_constants(
+        &mut self,
+        layout: &super::PipelineLayout,
+        stages: wgt::ShaderStages,
+        offset_bytes: u32,
+        data: &[u32],
+    ) -> Result<(), super::UnsupportedFeatureError> {
+        check_features(self.device.features, wgt::Features::PUSH_CONSTANTS)?;
         unsafe {
-            self.device.raw.cmd_reset_query_pool(
-                self.active,
-                set.raw,
-                range.start,
-                range.end - range.start,
-            )
+            crate::CommandEncoder::set_push_constants(self, layout, stages, offset_bytes, data)
         };
+        Ok(())
     }
-    unsafe fn copy_query_results(
+
+    /// Like [`crate::CommandEncoder::write_timestamp`], but returns an error
+    /// instead of writing the timestamp if `TIMESTAMP_QUERY_INSIDE_ENCODERS`
+    /// is not enabled.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::CommandEncoder::write_timestamp`].
+    pub unsafe fn try_write_timestamp(
+        &mut self,
+        set: &super::QuerySet,
+        index: u32,
+    ) -> Result<(), super::UnsupportedFeatureError> {
+        check_features(
+            self.device.features,
+            wgt::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS,
+        )?;
+        unsafe { crate::CommandEncoder::write_timestamp(self, set, index) };
+        Ok(())
+    }
+
+    /// Records `draw_count` indirect draws with `record`, which is called with
+    /// the device, the active command buffer, an offset and a draw count.
+    ///
+    /// Without `multiDrawIndirect` the draw count must be 0 or 1, so the
+    /// draws are split into single draws in that case.
+    fn record_indirect_draws(
+        &mut self,
+        offset: wgt::BufferAddress,
+        draw_count: u32,
+        stride: u32,
+        record: impl Fn(&ash::Device, vk::CommandBuffer, wgt::BufferAddress, u32),
+    ) {
+        if draw_count > 1
+            && !self
+                .device
+                .features
+                .contains(wgt::Features::MULTI_DRAW_INDIRECT)
+        {
+            for offset in indirect_draw_offsets(offset, draw_count, stride) {
+                record(&self.device.raw, self.active, offset, 1);
+            }
+        } else {
+            record(&self.device.raw, self.active, offset, draw_count);
+        }
+    }
+
+    /// Returns `true` if `VK_EXT_conditional_rendering` is enabled, see
+    /// [`Self::begin_conditional_rendering`].
+    pub fn supports_conditional_rendering(&self) -> bool {
+        self.device.extension_fns.conditional_rendering.is_some()
+    }
+
+    /// Begins a conditional rendering block.
+    ///
+    /// Until [`Self::end_conditional_rendering`], draws and clears are
+    /// discarded if the 32-bit value at `offset` in `buffer` is zero, or
+    /// non-zero if `inverted` is set.
+    ///
+    /// To skip drawing objects whose bounding boxes were occluded:
+    /// 1. draw the bounding boxes inside occlusion queries, with
+    ///    [`crate::CommandEncoder::begin_query`] and
+    ///    [`crate::CommandEncoder::end_query`], outside of the pass reading
+    ///    the results;
+    /// 2. write the results with [`Self::copy_occlusion_predicates`];
+    /// 3. transition the buffer from `COPY_DST` to `CONDITIONAL_RENDERING`
+    ///    with [`crate::CommandEncoder::transition_buffers`], which waits for
+    ///    the copy before the predicate is read;
+    /// 4. draw each object within a conditional rendering block reading its
+    ///    predicate.
+    ///
+    /// # Safety
+    ///
+    /// - [`Self::supports_conditional_rendering`] must be `true`.
+    /// - `buffer` must be in the `CONDITIONAL_RENDERING` usage and `offset`
+    ///   must be a multiple of 4.
+    /// - Conditional rendering blocks can't be nested, and a block begun
+    ///   inside a render pass must end within it.
+    pub unsafe fn begin_conditional_rendering(
+        &mut self,
+        buffer: &super::Buffer,
+        offset: wgt::BufferAddress,
+        inverted: bool,
+    ) {
+        self.expect_active();
+        if cfg!(debug_assertions) && offset % 4 != 0 {
+            super::hal_usage_error(format!(
+                "conditional rendering offset {offset} is not a multiple of 4"
+            ));
+        }
+        let flags = if inverted {
+            vk::ConditionalRenderingFlagsEXT::INVERTED
+        } else {
+            vk::ConditionalRenderingFlagsEXT::empty()
+        };
+        let vk_info = vk::ConditionalRenderingBeginInfoEXT::default()
+            .buffer(buffer.raw)
+            .offset(offset)
+            .flags(flags);
+        let ext = self.conditional_rendering_fns();
+        unsafe { (ext.fp().cmd_begin_conditional_rendering_ext)(self.active, &vk_info) };
+    }
+
+    /// Writes the results of the occlusion queries in `range` of `set` as
+    /// tightly packed 32-bit predicates at `offset` in `buffer`, for
+    /// [`Self::begin_conditional_rendering`]: each is non-zero if any sample
+    /// of the query passed.
+    ///
+    /// The copy waits for the queries to finish. Vulkan lets sample counts
+    /// that don't fit in 32 bits wrap, which takes billions of samples.
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`crate::CommandEncoder::copy_query_results`].
+    /// - `set` must be an occlusion query set.
+    /// - `offset` must be a multiple of 4.
+    pub unsafe fn copy_occlusion_predicates(
         &mut self,
         set: &super::QuerySet,
         range: Range<u32>,
         buffer: &super::Buffer,
         offset: wgt::BufferAddress,
-        stride: wgt::BufferSize,
     ) {
+        self.expect_active();
+        set.check_range(&range);
+        if cfg!(debug_assertions) && set.result_kind() != super::QueryResultKind::Occlusion {
+            super::hal_usage_error("predicates can only be copied from occlusion queries");
+        }
+        if cfg!(debug_assertions) && offset % 4 != 0 {
+            super::hal_usage_error(format!(
+                "occlusion predicate offset {offset} is not a multiple of 4"
+            ));
+        }
+        let stride = mem::size_of::<u32>() as wgt::BufferAddress;
         unsafe {
             self.device.raw.cmd_copy_query_pool_results(
                 self.active,
@@ -414,74 +1107,3061 @@ impl crate::CommandEncoder for super::CommandEncoder {
                 range.end - range.start,
                 buffer.raw,
                 offset,
-                stride.get(),
-                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+                stride,
+                vk::QueryResultFlags::WAIT,
             )
         };
     }
 
-    unsafe fn build_acceleration_structures<'a, T>(&mut self, descriptor_count: u32, descriptors: T)
-    where
-        super::Api: 'a,
-        T: IntoIterator<
-            Item = crate::BuildAccelerationStructureDescriptor<
-                'a,
-                super::Buffer,
-                super::AccelerationStructure,
-            >,
-        >,
-    {
-        const CAPACITY_OUTER: usize = 8;
-        const CAPACITY_INNER: usize = 1;
-        let descriptor_count = descriptor_count as usize;
+    /// Ends the conditional rendering block begun by [`Self::begin_conditional_rendering`].
+    ///
+    /// # Safety
+    ///
+    /// - A conditional rendering block must be active.
+    pub unsafe fn end_conditional_rendering(&mut self) {
+        self.expect_active();
+        let ext = self.conditional_rendering_fns();
+        unsafe { (ext.fp().cmd_end_conditional_rendering_ext)(self.active) };
+    }
 
-        let ray_tracing_functions = self
-            .device
+    fn conditional_rendering_fns(&self) -> &ext::conditional_rendering::Device {
+        self.device
             .extension_fns
-            .ray_tracing
+            .conditional_rendering
             .as_ref()
-            .expect("Feature `RAY_TRACING` not enabled");
+            .expect("`VK_EXT_conditional_rendering` is not enabled")
+    }
 
-        let get_device_address = |buffer: Option<&super::Buffer>| unsafe {
-            match buffer {
-                Some(buffer) => ray_tracing_functions
-                    .buffer_device_address
-                    .get_buffer_device_address(
-                        &vk::BufferDeviceAddressInfo::default().buffer(buffer.raw),
-                    ),
-                None => panic!("Buffers are required to build acceleration structures"),
-            }
-        };
+    /// Returns `true` if `VK_EXT_transform_feedback` is enabled, see
+    /// [`Self::begin_transform_feedback`].
+    pub fn supports_transform_feedback(&self) -> bool {
+        self.device.extension_fns.transform_feedback.is_some()
+    }
 
-        // storage to all the data required for cmd_build_acceleration_structures
-        let mut ranges_storage = smallvec::SmallVec::<
-            [smallvec::SmallVec<[vk::AccelerationStructureBuildRangeInfoKHR; CAPACITY_INNER]>;
-                CAPACITY_OUTER],
-        >::with_capacity(descriptor_count);
-        let mut geometries_storage = smallvec::SmallVec::<
-            [smallvec::SmallVec<[vk::AccelerationStructureGeometryKHR; CAPACITY_INNER]>;
-                CAPACITY_OUTER],
-        >::with_capacity(descriptor_count);
+    /// Binds the buffers capturing the vertex outputs, starting at
+    /// transform feedback binding `first`.
+    ///
+    /// # Safety
+    ///
+    /// - [`Self::supports_transform_feedback`] must be `true`.
+    /// - The buffers must be in the `TRANSFORM_FEEDBACK` usage, and the
+    ///   offsets must be multiples of 4.
+    /// - Transform feedback must not be active.
+    pub unsafe fn bind_transform_feedback_buffers(
+        &mut self,
+        first: u32,
+        bindings: &[crate::BufferBinding<'_, super::Buffer>],
+    ) {
+        self.expect_active();
+        let vk_buffers = bindings
+            .iter()
+            .map(|binding| binding.buffer.raw)
+            .collect::<smallvec::SmallVec<[_; 4]>>();
+        let vk_offsets = bindings
+            .iter()
+            .map(|binding| binding.offset)
+            .collect::<smallvec::SmallVec<[_; 4]>>();
+        let vk_sizes = bindings
+            .iter()
+            .map(|binding| binding.size.map_or(vk::WHOLE_SIZE, wgt::BufferSize::get))
+            .collect::<smallvec::SmallVec<[_; 4]>>();
+        let ext = self.transform_feedback_fns();
+        unsafe {
+            (ext.fp().cmd_bind_transform_feedback_buffers_ext)(
+                self.active,
+                first,
+                bindings.len() as u32,
+                vk_buffers.as_ptr(),
+                vk_offsets.as_ptr(),
+                vk_sizes.as_ptr(),
+            )
+        };
+    }
 
-        // pointers to all the data required for cmd_build_acceleration_structures
-        let mut geometry_infos = smallvec::SmallVec::<
-            [vk::AccelerationStructureBuildGeometryInfoKHR; CAPACITY_OUTER],
-        >::with_capacity(descriptor_count);
-        let mut ranges_ptrs = smallvec::SmallVec::<
-            [&[vk::AccelerationStructureBuildRangeInfoKHR]; CAPACITY_OUTER],
-        >::with_capacity(descriptor_count);
+    /// Begins capturing the vertex outputs of draws into the bound transform
+    /// feedback buffers.
+    ///
+    /// `counters` holds a counter buffer and offset per transform feedback
+    /// binding, from which capturing resumes. An empty slice, or a `None`
+    /// counter, starts capturing at the beginning of the buffers.
+    ///
+    /// # Safety
+    ///
+    /// - [`Self::supports_transform_feedback`] must be `true`.
+    /// - Must be called inside a render pass, and ended within it.
+    /// - The counter buffers must be in the `TRANSFORM_FEEDBACK_COUNTER` usage.
+    pub unsafe fn begin_transform_feedback(
+        &mut self,
+        counters: &[Option<(&super::Buffer, wgt::BufferAddress)>],
+    ) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "begin_transform_feedback");
+        let (vk_buffers, vk_offsets) = map_transform_feedback_counters(counters);
+        let ext = self.transform_feedback_fns();
+        unsafe {
+            (ext.fp().cmd_begin_transform_feedback_ext)(
+                self.active,
+                0,
+                counters.len() as u32,
+                vk_buffers.as_ptr(),
+                vk_offsets.as_ptr(),
+            )
+        };
+    }
 
-        for desc in descriptors {
-            let (geometries, ranges) = match *desc.entries {
-                crate::AccelerationStructureEntries::Instances(ref instances) => {
-                    let instance_data = vk::AccelerationStructureGeometryInstancesDataKHR::default(
-                    // TODO: Code is so large that rustfmt refuses to treat this... :(
-                    )
-                    .data(vk::DeviceOrHostAddressConstKHR {
-                        device_address: get_device_address(instances.buffer),
-                    });
+    /// Ends capturing, writing the capture positions into `counters`, so that
+    /// a later [`Self::begin_transform_feedback`] can resume from them.
+    ///
+    /// # Safety
+    ///
+    /// - Transform feedback must be active.
+    /// - The counter buffers must be in the `TRANSFORM_FEEDBACK_COUNTER` usage.
+    pub unsafe fn end_transform_feedback(
+        &mut self,
+        counters: &[Option<(&super::Buffer, wgt::BufferAddress)>],
+    ) {
+        self.expect_active();
+        let (vk_buffers, vk_offsets) = map_transform_feedback_counters(counters);
+        let ext = self.transform_feedback_fns();
+        unsafe {
+            (ext.fp().cmd_end_transform_feedback_ext)(
+                self.active,
+                0,
+                counters.len() as u32,
+                vk_buffers.as_ptr(),
+                vk_offsets.as_ptr(),
+            )
+        };
+    }
 
-                    let geometry = vk::AccelerationStructureGeometryKHR::default()
+    fn transform_feedback_fns(&self) -> &ext::transform_feedback::Device {
+        self.device
+            .extension_fns
+            .transform_feedback
+            .as_ref()
+            .expect("`VK_EXT_transform_feedback` is not enabled")
+    }
+
+    /// Like [`crate::CommandEncoder::copy_buffer_to_texture`], with `dst`
+    /// in the layout of `dst_usage` instead of `COPY_DST`, e.g. a storage
+    /// image kept in the `GENERAL` layout.
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`crate::CommandEncoder::copy_buffer_to_texture`], with
+    ///   `dst` in `dst_usage` instead of `COPY_DST`.
+    /// - `dst_usage` must map to the `TRANSFER_DST_OPTIMAL` or `GENERAL`
+    ///   layout.
+    pub unsafe fn copy_buffer_to_texture_with_dst_usage<T>(
+        &mut self,
+        src: &super::Buffer,
+        dst: &super::Texture,
+        dst_usage: crate::TextureUses,
+        regions: T,
+    ) where
+        T: Iterator<Item = crate::BufferTextureCopy>,
+    {
+        self.expect_active();
+        self.check_buffer_usage(src, crate::BufferUses::COPY_SRC, "copy_buffer_to_texture");
+        self.check_texture_usage(dst, dst_usage, "copy_buffer_to_texture");
+        let dst_layout = copy_dst_layout(dst, dst_usage);
+        let vk_regions = smallvec::SmallVec::<[vk::BufferImageCopy; 32]>::from_iter(
+            dst.map_buffer_copies(regions),
+        );
+        if vk_regions.is_empty() {
+            return;
+        }
+
+        unsafe {
+            self.device.raw.cmd_copy_buffer_to_image(
+                self.active,
+                src.raw,
+                dst.raw,
+                dst_layout,
+                &vk_regions,
+            )
+        };
+        self.count(|stats| &mut stats.copies);
+    }
+
+    /// Like [`crate::CommandEncoder::copy_texture_to_texture`], with `dst`
+    /// in the layout of `dst_usage` instead of `COPY_DST`, e.g. a storage
+    /// image kept in the `GENERAL` layout.
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`crate::CommandEncoder::copy_texture_to_texture`], with
+    ///   `dst` in `dst_usage` instead of `COPY_DST`.
+    /// - `dst_usage` must map to the `TRANSFER_DST_OPTIMAL` or `GENERAL`
+    ///   layout.
+    pub unsafe fn copy_texture_to_texture_with_dst_usage<T>(
+        &mut self,
+        src: &super::Texture,
+        src_usage: crate::TextureUses,
+        dst: &super::Texture,
+        dst_usage: crate::TextureUses,
+        regions: T,
+    ) where
+        T: Iterator<Item = crate::TextureCopy>,
+    {
+        self.expect_active();
+        let src_layout = conv::derive_image_layout(src_usage, src.format);
+        let dst_layout = copy_dst_layout(dst, dst_usage);
+        // Formats with different blocks, e.g. BC1 and RG32Uint, can be copied
+        // between if their blocks are the same size. `r.size` is then in
+        // texels of `src`, and covers the same number of blocks in `dst`.
+        let src_block = src.format.block_dimensions();
+        let dst_block = dst.format.block_dimensions();
+        check_copy_formats(src.format, dst.format);
+        if cfg!(debug_assertions) {
+            if let Err(message) = check_copy_sample_counts(src.sample_count, dst.sample_count) {
+                super::hal_usage_error(message);
+            }
+        }
+
+        let vk_regions_iter = regions.filter_map(|r| {
+            check_copy_region(src.format, &src.copy_size, &r.src_base, &r.size, false);
+            check_copy_region(
+                dst.format,
+                &dst.copy_size,
+                &r.dst_base,
+                &convert_block_extent(&r.size, src_block, dst_block),
+                false,
+            );
+            let (src_subresource, src_offset) = conv::map_subresource_layers(&r.src_base);
+            let (dst_subresource, dst_offset) = conv::map_subresource_layers(&r.dst_base);
+            let extent =
+                r.size
+                    .min(&r.src_base.max_copy_size(&src.copy_size))
+                    .min(&convert_block_extent(
+                        &r.dst_base.max_copy_size(&dst.copy_size),
+                        dst_block,
+                        src_block,
+                    ));
+            if extent.is_empty() {
+                return None;
+            }
+            Some(vk::ImageCopy {
+                src_subresource,
+                src_offset,
+                dst_subresource,
+                dst_offset,
+                extent: conv::map_copy_extent(&extent),
+            })
+        });
+        let vk_regions = smallvec::SmallVec::<[vk::ImageCopy; 32]>::from_iter(vk_regions_iter);
+        if vk_regions.is_empty() {
+            return;
+        }
+
+        unsafe {
+            self.device.raw.cmd_copy_image(
+                self.active,
+                src.raw,
+                src_layout,
+                dst.raw,
+                dst_layout,
+                &vk_regions,
+            )
+        };
+        self.count(|stats| &mut stats.copies);
+    }
+
+    /// Like [`crate::CommandEncoder::transition_buffers`], waiting on and
+    /// blocking only `shader_stages.start` and `shader_stages.end` for shader
+    /// accesses, instead of every shader stage.
+    ///
+    /// Empty stages keep the conservative stages.
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`crate::CommandEncoder::transition_buffers`].
+    /// - The shaders accessing the buffers before and after the barriers
+    ///   must be in `shader_stages.start` and `shader_stages.end`.
+    pub unsafe fn transition_buffers_in_stages<'a, T>(
+        &mut self,
+        barriers: T,
+        shader_stages: Range<wgt::ShaderStages>,
+    ) where
+        T: Iterator<Item = crate::BufferBarrier<'a, super::Buffer>>,
+    {
+        self.expect_active();
+        let synchronization2 = self.device.extension_fns.synchronization2.is_some();
+        //Note: this is done so that we never end up with empty stage flags
+        let mut src_stages = vk::PipelineStageFlags::TOP_OF_PIPE;
+        let mut dst_stages = vk::PipelineStageFlags::BOTTOM_OF_PIPE;
+        let vk_barriers = &mut self.temp.buffer_barriers;
+        vk_barriers.clear();
+        let vk_barriers2 = &mut self.temp.buffer_barriers2;
+        vk_barriers2.clear();
+
+        for bar in barriers {
+            if let Some(ref mut tracker) = self.encoding.usage_tracker {
+                tracker.record_buffer(&bar);
+            }
+            let (src_stage, src_access) = conv::map_buffer_usage_to_barrier(bar.usage.start);
+            let src_stage = conv::narrow_shader_stages(src_stage, shader_stages.start);
+            let (dst_stage, dst_access) = conv::map_buffer_usage_to_barrier(bar.usage.end);
+            let dst_stage = conv::narrow_shader_stages(dst_stage, shader_stages.end);
+            if synchronization2 {
+                vk_barriers2.push(
+                    vk::BufferMemoryBarrier2::default()
+                        .buffer(bar.buffer.raw)
+                        .size(vk::WHOLE_SIZE)
+                        .src_stage_mask(conv::map_stage_flags2(src_stage))
+                        .src_access_mask(conv::map_access_flags2(src_access))
+                        .dst_stage_mask(conv::map_stage_flags2(dst_stage))
+                        .dst_access_mask(conv::map_access_flags2(dst_access)),
+                );
+                continue;
+            }
+            src_stages |= src_stage;
+            dst_stages |= dst_stage;
+
+            vk_barriers.push(
+                vk::BufferMemoryBarrier::default()
+                    .buffer(bar.buffer.raw)
+                    .size(vk::WHOLE_SIZE)
+                    .src_access_mask(src_access)
+                    .dst_access_mask(dst_access),
+            )
+        }
+
+        if synchronization2 {
+            if !self.temp.buffer_barriers2.is_empty() {
+                let vk_info = vk::DependencyInfo::default()
+                    .buffer_memory_barriers(&self.temp.buffer_barriers2);
+                unsafe { self.pipeline_barrier2(&vk_info) };
+                self.count(|stats| &mut stats.barriers);
+            }
+        } else if !vk_barriers.is_empty() {
+            unsafe {
+                self.device.raw.cmd_pipeline_barrier(
+                    self.active,
+                    src_stages,
+                    dst_stages,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    vk_barriers,
+                    &[],
+                )
+            };
+            self.count(|stats| &mut stats.barriers);
+        }
+    }
+
+    /// Makes the writes of compute shaders to `buffer` visible to the
+    /// indirect draws and dispatches reading their arguments from it.
+    ///
+    /// This is the barrier between a compute pass filling indirect arguments
+    /// and the passes consuming them. Like every buffer barrier, it covers
+    /// the whole buffer.
+    ///
+    /// # Safety
+    ///
+    /// - `buffer` must be in the `STORAGE_READ_WRITE` state, and is left in
+    ///   the `INDIRECT` state.
+    /// - Must not be called inside a render pass.
+    pub unsafe fn barrier_compute_to_indirect(&mut self, buffer: &super::Buffer) {
+        self.check_outside_render_pass("barrier_compute_to_indirect");
+        let barrier = crate::BufferBarrier {
+            buffer,
+            usage: crate::BufferUses::STORAGE_READ_WRITE..crate::BufferUses::INDIRECT,
+        };
+        unsafe {
+            self.transition_buffers_in_stages(
+                std::iter::once(barrier),
+                wgt::ShaderStages::COMPUTE..wgt::ShaderStages::empty(),
+            )
+        };
+    }
+
+    /// Like [`crate::CommandEncoder::transition_textures`], waiting on and
+    /// blocking only `shader_stages.start` and `shader_stages.end` for shader
+    /// accesses, instead of every shader stage.
+    ///
+    /// Empty stages keep the conservative stages.
+    ///
+    /// Barriers whose usage doesn't change don't emit image barriers: they
+    /// are skipped if the usage is read-only, and become a memory barrier,
+    /// shared by all of them, otherwise, unless disabled with
+    /// [`Self::set_barrier_elision`].
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`crate::CommandEncoder::transition_textures`].
+    /// - The shaders accessing the textures before and after the barriers
+    ///   must be in `shader_stages.start` and `shader_stages.end`.
+    pub unsafe fn transition_textures_in_stages<'a, T>(
+        &mut self,
+        barriers: T,
+        shader_stages: Range<wgt::ShaderStages>,
+    ) where
+        T: Iterator<Item = crate::TextureBarrier<'a, super::Texture>>,
+    {
+        self.expect_active();
+        let synchronization2 = self.device.extension_fns.synchronization2.is_some();
+        let elision = self.barrier_elision;
+        let mut src_stages = vk::PipelineStageFlags::empty();
+        let mut dst_stages = vk::PipelineStageFlags::empty();
+        let vk_barriers = &mut self.temp.image_barriers;
+        vk_barriers.clear();
+        let vk_barriers2 = &mut self.temp.image_barriers2;
+        vk_barriers2.clear();
+        // Orders the writes of barriers whose usage doesn't change.
+        let mut vk_memory_barrier = vk::MemoryBarrier::default();
+        let mut vk_memory_barrier2 = vk::MemoryBarrier2::default();
+
+        for bar in barriers {
+            if let Some(ref mut tracker) = self.encoding.usage_tracker {
+                tracker.record_texture(&bar);
+            }
+            let range = conv::map_barrier_subresource_range(
+                bar.texture,
+                &bar.range,
+                &self.device.private_caps,
+            );
+            let (src_stage, src_access) = conv::map_texture_usage_to_barrier(bar.usage.start);
+            let src_stage = conv::narrow_shader_stages(src_stage, shader_stages.start);
+            let src_layout = conv::derive_image_layout(bar.usage.start, bar.texture.format);
+            let (dst_stage, dst_access) = conv::map_texture_usage_to_barrier(bar.usage.end);
+            let dst_stage = conv::narrow_shader_stages(dst_stage, shader_stages.end);
+            let dst_layout = conv::derive_image_layout(bar.usage.end, bar.texture.format);
+
+            // Read-only accesses within the same layout don't need a barrier.
+            if elision
+                && src_layout == dst_layout
+                && src_access == dst_access
+                && !src_access.intersects(WRITE_ACCESS_FLAGS)
+            {
+                continue;
+            }
+            // There is no layout transition, so only the accesses need to be
+            // ordered, which doesn't need to name the image.
+            if elision && bar.usage.start == bar.usage.end && src_layout == dst_layout {
+                if synchronization2 {
+                    vk_memory_barrier2.src_stage_mask |= conv::map_stage_flags2(src_stage);
+                    vk_memory_barrier2.src_access_mask |= conv::map_access_flags2(src_access);
+                    vk_memory_barrier2.dst_stage_mask |= conv::map_stage_flags2(dst_stage);
+                    vk_memory_barrier2.dst_access_mask |= conv::map_access_flags2(dst_access);
+                } else {
+                    src_stages |= src_stage;
+                    dst_stages |= dst_stage;
+                    vk_memory_barrier.src_access_mask |= src_access;
+                    vk_memory_barrier.dst_access_mask |= dst_access;
+                }
+                continue;
+            }
+            // Each barrier keeps its own stages, instead of waiting on the
+            // union of all of them.
+            if synchronization2 {
+                vk_barriers2.push(
+                    vk::ImageMemoryBarrier2::default()
+                        .image(bar.texture.raw)
+                        .subresource_range(range)
+                        .src_stage_mask(conv::map_stage_flags2(src_stage))
+                        .src_access_mask(conv::map_access_flags2(src_access))
+                        .dst_stage_mask(conv::map_stage_flags2(dst_stage))
+                        .dst_access_mask(conv::map_access_flags2(dst_access))
+                        .old_layout(src_layout)
+                        .new_layout(dst_layout),
+                );
+                continue;
+            }
+            src_stages |= src_stage;
+            dst_stages |= dst_stage;
+
+            let vk_barrier = vk::ImageMemoryBarrier::default()
+                .image(bar.texture.raw)
+                .subresource_range(range)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .old_layout(src_layout)
+                .new_layout(dst_layout);
+            if elision {
+                push_image_barrier(vk_barriers, vk_barrier);
+            } else {
+                vk_barriers.push(vk_barrier);
+            }
+        }
+
+        let vk_memory_barriers2: &[_] = if vk_memory_barrier2.src_access_mask.is_empty() {
+            &[]
+        } else {
+            slice::from_ref(&vk_memory_barrier2)
+        };
+        let vk_memory_barriers: &[_] = if vk_memory_barrier.src_access_mask.is_empty() {
+            &[]
+        } else {
+            slice::from_ref(&vk_memory_barrier)
+        };
+        if synchronization2 {
+            if !self.temp.image_barriers2.is_empty() || !vk_memory_barriers2.is_empty() {
+                let vk_info = vk::DependencyInfo::default()
+                    .memory_barriers(vk_memory_barriers2)
+                    .image_memory_barriers(&self.temp.image_barriers2);
+                unsafe { self.pipeline_barrier2(&vk_info) };
+                self.count(|stats| &mut stats.barriers);
+            }
+        } else if !vk_barriers.is_empty() || !vk_memory_barriers.is_empty() {
+            unsafe {
+                self.device.raw.cmd_pipeline_barrier(
+                    self.active,
+                    src_stages,
+                    dst_stages,
+                    vk::DependencyFlags::empty(),
+                    vk_memory_barriers,
+                    &[],
+                    vk_barriers,
+                )
+            };
+            self.count(|stats| &mut stats.barriers);
+        }
+    }
+
+    /// Clears `view` to `color` when the next render pass begins, instead of
+    /// loading its contents.
+    ///
+    /// The clear is folded into the render pass by replacing the `LOAD` op
+    /// of the color attachment of `view` with a clear, and `color` overrides
+    /// its clear value. Compared to [`Self::clear_texture`] before a pass
+    /// that loads, this saves a write and a read of the whole attachment on
+    /// tiled GPUs, as well as the barrier between them.
+    ///
+    /// # Safety
+    ///
+    /// - The next render pass must have `view` as a color attachment.
+    /// - Only the render area of the pass is cleared, so it must cover all
+    ///   of `view` that is meant to be cleared.
+    pub unsafe fn clear_on_load(&mut self, view: &super::TextureView, color: wgt::Color) {
+        self.expect_active();
+        self.check_outside_render_pass("clear_on_load");
+        if let Some(pending) = self
+            .pending_clears
+            .iter_mut()
+            .find(|(raw, _)| *raw == view.raw)
+        {
+            pending.1 = color;
+        } else if self
+            .encoding
+            .pending_clears
+            .try_push((view.raw, color))
+            .is_err()
+        {
+            super::hal_usage_error(format!(
+                "more than {} clears are pending for the next render pass",
+                crate::MAX_COLOR_ATTACHMENTS
+            ));
+        }
+    }
+
+    /// Sets the current recording aside, so that another one can be begun on
+    /// this encoder without ending it.
+    ///
+    /// This allows interleaving the recording of several command buffers,
+    /// e.g. uploads and rendering, with a single encoder. The state of the
+    /// recording, such as dynamic state, pending clears, reset queries and
+    /// tracked usages, is set aside with it.
+    ///
+    /// # Safety
+    ///
+    /// - Must not be called inside a pass.
+    pub unsafe fn suspend_encoding(&mut self) -> super::SuspendedEncoding {
+        self.expect_active();
+        if self.active_pass.is_some() {
+            super::hal_usage_error("suspend_encoding called inside a pass");
+        }
+        self.flush_bind_groups();
+        let raw = mem::replace(&mut self.active, vk::CommandBuffer::null());
+        let next = self.encoding.begin();
+        super::SuspendedEncoding {
+            cmd_buf: super::CommandBuffer {
+                raw,
+                secondary: mem::take(&mut self.active_is_secondary),
+                external: mem::take(&mut self.active_is_external),
+                signal: self.signal.take(),
+            },
+            encoding: mem::replace(&mut self.encoding, next),
+        }
+    }
+
+    /// Continues a recording set aside by [`Self::suspend_encoding`], which
+    /// can then be ended or discarded as usual.
+    ///
+    /// Pipelines and bind groups must be bound again before the next draw
+    /// or dispatch.
+    ///
+    /// # Safety
+    ///
+    /// - The encoder must not be recording.
+    /// - `suspended` must come from this encoder, since the command buffer
+    ///   belongs to its pool.
+    pub unsafe fn resume_encoding(&mut self, suspended: super::SuspendedEncoding) {
+        if cfg!(debug_assertions) && self.active != vk::CommandBuffer::null() {
+            super::hal_usage_error(
+                "resume_encoding called while the previous encoding was not ended or discarded",
+            );
+        }
+        let super::SuspendedEncoding { cmd_buf, encoding } = suspended;
+        self.active = cmd_buf.raw;
+        self.active_is_secondary = cmd_buf.secondary;
+        self.active_is_external = cmd_buf.external;
+        self.signal = cmd_buf.signal;
+        self.rpass_debug_marker_active = false;
+        self.reset_bound_pipelines();
+        self.pending_bind_groups.clear();
+        self.active_pass = None;
+        self.encoding = encoding.resume(&self.encoding);
+    }
+
+    /// Returns `true` if [`Self::dispatch_base`] is supported.
+    pub fn supports_dispatch_base(&self) -> bool {
+        self.device.private_caps.dispatch_base
+    }
+
+    /// Like [`crate::CommandEncoder::dispatch`], with the workgroup IDs
+    /// starting at `base` instead of zero.
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`crate::CommandEncoder::dispatch`].
+    /// - [`Self::supports_dispatch_base`] must be `true`.
+    pub unsafe fn dispatch_base(&mut self, base: [u32; 3], count: [u32; 3]) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::COMPUTE, "dispatch_base");
+        self.check_pipeline_bound(vk::PipelineBindPoint::COMPUTE, "dispatch_base");
+        if !self.device.private_caps.dispatch_base {
+            super::hal_usage_error("vkCmdDispatchBase requires Vulkan 1.1");
+        }
+        self.flush_bind_groups();
+        let [base_x, base_y, base_z] = base;
+        let [x, y, z] = count;
+        unsafe {
+            self.device
+                .raw
+                .cmd_dispatch_base(self.active, base_x, base_y, base_z, x, y, z)
+        };
+        self.count(|stats| &mut stats.dispatches);
+    }
+
+    /// Records `max_count` indirect dispatches of `stride` bytes each,
+    /// starting at `offset` in `buffer`.
+    ///
+    /// Vulkan has no indirect dispatch with a count buffer, so a variable
+    /// number of dispatches computed on the GPU is recorded as the maximum
+    /// number, and the unused ones must be made empty by writing zero
+    /// workgroup counts for them, e.g. by the shader writing the arguments.
+    /// Empty dispatches still cost their commands, so `max_count` is best
+    /// kept close to the actual counts.
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`crate::CommandEncoder::dispatch_indirect`], for each
+    ///   dispatch.
+    /// - `stride` must be a multiple of 4, and at least the size of
+    ///   [`wgt::DispatchIndirectArgs`].
+    pub unsafe fn dispatch_indirect_multi(
+        &mut self,
+        buffer: &super::Buffer,
+        offset: wgt::BufferAddress,
+        max_count: u32,
+        stride: u32,
+    ) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::COMPUTE, "dispatch_indirect_multi");
+        self.check_pipeline_bound(vk::PipelineBindPoint::COMPUTE, "dispatch_indirect_multi");
+        if cfg!(debug_assertions) {
+            if let Err(message) = check_indirect_buffer_size(buffer.size, offset, max_count, stride)
+            {
+                super::hal_usage_error(message);
+            }
+        }
+        self.flush_bind_groups();
+        for offset in indirect_draw_offsets(offset, max_count, stride) {
+            unsafe {
+                self.device
+                    .raw
+                    .cmd_dispatch_indirect(self.active, buffer.raw, offset)
+            };
+            self.count(|stats| &mut stats.dispatches);
+        }
+    }
+
+    /// Returns `true` if `VK_EXT_mesh_shader` is enabled, see
+    /// [`Self::draw_mesh_tasks`].
+    pub fn supports_mesh_shader(&self) -> bool {
+        self.device.extension_fns.mesh_shader.is_some()
+    }
+
+    /// Draws `group_counts` mesh shader workgroups, or task shader
+    /// workgroups if the bound pipeline has a task shader.
+    ///
+    /// # Safety
+    ///
+    /// - [`Self::supports_mesh_shader`] must be `true`.
+    /// - The bound render pipeline must have a mesh shader.
+    /// - `group_counts` must not exceed `maxMeshWorkGroupCount`, or
+    ///   `maxTaskWorkGroupCount` with a task shader.
+    pub unsafe fn draw_mesh_tasks(&mut self, group_counts: [u32; 3]) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "draw_mesh_tasks");
+        self.check_pipeline_bound(vk::PipelineBindPoint::GRAPHICS, "draw_mesh_tasks");
+        self.check_viewport_counts("draw_mesh_tasks");
+        self.flush_bind_groups();
+        let [x, y, z] = group_counts;
+        unsafe {
+            self.mesh_shader_fns()
+                .cmd_draw_mesh_tasks(self.active, x, y, z)
+        };
+        self.count(|stats| &mut stats.draws);
+    }
+
+    /// Like [`Self::draw_mesh_tasks`], with `draw_count` group counts read
+    /// from `buffer` as `VkDrawMeshTasksIndirectCommandEXT`.
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`Self::draw_mesh_tasks`].
+    /// - `buffer` must be in the `INDIRECT` usage, and `offset` a multiple
+    ///   of 4.
+    pub unsafe fn draw_mesh_tasks_indirect(
+        &mut self,
+        buffer: &super::Buffer,
+        offset: wgt::BufferAddress,
+        draw_count: u32,
+    ) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "draw_mesh_tasks_indirect");
+        self.check_pipeline_bound(vk::PipelineBindPoint::GRAPHICS, "draw_mesh_tasks_indirect");
+        self.check_viewport_counts("draw_mesh_tasks_indirect");
+        self.flush_bind_groups();
+        let stride = size_of::<vk::DrawMeshTasksIndirectCommandEXT>() as u32;
+        let ext = self.mesh_shader_fns().clone();
+        self.record_indirect_draws(offset, draw_count, stride, |_, active, offset, count| {
+            unsafe { ext.cmd_draw_mesh_tasks_indirect(active, buffer.raw, offset, count, stride) };
+        });
+        self.count(|stats| &mut stats.draws);
+    }
+
+    /// Like [`Self::draw_mesh_tasks_indirect`], with the draw count read
+    /// from `count_buffer` and capped to `max_count`.
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`Self::draw_mesh_tasks_indirect`].
+    /// - `MULTI_DRAW_INDIRECT_COUNT` must be enabled.
+    /// - `count_buffer` must be in the `INDIRECT` usage, and
+    ///   `count_offset` a multiple of 4.
+    pub unsafe fn draw_mesh_tasks_indirect_count(
+        &mut self,
+        buffer: &super::Buffer,
+        offset: wgt::BufferAddress,
+        count_buffer: &super::Buffer,
+        count_offset: wgt::BufferAddress,
+        max_count: u32,
+    ) {
+        self.expect_active();
+        self.check_pass(
+            vk::PipelineBindPoint::GRAPHICS,
+            "draw_mesh_tasks_indirect_count",
+        );
+        self.check_pipeline_bound(
+            vk::PipelineBindPoint::GRAPHICS,
+            "draw_mesh_tasks_indirect_count",
+        );
+        self.check_viewport_counts("draw_mesh_tasks_indirect_count");
+        if let Err(err) = check_features(
+            self.device.features,
+            wgt::Features::MULTI_DRAW_INDIRECT_COUNT,
+        ) {
+            super::hal_usage_error(err.to_string());
+        }
+        if cfg!(debug_assertions) {
+            if let Err(message) = check_indirect_count_offsets(offset, count_offset) {
+                super::hal_usage_error(message);
+            }
+        }
+        self.flush_bind_groups();
+        let stride = size_of::<vk::DrawMeshTasksIndirectCommandEXT>() as u32;
+        unsafe {
+            self.mesh_shader_fns().cmd_draw_mesh_tasks_indirect_count(
+                self.active,
+                buffer.raw,
+                offset,
+                count_buffer.raw,
+                count_offset,
+                max_count,
+                stride,
+            )
+        };
+        self.count(|stats| &mut stats.draws);
+    }
+
+    /// Records `vkCmdPipelineBarrier2`, from `VK_KHR_synchronization2` or
+    /// Vulkan 1.3.
+    unsafe fn pipeline_barrier2(&self, info: &vk::DependencyInfo) {
+        match self.device.extension_fns.synchronization2 {
+            Some(super::ExtensionFn::Extension(ref ext)) => unsafe {
+                ext.cmd_pipeline_barrier2(self.active, info)
+            },
+            Some(super::ExtensionFn::Promoted) => unsafe {
+                self.device.raw.cmd_pipeline_barrier2(self.active, info)
+            },
+            None => panic!("`VK_KHR_synchronization2` is not enabled"),
+        }
+    }
+
+    fn mesh_shader_fns(&self) -> &ext::mesh_shader::Device {
+        self.device
+            .extension_fns
+            .mesh_shader
+            .as_ref()
+            .expect("`VK_EXT_mesh_shader` is not enabled")
+    }
+
+    fn fragment_shading_rate_fns(&self) -> &khr::fragment_shading_rate::Device {
+        self.device
+            .extension_fns
+            .fragment_shading_rate
+            .as_ref()
+            .expect("`VK_KHR_fragment_shading_rate` is not enabled")
+    }
+
+    fn extended_dynamic_state3_fns(&self) -> &ext::extended_dynamic_state3::Device {
+        self.device
+            .extension_fns
+            .extended_dynamic_state3
+            .as_ref()
+            .expect("`VK_EXT_extended_dynamic_state3` is not enabled")
+    }
+
+    /// Like [`crate::CommandEncoder::write_timestamp`], but writes the
+    /// timestamp once the previous commands reach `stage`, instead of once
+    /// they complete.
+    ///
+    /// The queue of the device supports graphics and compute, so all stages
+    /// are valid in any pass.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::CommandEncoder::write_timestamp`].
+    pub unsafe fn write_timestamp_at(
+        &mut self,
+        set: &super::QuerySet,
+        index: u32,
+        stage: super::TimestampStage,
+    ) {
+        self.expect_active();
+        self.reset_query_before_use(set.raw, index);
+        unsafe {
+            self.device.raw.cmd_write_timestamp(
+                self.active,
+                conv::map_timestamp_stage(stage),
+                set.raw,
+                index,
+            )
+        };
+    }
+
+    /// Resets ranges of queries spanning several query sets, such as a ring
+    /// of sets used across frames.
+    ///
+    /// Each range must stay within its set: a range continuing into the
+    /// next set has to be split into one entry per set.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::CommandEncoder::reset_queries`], for every entry.
+    pub unsafe fn reset_query_sets(&mut self, resets: &[(&super::QuerySet, Range<u32>)]) {
+        self.expect_active();
+        for (set, range) in resets {
+            unsafe { crate::CommandEncoder::reset_queries(self, set, range.clone()) };
+        }
+    }
+
+    /// Enables or disables resetting queries automatically before their
+    /// first use in each encoding, by [`crate::CommandEncoder::begin_query`]
+    /// and the timestamp writes.
+    ///
+    /// Queries can't be reset inside render passes, so those used there must
+    /// still be reset before the pass begins, with
+    /// [`crate::CommandEncoder::reset_queries`]. Queries are tracked per
+    /// encoding, so a query used by several command buffers is reset at its
+    /// first use in each of them.
+    pub fn set_auto_reset_queries(&mut self, enabled: bool) {
+        self.encoding.auto_reset_queries = enabled.then(Default::default);
+    }
+
+    /// Enables or disables checking, in debug builds, that copies read from
+    /// resources in a `COPY_SRC` usage and write to resources in a `COPY_DST`
+    /// usage.
+    ///
+    /// Only the usages set by barriers recorded in the current encoding are
+    /// known, so resources not transitioned yet, and textures last
+    /// transitioned in parts, aren't checked.
+    pub fn set_usage_checks(&mut self, enabled: bool) {
+        self.encoding.usage_tracker = enabled.then(Default::default);
+    }
+
+    /// Checks that `buffer` is in a `required` usage before `call`, if known.
+    fn check_buffer_usage(&self, buffer: &super::Buffer, required: crate::BufferUses, call: &str) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let Some(ref tracker) = self.encoding.usage_tracker else {
+            return;
+        };
+        if let Err(message) = check_tracked_usage(tracker.buffers.get(&buffer.raw), required) {
+            super::hal_usage_error(format!("`{call}`: buffer {message}"));
+        }
+    }
+
+    /// Checks that `texture` is in a `required` usage before `call`, if known.
+    fn check_texture_usage(
+        &self,
+        texture: &super::Texture,
+        required: crate::TextureUses,
+        call: &str,
+    ) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let Some(ref tracker) = self.encoding.usage_tracker else {
+            return;
+        };
+        if let Err(message) = check_tracked_usage(tracker.textures.get(&texture.raw), required) {
+            super::hal_usage_error(format!("`{call}`: texture {message}"));
+        }
+    }
+
+    /// Enables or disables the elision of texture barriers, enabled by
+    /// default.
+    ///
+    /// Barriers whose usage doesn't change are skipped or turned into memory
+    /// barriers, and barriers of adjacent subresources are merged. Disabling
+    /// this records each barrier as an image barrier, as requested, to find
+    /// out whether an elision hides a synchronization bug.
+    pub fn set_barrier_elision(&mut self, enabled: bool) {
+        self.barrier_elision = enabled;
+    }
+
+    /// Resets query `index` of `pool` if queries are reset automatically and
+    /// it wasn't reset in this encoding yet.
+    fn reset_query_before_use(&mut self, pool: vk::QueryPool, index: u32) {
+        if !self.encoding.needs_query_reset(pool, index) {
+            return;
+        }
+        if cfg!(debug_assertions) && self.active_pass == Some(vk::PipelineBindPoint::GRAPHICS) {
+            super::hal_usage_error(format!(
+                "query {index} is used in a render pass without being reset before it"
+            ));
+        }
+        unsafe {
+            self.device
+                .raw
+                .cmd_reset_query_pool(self.active, pool, index, 1)
+        };
+    }
+
+    /// Checks that a command buffer is being recorded, i.e. that the encoder
+    /// is between `begin_encoding` and `end_encoding` or `discard_encoding`.
+    fn expect_active(&self) {
+        if cfg!(debug_assertions) && self.active == vk::CommandBuffer::null() {
+            super::hal_usage_error(
+                "commands must be recorded between `begin_encoding` and `end_encoding`",
+            );
+        }
+    }
+
+    /// Checks that `call` is recorded inside a pass of the `bind_point` kind.
+    fn check_pass(&self, bind_point: vk::PipelineBindPoint, call: &str) {
+        if cfg!(debug_assertions) && self.active_pass != Some(bind_point) {
+            let kind = if bind_point == vk::PipelineBindPoint::COMPUTE {
+                "compute"
+            } else {
+                "render"
+            };
+            super::hal_usage_error(format!("`{call}` must be recorded inside a {kind} pass"));
+        }
+    }
+
+    /// Checks that a pipeline is bound at `bind_point` before `call`.
+    fn check_pipeline_bound(&self, bind_point: vk::PipelineBindPoint, call: &str) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let (bound, kind) = if bind_point == vk::PipelineBindPoint::COMPUTE {
+            (self.bound_compute_pipeline, "compute")
+        } else {
+            (self.bound_render_pipeline, "render")
+        };
+        if bound == vk::Pipeline::null() {
+            super::hal_usage_error(format!(
+                "`{call}` requires a {kind} pipeline bound with `set_{kind}_pipeline`"
+            ));
+        }
+    }
+
+    /// Checks that `call` is recorded outside of any render pass.
+    fn check_outside_render_pass(&self, call: &str) {
+        if cfg!(debug_assertions) && self.active_pass == Some(vk::PipelineBindPoint::GRAPHICS) {
+            super::hal_usage_error(format!(
+                "`{call}` must be recorded outside of a render pass"
+            ));
+        }
+    }
+
+    /// Increments the counter of `stats` picked by `counter`, in debug builds.
+    fn count(&mut self, counter: fn(&mut super::EncoderStats) -> &mut u32) {
+        if cfg!(debug_assertions) {
+            *counter(&mut self.stats) += 1;
+        }
+    }
+
+    /// Binds the bind groups recorded by `set_bind_group` since the last
+    /// flush, with a single `vkCmdBindDescriptorSets`.
+    fn flush_bind_groups(&mut self) {
+        let pending = &mut self.pending_bind_groups;
+        if pending.sets.is_empty() {
+            return;
+        }
+        unsafe {
+            self.device.raw.cmd_bind_descriptor_sets(
+                self.active,
+                pending.bind_point,
+                pending.layout,
+                pending.first_set,
+                &pending.sets,
+                &pending.dynamic_offsets,
+            )
+        };
+        pending.clear();
+    }
+
+    /// Forgets the bound pipelines, so the next ones are bound unconditionally.
+    fn reset_bound_pipelines(&mut self) {
+        self.bound_render_pipeline = vk::Pipeline::null();
+        self.bound_compute_pipeline = vk::Pipeline::null();
+        self.color_write_attachments = None;
+    }
+
+    /// Returns the `VK_KHR_draw_indirect_count` functions, which are loaded
+    /// if `MULTI_DRAW_INDIRECT_COUNT` is enabled.
+    fn draw_indirect_count_fns(&self) -> &khr::draw_indirect_count::Device {
+        self.device
+            .extension_fns
+            .draw_indirect_count
+            .as_ref()
+            .expect("Feature `MULTI_DRAW_INDIRECT_COUNT` not enabled")
+    }
+
+    /// Writes the timestamp at the beginning of a pass, if requested, and
+    /// remembers the one to write at its end.
+    ///
+    /// The beginning timestamp is written at the top of the pipe, so that it
+    /// doesn't wait for the commands before the pass to complete.
+    unsafe fn write_pass_begin_timestamp(
+        &mut self,
+        timestamp_writes: Option<&crate::PassTimestampWrites<'_, super::QuerySet>>,
+    ) {
+        let Some(timestamp_writes) = timestamp_writes else {
+            return;
+        };
+        if let Some(index) = timestamp_writes.beginning_of_pass_write_index {
+            unsafe {
+                self.write_timestamp_at(
+                    timestamp_writes.query_set,
+                    index,
+                    super::TimestampStage::TopOfPipe,
+                )
+            };
+        }
+        self.end_of_pass_timer_query = timestamp_writes
+            .end_of_pass_write_index
+            .map(|index| (timestamp_writes.query_set.raw, index));
+    }
+
+    fn write_pass_end_timestamp_if_requested(&mut self) {
+        if let Some((query_set, index)) = self.end_of_pass_timer_query.take() {
+            self.reset_query_before_use(query_set, index);
+            unsafe {
+                self.device.raw.cmd_write_timestamp(
+                    self.active,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    query_set,
+                    index,
+                );
+            }
+        }
+    }
+
+    /// Returns `true` if viewport depth ranges may go outside `0.0..1.0`.
+    pub fn supports_unrestricted_depth_range(&self) -> bool {
+        self.device.private_caps.depth_range_unrestricted
+    }
+
+    /// Returns the number of viewports and scissors that can be set at once.
+    ///
+    /// This is 1 unless the device supports `multiViewport`.
+    pub fn max_viewports(&self) -> u32 {
+        self.device.private_caps.max_viewports
+    }
+
+    /// Sets the viewports starting at slot `first`.
+    ///
+    /// Depth ranges are clamped to `0.0..1.0` unless the device supports
+    /// `VK_EXT_depth_range_unrestricted`, see
+    /// [`Self::supports_unrestricted_depth_range`].
+    ///
+    /// # Safety
+    ///
+    /// - `first + viewports.len()` must not exceed [`Self::max_viewports`].
+    pub unsafe fn set_viewports(
+        &mut self,
+        first: u32,
+        viewports: &[(crate::Rect<f32>, Range<f32>)],
+    ) {
+        self.expect_active();
+        self.check_viewport_slots(first, viewports.len());
+        let flip_y_requires_shift = self.device.private_caps.flip_y_requires_shift;
+        let clamp_depth = |depth: f32| {
+            if self.device.private_caps.depth_range_unrestricted {
+                depth
+            } else {
+                depth.clamp(0.0, 1.0)
+            }
+        };
+        let vk_viewports = viewports
+            .iter()
+            .map(|(rect, depth_range)| vk::Viewport {
+                x: rect.x,
+                y: if flip_y_requires_shift {
+                    rect.y + rect.h
+                } else {
+                    rect.y
+                },
+                width: rect.w,
+                height: -rect.h, // flip Y
+                min_depth: clamp_depth(depth_range.start),
+                max_depth: clamp_depth(depth_range.end),
+            })
+            .collect::<ArrayVec<_, { super::MAX_VIEWPORTS }>>();
+        unsafe {
+            self.device
+                .raw
+                .cmd_set_viewport(self.active, first, &vk_viewports)
+        };
+        self.viewport_count = self.viewport_count.max(first + viewports.len() as u32);
+    }
+
+    /// Sets the scissor rectangles starting at slot `first`.
+    ///
+    /// Draws must have as many scissor rectangles set as viewports, see
+    /// [`Self::set_viewports`].
+    ///
+    /// # Safety
+    ///
+    /// - `first + rects.len()` must not exceed [`Self::max_viewports`].
+    pub unsafe fn set_scissor_rects(&mut self, first: u32, rects: &[crate::Rect<u32>]) {
+        self.expect_active();
+        self.check_viewport_slots(first, rects.len());
+        let vk_scissors = rects
+            .iter()
+            .map(|rect| vk::Rect2D {
+                offset: vk::Offset2D {
+                    x: rect.x as i32,
+                    y: rect.y as i32,
+                },
+                extent: vk::Extent2D {
+                    width: rect.w,
+                    height: rect.h,
+                },
+            })
+            .collect::<ArrayVec<_, { super::MAX_VIEWPORTS }>>();
+        unsafe {
+            self.device
+                .raw
+                .cmd_set_scissor(self.active, first, &vk_scissors)
+        };
+        self.scissor_count = self.scissor_count.max(first + rects.len() as u32);
+    }
+
+    /// Checks that as many scissor rectangles as viewports are set before
+    /// `call`.
+    fn check_viewport_counts(&self, call: &str) {
+        if cfg!(debug_assertions) {
+            if let Err(message) = check_scissor_count(self.viewport_count, self.scissor_count) {
+                super::hal_usage_error(format!("`{call}`: {message}"));
+            }
+        }
+    }
+
+    fn check_viewport_slots(&self, first: u32, count: usize) {
+        let end = first as usize + count;
+        if cfg!(debug_assertions) && end > self.max_viewports() as usize {
+            super::hal_usage_error(format!(
+                "viewport slots {first}..{end} exceed the device limit of {}",
+                self.max_viewports()
+            ));
+        }
+    }
+
+    /// Zeroes `range` of `buffer`, or the whole buffer if `range` is `None`.
+    ///
+    /// The whole-buffer form doesn't need the size of `buffer`, which makes
+    /// it usable with buffers created by [`super::Device::buffer_from_raw`].
+    ///
+    /// # Safety
+    ///
+    /// - The cleared part of `buffer` must be in the `COPY_DST` state.
+    /// - `range`, if given, must follow the same rules as
+    ///   [`crate::CommandEncoder::clear_buffer`].
+    pub unsafe fn zero_buffer(
+        &mut self,
+        buffer: &super::Buffer,
+        range: Option<crate::MemoryRange>,
+    ) {
+        self.expect_active();
+        match range {
+            Some(range) => unsafe { crate::CommandEncoder::clear_buffer(self, buffer, range) },
+            None => unsafe {
+                self.device
+                    .raw
+                    .cmd_fill_buffer(self.active, buffer.raw, 0, vk::WHOLE_SIZE, 0)
+            },
+        }
+    }
+
+    /// Sets every 32-bit word of `range` of `buffer` to `value`, e.g. a
+    /// sentinel or the bits of a float, in the native byte order.
+    ///
+    /// Unlike [`Self::fill_buffer_exact`], `value` isn't a repeated byte.
+    ///
+    /// # Safety
+    ///
+    /// - `range` of `buffer` must be in the `COPY_DST` state.
+    /// - Must not be called inside a render pass.
+    pub unsafe fn fill_buffer_u32(
+        &mut self,
+        buffer: &super::Buffer,
+        range: crate::MemoryRange,
+        value: u32,
+    ) {
+        self.expect_active();
+        self.check_outside_render_pass("fill_buffer_u32");
+        // `vkCmdFillBuffer` requires both the offset and the size to be multiples of 4.
+        if cfg!(debug_assertions) && (range.start % 4 != 0 || range.end % 4 != 0) {
+            super::hal_usage_error(format!(
+                "fill_buffer_u32 range {range:?} is not aligned to 4"
+            ));
+        }
+        if range.start == range.end {
+            return;
+        }
+        unsafe {
+            self.device.raw.cmd_fill_buffer(
+                self.active,
+                buffer.raw,
+                range.start,
+                range.end - range.start,
+                value,
+            )
+        };
+    }
+
+    /// Writes `data` to `buffer` at `offset`, inline in the command buffer.
+    ///
+    /// Meant for small, frequently changing data, where a staging buffer
+    /// would cost more than the copy.
+    ///
+    /// # Safety
+    ///
+    /// - The written part of `buffer` must be in the `COPY_DST` state.
+    /// - Must not be called inside a render pass.
+    /// - `offset + data.len()` must not exceed the size of `buffer`.
+    pub unsafe fn update_buffer(
+        &mut self,
+        buffer: &super::Buffer,
+        offset: wgt::BufferAddress,
+        data: &[u8],
+    ) {
+        self.expect_active();
+        self.check_outside_render_pass("update_buffer");
+        if cfg!(debug_assertions) && offset % 4 != 0 {
+            super::hal_usage_error(format!("update offset {offset} is not a multiple of 4"));
+        }
+        if cfg!(debug_assertions)
+            && (data.is_empty() || data.len() % 4 != 0 || data.len() > MAX_UPDATE_BUFFER_SIZE)
+        {
+            super::hal_usage_error(format!(
+                "update size {} is not a non-zero multiple of 4 up to {MAX_UPDATE_BUFFER_SIZE}",
+                data.len()
+            ));
+        }
+        unsafe {
+            self.device
+                .raw
+                .cmd_update_buffer(self.active, buffer.raw, offset, data)
+        };
+    }
+
+    /// Like [`crate::CommandEncoder::copy_buffer_to_buffer`], splitting
+    /// regions larger than `max_chunk_size` bytes into several regions of at
+    /// most that size, for drivers failing on very large copies.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::CommandEncoder::copy_buffer_to_buffer`].
+    pub unsafe fn copy_buffer_to_buffer_in_chunks<T>(
+        &mut self,
+        src: &super::Buffer,
+        dst: &super::Buffer,
+        regions: T,
+        max_chunk_size: wgt::BufferSize,
+    ) where
+        T: Iterator<Item = crate::BufferCopy>,
+    {
+        let chunks = regions.flat_map(|r| split_buffer_copy(r, max_chunk_size));
+        unsafe { crate::CommandEncoder::copy_buffer_to_buffer(self, src, dst, chunks) };
+    }
+
+    /// Sets every byte of `range` of `buffer` to `value`, for any alignment
+    /// of `range`.
+    ///
+    /// `vkCmdFillBuffer` and `vkCmdUpdateBuffer` only write whole words, so
+    /// the aligned body is filled directly, and the unaligned head and tail
+    /// are copied from `scratch`, which is filled with `value` first.
+    ///
+    /// # Safety
+    ///
+    /// - `range` of `buffer` must be in the `COPY_DST` state.
+    /// - `scratch` must be at least 4 bytes, in the `COPY_DST` state, and is
+    ///   left in the `COPY_SRC` state if `range` isn't aligned.
+    /// - Must not be called inside a render pass.
+    pub unsafe fn fill_buffer_exact(
+        &mut self,
+        buffer: &super::Buffer,
+        range: crate::MemoryRange,
+        value: u8,
+        scratch: &super::Buffer,
+    ) {
+        self.expect_active();
+        self.check_outside_render_pass("fill_buffer_exact");
+        let pattern = u32::from_ne_bytes([value; 4]);
+        let [head, body, tail] = split_fill_range(range);
+        if !body.is_empty() {
+            unsafe {
+                self.device.raw.cmd_fill_buffer(
+                    self.active,
+                    buffer.raw,
+                    body.start,
+                    body.end - body.start,
+                    pattern,
+                )
+            };
+        }
+
+        let vk_regions = [head, tail]
+            .into_iter()
+            .filter(|edge| !edge.is_empty())
+            .map(|edge| vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: edge.start,
+                size: edge.end - edge.start,
+            })
+            .collect::<ArrayVec<_, 2>>();
+        if vk_regions.is_empty() {
+            return;
+        }
+        unsafe {
+            self.device
+                .raw
+                .cmd_fill_buffer(self.active, scratch.raw, 0, 4, pattern);
+            self.transition_buffer(
+                scratch,
+                crate::BufferUses::COPY_DST..crate::BufferUses::COPY_SRC,
+            );
+            self.device
+                .raw
+                .cmd_copy_buffer(self.active, scratch.raw, buffer.raw, &vk_regions)
+        };
+        self.count(|stats| &mut stats.copies);
+    }
+
+    /// Makes all memory accesses of `usage.start` visible to subsequent
+    /// accesses of `usage.end`, regardless of the resource.
+    ///
+    /// This is coarser than [`crate::CommandEncoder::transition_buffers`], and
+    /// may synchronize more work than needed, but spares listing every buffer,
+    /// for instance after a compute pass writing indirect arguments for many
+    /// consumers. It doesn't change the layout of any texture.
+    ///
+    /// # Safety
+    ///
+    /// - Must not be called inside a render pass.
+    pub unsafe fn memory_barrier(&mut self, usage: Range<crate::BufferUses>) {
+        self.expect_active();
+        let (src_stage, src_access) = conv::map_buffer_usage_to_barrier(usage.start);
+        let (dst_stage, dst_access) = conv::map_buffer_usage_to_barrier(usage.end);
+        let vk_barrier = vk::MemoryBarrier::default()
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access);
+        unsafe {
+            self.device.raw.cmd_pipeline_barrier(
+                self.active,
+                //Note: this is done so that we never end up with empty stage flags
+                vk::PipelineStageFlags::TOP_OF_PIPE | src_stage,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE | dst_stage,
+                vk::DependencyFlags::empty(),
+                &[vk_barrier],
+                &[],
+                &[],
+            )
+        };
+        self.count(|stats| &mut stats.barriers);
+    }
+
+    /// Waits for all previous commands and makes all of their writes
+    /// visible to all subsequent commands.
+    ///
+    /// This is a diagnostic for suspected missing barriers: if a race goes
+    /// away with this inserted, a barrier is missing around that point.
+    ///
+    /// **Never use this in shipping code paths**: it serializes all GPU work
+    /// and flushes every cache. It only exists in debug builds for that
+    /// reason.
+    ///
+    /// # Safety
+    ///
+    /// - Must not be called inside a render pass.
+    #[cfg(debug_assertions)]
+    pub unsafe fn debug_full_barrier(&mut self) {
+        self.expect_active();
+        self.check_outside_render_pass("debug_full_barrier");
+        let all_access = vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE;
+        let vk_barrier = vk::MemoryBarrier::default()
+            .src_access_mask(all_access)
+            .dst_access_mask(all_access);
+        unsafe {
+            self.device.raw.cmd_pipeline_barrier(
+                self.active,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                &[vk_barrier],
+                &[],
+                &[],
+            )
+        };
+        self.count(|stats| &mut stats.barriers);
+    }
+
+    /// Sets the width of rasterized lines, for pipelines drawing lines.
+    ///
+    /// The width is clamped to the `lineWidthRange` of the device, and is
+    /// always 1.0 if the device doesn't support `wideLines`. It applies to
+    /// the bound pipeline and those bound afterwards in this command buffer.
+    ///
+    /// # Safety
+    ///
+    /// - The encoder must be recording commands.
+    pub unsafe fn set_line_width(&mut self, width: f32) {
+        self.expect_active();
+        let clamped = match self.device.private_caps.line_width_range {
+            Some([min, max]) => width.clamp(min, max),
+            None => 1.0,
+        };
+        if clamped != width {
+            log::warn!("Line width {width} is not supported, using {clamped}");
+        }
+        self.encoding.line_width = clamped;
+        if self.active_pass == Some(vk::PipelineBindPoint::GRAPHICS) {
+            unsafe { self.device.raw.cmd_set_line_width(self.active, clamped) };
+        }
+    }
+
+    /// Overrides the depth bias of the bound render pipeline, until another
+    /// one is bound.
+    ///
+    /// Only pipelines created with a depth bias apply it. `clamp` is ignored
+    /// if the device doesn't support `depthBiasClamp`.
+    ///
+    /// # Safety
+    ///
+    /// - Must be called inside a render pass.
+    pub unsafe fn set_depth_bias(&mut self, constant: f32, clamp: f32, slope: f32) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "set_depth_bias");
+        let clamp = if self.device.private_caps.depth_bias_clamp {
+            clamp
+        } else {
+            0.0
+        };
+        unsafe {
+            self.device
+                .raw
+                .cmd_set_depth_bias(self.active, constant, clamp, slope)
+        };
+    }
+
+    /// Sets separate stencil reference values for front-facing and
+    /// back-facing primitives.
+    ///
+    /// The stencil reference is dynamic in all render pipelines, so this
+    /// applies to the bound pipeline and those bound afterwards.
+    ///
+    /// # Safety
+    ///
+    /// - Must be called inside a render pass.
+    pub unsafe fn set_stencil_reference_faces(&mut self, front: u32, back: u32) {
+        self.expect_active();
+        self.check_pass(
+            vk::PipelineBindPoint::GRAPHICS,
+            "set_stencil_reference_faces",
+        );
+        let faces: &[(vk::StencilFaceFlags, u32)] = if front == back {
+            &[(vk::StencilFaceFlags::FRONT_AND_BACK, front)]
+        } else {
+            &[
+                (vk::StencilFaceFlags::FRONT, front),
+                (vk::StencilFaceFlags::BACK, back),
+            ]
+        };
+        for &(face_mask, value) in faces {
+            unsafe {
+                self.device
+                    .raw
+                    .cmd_set_stencil_reference(self.active, face_mask, value)
+            };
+        }
+    }
+
+    /// Returns `true` if [`Self::set_sample_locations`] supports
+    /// `sample_count` samples per pixel.
+    pub fn supports_sample_locations(&self, sample_count: u32) -> bool {
+        self.device
+            .private_caps
+            .sample_location_sample_counts
+            .contains(vk::SampleCountFlags::from_raw(sample_count))
+    }
+
+    /// Sets custom sample locations for render pipelines with
+    /// `info.sample_count` samples.
+    ///
+    /// Render pipelines created with
+    /// [`super::RenderPipelineOptions::custom_sample_locations`] use the
+    /// standard sample locations until this is called. The locations apply to
+    /// the bound pipeline and those bound afterwards in this command buffer.
+    ///
+    /// # Safety
+    ///
+    /// - [`Self::supports_sample_locations`] must be `true` for
+    ///   `info.sample_count`.
+    /// - `info.grid_size` must not exceed `maxSampleLocationGridSize` for
+    ///   that sample count.
+    pub unsafe fn set_sample_locations(&mut self, info: &super::SampleLocationsInfo) {
+        self.expect_active();
+        if cfg!(debug_assertions) && !self.supports_sample_locations(info.sample_count) {
+            super::hal_usage_error(format!(
+                "custom sample locations are not supported with {} samples",
+                info.sample_count
+            ));
+        }
+        let [width, height] = info.grid_size;
+        let expected = (info.sample_count * width * height) as usize;
+        if cfg!(debug_assertions) && info.locations.len() != expected {
+            super::hal_usage_error(format!(
+                "{} sample locations given for {width}x{height} pixels of {} samples",
+                info.locations.len(),
+                info.sample_count
+            ));
+        }
+        self.encoding.sample_locations = Some(info.clone());
+        if self.active_pass == Some(vk::PipelineBindPoint::GRAPHICS) {
+            unsafe { self.apply_sample_locations(info.sample_count) };
+        }
+    }
+
+    /// Sets the sample locations of `set_sample_locations` if they are for
+    /// `sample_count` samples, or the standard ones otherwise.
+    unsafe fn apply_sample_locations(&self, sample_count: u32) {
+        let (grid_size, locations) = match self.encoding.sample_locations {
+            Some(ref info) if info.sample_count == sample_count => {
+                (info.grid_size, info.locations.as_slice())
+            }
+            _ => ([1, 1], conv::standard_sample_locations(sample_count)),
+        };
+        let vk_locations = locations
+            .iter()
+            .map(|&[x, y]| vk::SampleLocationEXT { x, y })
+            .collect::<smallvec::SmallVec<[_; 16]>>();
+        let vk_info = vk::SampleLocationsInfoEXT::default()
+            .sample_locations_per_pixel(vk::SampleCountFlags::from_raw(sample_count))
+            .sample_location_grid_size(vk::Extent2D {
+                width: grid_size[0],
+                height: grid_size[1],
+            })
+            .sample_locations(&vk_locations);
+        unsafe {
+            self.device
+                .extension_fns
+                .sample_locations
+                .as_ref()
+                .expect("`VK_EXT_sample_locations` is not enabled")
+                .cmd_set_sample_locations(self.active, &vk_info)
+        };
+    }
+
+    /// Returns `true` if [`Self::set_depth_bounds`] is supported.
+    pub fn supports_depth_bounds(&self) -> bool {
+        self.device.private_caps.depth_bounds
+    }
+
+    /// Discards fragments whose existing depth in the depth-stencil
+    /// attachment is outside of `min..=max`.
+    ///
+    /// Only render pipelines created with
+    /// [`super::RenderPipelineOptions::depth_bounds`] test the depth bounds,
+    /// which cover all depths until this is called. The bounds apply to the
+    /// bound pipeline and those bound afterwards in this command buffer.
+    ///
+    /// # Safety
+    ///
+    /// - [`Self::supports_depth_bounds`] must be `true`.
+    /// - The encoder must be recording commands.
+    pub unsafe fn set_depth_bounds(&mut self, min: f32, max: f32) {
+        self.expect_active();
+        if !self.supports_depth_bounds() {
+            super::hal_usage_error("the `depthBounds` feature is not supported");
+        }
+        if cfg!(debug_assertions) {
+            let [full_min, full_max] = self.full_depth_bounds();
+            if !(full_min <= min && min <= max && max <= full_max) {
+                super::hal_usage_error(format!(
+                    "depth bounds {min}..={max} are not an ordered range within {full_min}..={full_max}"
+                ));
+            }
+        }
+        self.encoding.depth_bounds = Some([min, max]);
+        if self.active_pass == Some(vk::PipelineBindPoint::GRAPHICS) {
+            unsafe { self.device.raw.cmd_set_depth_bounds(self.active, min, max) };
+        }
+    }
+
+    /// Returns the depth bounds covering all depths, depending on
+    /// `VK_EXT_depth_range_unrestricted`.
+    fn full_depth_bounds(&self) -> [f32; 2] {
+        if self.device.private_caps.depth_range_unrestricted {
+            [f32::MIN, f32::MAX]
+        } else {
+            [0.0, 1.0]
+        }
+    }
+
+    /// Returns `true` if [`Self::set_depth_clamp_enabled`] is supported.
+    pub fn supports_dynamic_depth_clamp(&self) -> bool {
+        self.device.private_caps.dynamic_depth_clamp
+    }
+
+    /// Overrides whether the bound render pipeline clamps depth instead of
+    /// clipping it, until another one is bound.
+    ///
+    /// Pipelines start out with their `unclipped_depth` setting.
+    ///
+    /// # Safety
+    ///
+    /// - Must be called inside a render pass, after binding a pipeline.
+    pub unsafe fn set_depth_clamp_enabled(&mut self, enabled: bool) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "set_depth_clamp_enabled");
+        if !self.supports_dynamic_depth_clamp() {
+            super::hal_usage_error(
+                "dynamic depth clamp requires `depthClamp` and `VK_EXT_extended_dynamic_state3`",
+            );
+        }
+        unsafe {
+            self.extended_dynamic_state3_fns()
+                .cmd_set_depth_clamp_enable(self.active, enabled)
+        };
+    }
+
+    /// Returns `true` if [`Self::set_color_write_enable`] is supported.
+    pub fn supports_color_write_enable(&self) -> bool {
+        self.device.private_caps.color_write_enable
+    }
+
+    /// Enables or disables writes to each color attachment of the bound
+    /// render pipeline, until another one is bound, which enables them all.
+    ///
+    /// Disabled attachments are left untouched, as if their write mask was
+    /// empty, so that one pipeline can write to any subset of them.
+    ///
+    /// # Safety
+    ///
+    /// - Must be called inside a render pass, after binding a pipeline
+    ///   created with [`super::RenderPipelineOptions::dynamic_color_write_enable`].
+    pub unsafe fn set_color_write_enable(&mut self, enables: &[bool]) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "set_color_write_enable");
+        if !self.supports_color_write_enable() {
+            super::hal_usage_error("color write enables require `VK_EXT_color_write_enable`");
+        }
+        if cfg!(debug_assertions) && self.color_write_attachments != Some(enables.len() as u32) {
+            super::hal_usage_error(format!(
+                "{} color write enables set for a pipeline with {:?} color attachments",
+                enables.len(),
+                self.color_write_attachments
+            ));
+        }
+        let vk_enables = enables
+            .iter()
+            .map(|&enable| enable as vk::Bool32)
+            .collect::<ArrayVec<_, { crate::MAX_COLOR_ATTACHMENTS }>>();
+        unsafe {
+            self.color_write_enable_fns()
+                .cmd_set_color_write_enable(self.active, &vk_enables)
+        };
+    }
+
+    fn color_write_enable_fns(&self) -> &ext::color_write_enable::Device {
+        self.device
+            .extension_fns
+            .color_write_enable
+            .as_ref()
+            .expect("`VK_EXT_color_write_enable` is not enabled")
+    }
+
+    /// Returns `true` if [`Self::set_vertex_input`] is supported.
+    pub fn supports_vertex_input_dynamic_state(&self) -> bool {
+        self.device.private_caps.vertex_input_dynamic_state
+    }
+
+    /// Overrides the vertex buffer layouts of the bound render pipeline,
+    /// until another one is bound, with buffer `i` at vertex buffer slot `i`.
+    ///
+    /// This lets one pipeline read vertices of different formats, as long as
+    /// the shader inputs match the attributes.
+    ///
+    /// # Safety
+    ///
+    /// - Must be called inside a render pass, after binding a pipeline
+    ///   created with [`super::RenderPipelineOptions::dynamic_vertex_input`].
+    /// - Every input of the vertex shader must be provided by an attribute.
+    pub unsafe fn set_vertex_input(&mut self, vertex_buffers: &[crate::VertexBufferLayout<'_>]) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "set_vertex_input");
+        self.check_pipeline_bound(vk::PipelineBindPoint::GRAPHICS, "set_vertex_input");
+        if !self.supports_vertex_input_dynamic_state() {
+            super::hal_usage_error(
+                "dynamic vertex input requires `VK_EXT_vertex_input_dynamic_state`",
+            );
+        }
+        let input = conv::map_vertex_input(vertex_buffers);
+        unsafe { self.apply_vertex_input(&input) };
+    }
+
+    unsafe fn apply_vertex_input(&self, input: &super::VertexInput) {
+        unsafe {
+            self.vertex_input_dynamic_state_fns().cmd_set_vertex_input(
+                self.active,
+                &input.bindings,
+                &input.attributes,
+            )
+        };
+    }
+
+    fn vertex_input_dynamic_state_fns(&self) -> &ext::vertex_input_dynamic_state::Device {
+        self.device
+            .extension_fns
+            .vertex_input_dynamic_state
+            .as_ref()
+            .expect("`VK_EXT_vertex_input_dynamic_state` is not enabled")
+    }
+
+    /// Returns `true` if [`Self::set_primitive_topology`],
+    /// [`Self::set_cull_mode`] and [`Self::set_front_face`] are supported.
+    pub fn supports_extended_dynamic_state(&self) -> bool {
+        self.device.private_caps.extended_dynamic_state
+    }
+
+    /// Overrides the primitive topology of the bound render pipeline, until
+    /// another one is bound.
+    ///
+    /// Without `dynamicPrimitiveTopologyUnrestricted`, `topology` must be of
+    /// the same class as the topology of the pipeline, e.g. a triangle list
+    /// and a triangle strip.
+    ///
+    /// # Safety
+    ///
+    /// - Must be called inside a render pass, after binding a pipeline.
+    pub unsafe fn set_primitive_topology(&mut self, topology: wgt::PrimitiveTopology) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "set_primitive_topology");
+        if !self.supports_extended_dynamic_state() {
+            super::hal_usage_error(
+                "dynamic primitive topology requires `VK_EXT_extended_dynamic_state`",
+            );
+        }
+        unsafe { self.cmd_set_primitive_topology(conv::map_topology(topology)) };
+    }
+
+    /// Overrides which faces the bound render pipeline culls, until another
+    /// one is bound.
+    ///
+    /// # Safety
+    ///
+    /// - Must be called inside a render pass, after binding a pipeline.
+    /// - If the pipeline has a read-only depth-stencil attachment because of
+    ///   its cull mode, the faces whose stencil operations write must stay
+    ///   culled.
+    pub unsafe fn set_cull_mode(&mut self, mode: Option<wgt::Face>) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "set_cull_mode");
+        if !self.supports_extended_dynamic_state() {
+            super::hal_usage_error("dynamic cull mode requires `VK_EXT_extended_dynamic_state`");
+        }
+        let cull_mode = mode.map_or(vk::CullModeFlags::NONE, conv::map_cull_face);
+        unsafe { self.cmd_set_cull_mode(cull_mode) };
+    }
+
+    /// Overrides the winding of front faces of the bound render pipeline,
+    /// until another one is bound, e.g. to render mirrored geometry.
+    ///
+    /// # Safety
+    ///
+    /// - Must be called inside a render pass, after binding a pipeline.
+    pub unsafe fn set_front_face(&mut self, front_face: wgt::FrontFace) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "set_front_face");
+        if !self.supports_extended_dynamic_state() {
+            super::hal_usage_error("dynamic front face requires `VK_EXT_extended_dynamic_state`");
+        }
+        unsafe { self.cmd_set_front_face(conv::map_front_face(front_face)) };
+    }
+
+    unsafe fn cmd_set_cull_mode(&self, cull_mode: vk::CullModeFlags) {
+        match self.device.extension_fns.extended_dynamic_state {
+            Some(super::ExtensionFn::Extension(ref ext)) => unsafe {
+                ext.cmd_set_cull_mode(self.active, cull_mode)
+            },
+            Some(super::ExtensionFn::Promoted) => unsafe {
+                self.device.raw.cmd_set_cull_mode(self.active, cull_mode)
+            },
+            None => panic!("`VK_EXT_extended_dynamic_state` is not enabled"),
+        }
+    }
+
+    unsafe fn cmd_set_front_face(&self, front_face: vk::FrontFace) {
+        match self.device.extension_fns.extended_dynamic_state {
+            Some(super::ExtensionFn::Extension(ref ext)) => unsafe {
+                ext.cmd_set_front_face(self.active, front_face)
+            },
+            Some(super::ExtensionFn::Promoted) => unsafe {
+                self.device.raw.cmd_set_front_face(self.active, front_face)
+            },
+            None => panic!("`VK_EXT_extended_dynamic_state` is not enabled"),
+        }
+    }
+
+    unsafe fn cmd_set_primitive_topology(&self, topology: vk::PrimitiveTopology) {
+        match self.device.extension_fns.extended_dynamic_state {
+            Some(super::ExtensionFn::Extension(ref ext)) => unsafe {
+                ext.cmd_set_primitive_topology(self.active, topology)
+            },
+            Some(super::ExtensionFn::Promoted) => unsafe {
+                self.device
+                    .raw
+                    .cmd_set_primitive_topology(self.active, topology)
+            },
+            None => panic!("`VK_EXT_extended_dynamic_state` is not enabled"),
+        }
+    }
+
+    /// Pushes the resources of `desc` as bind group `index` of `layout`,
+    /// without allocating a descriptor set.
+    ///
+    /// Like [`crate::CommandEncoder::set_bind_group`], this applies to the
+    /// bind point of the current pass.
+    ///
+    /// # Safety
+    ///
+    /// - `desc.layout` must have been created with
+    ///   [`super::Device::create_push_descriptor_bind_group_layout`], and be
+    ///   the layout of group `index` of `layout`.
+    /// - The resources must stay alive until the command buffer completes.
+    pub unsafe fn push_bind_group(
+        &mut self,
+        layout: &super::PipelineLayout,
+        index: u32,
+        desc: &crate::BindGroupDescriptor<
+            super::BindGroupLayout,
+            super::Buffer,
+            super::Sampler,
+            super::TextureView,
+            super::AccelerationStructure,
+        >,
+    ) {
+        self.expect_active();
+        // Keep the order of binds, in case `index` was just set too.
+        self.flush_bind_groups();
+        let Some(push_descriptor) = self.device.extension_fns.push_descriptor.as_ref() else {
+            super::hal_usage_error("VK_KHR_push_descriptor is not enabled");
+        };
+        // The destination set of the writes is ignored when pushing.
+        super::device::with_descriptor_writes(
+            desc,
+            vk::DescriptorSet::null(),
+            0,
+            |writes| unsafe {
+                push_descriptor.cmd_push_descriptor_set(
+                    self.active,
+                    self.bind_point,
+                    layout.raw,
+                    index,
+                    writes,
+                )
+            },
+        );
+    }
+
+    /// Frees all but `keep` buffers from each of the `free` pools.
+    ///
+    /// [`reset_all`] only ever grows the pools, so an encoder that once
+    /// needed many command buffers holds on to them until it's destroyed.
+    /// Call this once the working set has shrunk to hand the surplus back to
+    /// the Vulkan pool.
+    ///
+    /// The `active` buffer and the `discarded` ones are left alone; they
+    /// only become free again at the next [`reset_all`].
+    ///
+    /// [`reset_all`]: crate::CommandEncoder::reset_all
+    pub fn trim(&mut self, keep: usize) {
+        for free in [&mut self.free, &mut self.free_secondary] {
+            if free.len() > keep {
+                unsafe {
+                    self.device
+                        .raw
+                        .free_command_buffers(self.raw, &free[keep..])
+                };
+                free.truncate(keep);
+            }
+        }
+    }
+
+    /// Returns the number of free command buffers, of both levels, ready
+    /// to be used by the next encodings.
+    pub fn free_count(&self) -> usize {
+        self.free.len() + self.free_secondary.len()
+    }
+
+    /// Returns the number of discarded command buffers, of both levels,
+    /// waiting for the next [`reset_all`] to be freed.
+    ///
+    /// [`reset_all`]: crate::CommandEncoder::reset_all
+    pub fn discarded_count(&self) -> usize {
+        self.discarded.len() + self.discarded_secondary.len()
+    }
+
+    /// The first half of [`reset_all`]: returns `cmd_bufs` and the
+    /// discarded command buffers to the free lists, without resetting the
+    /// command pool.
+    ///
+    /// # Safety
+    ///
+    /// - Same as [`reset_all`].
+    /// - The recycled buffers are not in the initial state until the pool is
+    ///   reset, so [`Self::reset_pool`] must be called before the next
+    ///   encoding begins.
+    ///
+    /// [`reset_all`]: crate::CommandEncoder::reset_all
+    pub unsafe fn recycle<I>(&mut self, cmd_bufs: I)
+    where
+        I: Iterator<Item = super::CommandBuffer>,
+    {
+        self.temp.clear();
+        for cmd_buf in cmd_bufs {
+            if cmd_buf.external {
+                // Recycled by the caller of `begin_encoding_with`.
+            } else if cmd_buf.secondary {
+                self.free_secondary.push(cmd_buf.raw);
+            } else {
+                self.free.push(cmd_buf.raw);
+            }
+        }
+        self.free.append(&mut self.discarded);
+        self.free_secondary.append(&mut self.discarded_secondary);
+    }
+
+    /// The second half of [`reset_all`]: resets the command pool, moving all
+    /// of its command buffers back to the initial state.
+    ///
+    /// # Safety
+    ///
+    /// - None of the command buffers allocated by this encoder may be
+    ///   recording or pending execution.
+    ///
+    /// [`reset_all`]: crate::CommandEncoder::reset_all
+    pub unsafe fn reset_pool(&mut self) {
+        let _ = unsafe {
+            self.device
+                .raw
+                .reset_command_pool(self.raw, vk::CommandPoolResetFlags::default())
+        };
+    }
+
+    /// Like [`crate::CommandEncoder::copy_query_results`], with `mode`
+    /// choosing whether to wait for the queries to finish.
+    ///
+    /// With [`QueryResultMode::Partial`] and
+    /// [`QueryResultMode::Availability`], every result is followed by its
+    /// availability, so `stride` must be at least
+    /// [`QueryResultKind::size`] plus 8 bytes.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::CommandEncoder::copy_query_results`].
+    ///
+    /// [`QueryResultMode::Partial`]: super::QueryResultMode::Partial
+    /// [`QueryResultMode::Availability`]: super::QueryResultMode::Availability
+    /// [`QueryResultKind::size`]: super::QueryResultKind::size
+    pub unsafe fn copy_query_results_with(
+        &mut self,
+        set: &super::QuerySet,
+        range: Range<u32>,
+        buffer: &super::Buffer,
+        offset: wgt::BufferAddress,
+        stride: wgt::BufferSize,
+        mode: super::QueryResultMode,
+    ) {
+        self.expect_active();
+        set.check_range(&range);
+        if mode != super::QueryResultMode::Wait
+            && cfg!(debug_assertions)
+            && stride.get() < set.result_kind().size() + 8
+        {
+            super::hal_usage_error(format!(
+                "stride {} leaves no room for the availability of the results",
+                stride
+            ));
+        }
+        let flags = match mode {
+            super::QueryResultMode::Wait => {
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT
+            }
+            super::QueryResultMode::Partial => {
+                if cfg!(debug_assertions) && set.result_kind() == super::QueryResultKind::Timestamp
+                {
+                    super::hal_usage_error("partial results are not valid for timestamp queries");
+                }
+                vk::QueryResultFlags::TYPE_64
+                    | vk::QueryResultFlags::PARTIAL
+                    | vk::QueryResultFlags::WITH_AVAILABILITY
+            }
+            super::QueryResultMode::Availability => {
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY
+            }
+        };
+        unsafe {
+            self.device.raw.cmd_copy_query_pool_results(
+                self.active,
+                set.raw,
+                range.start,
+                range.end - range.start,
+                buffer.raw,
+                offset,
+                stride.get(),
+                flags,
+            )
+        };
+    }
+
+    /// Begins a debug marker labeled `label`, ended when the returned guard
+    /// is dropped.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::CommandEncoder::begin_debug_marker`]. The guard must
+    /// be dropped before the encoding or pass it was created in ends.
+    pub unsafe fn debug_marker_scope(&mut self, label: &str) -> super::DebugMarkerGuard<'_> {
+        unsafe { crate::CommandEncoder::begin_debug_marker(self, label) };
+        super::DebugMarkerGuard { encoder: self }
+    }
+
+    /// Signals `event` once the previous commands reach `stage`.
+    ///
+    /// Paired with [`Self::wait_events`], this splits a barrier in two, so
+    /// that commands recorded in between can overlap with it.
+    ///
+    /// # Safety
+    ///
+    /// - Must not be called inside a render pass.
+    /// - `stage` must only contain stages supported by the queue.
+    pub unsafe fn set_event(&mut self, event: &super::Event, stage: vk::PipelineStageFlags) {
+        self.expect_active();
+        self.check_outside_render_pass("set_event");
+        unsafe { self.device.raw.cmd_set_event(self.active, event.raw, stage) };
+    }
+
+    /// Unsignals `event` once the previous commands reach `stage`.
+    ///
+    /// # Safety
+    ///
+    /// - Must not be called inside a render pass.
+    /// - No [`Self::wait_events`] on `event` may be pending.
+    pub unsafe fn reset_event(&mut self, event: &super::Event, stage: vk::PipelineStageFlags) {
+        self.expect_active();
+        self.check_outside_render_pass("reset_event");
+        unsafe {
+            self.device
+                .raw
+                .cmd_reset_event(self.active, event.raw, stage)
+        };
+    }
+
+    /// Waits for `events` to be signaled, then applies the barriers.
+    ///
+    /// The barriers are mapped like in [`crate::CommandEncoder::transition_buffers`]
+    /// and [`crate::CommandEncoder::transition_textures`], except that the
+    /// source stages are `src_stages`.
+    ///
+    /// # Safety
+    ///
+    /// - Must not be called inside a render pass.
+    /// - `src_stages` must be the union of the stages `events` were set at.
+    /// - Each of `events` must be set by an earlier [`Self::set_event`]
+    ///   recorded on the same queue.
+    pub unsafe fn wait_events<'a, B, T>(
+        &mut self,
+        events: &[&super::Event],
+        src_stages: vk::PipelineStageFlags,
+        buffer_barriers: B,
+        texture_barriers: T,
+    ) where
+        B: Iterator<Item = crate::BufferBarrier<'a, super::Buffer>>,
+        T: Iterator<Item = crate::TextureBarrier<'a, super::Texture>>,
+    {
+        self.expect_active();
+        self.check_outside_render_pass("wait_events");
+        let mut dst_stages = vk::PipelineStageFlags::BOTTOM_OF_PIPE;
+        let vk_buffer_barriers = &mut self.temp.buffer_barriers;
+        let vk_image_barriers = &mut self.temp.image_barriers;
+        vk_buffer_barriers.clear();
+        vk_image_barriers.clear();
+
+        for bar in buffer_barriers {
+            if let Some(ref mut tracker) = self.encoding.usage_tracker {
+                tracker.record_buffer(&bar);
+            }
+            let (_, src_access) = conv::map_buffer_usage_to_barrier(bar.usage.start);
+            let (dst_stage, dst_access) = conv::map_buffer_usage_to_barrier(bar.usage.end);
+            dst_stages |= dst_stage;
+            vk_buffer_barriers.push(
+                vk::BufferMemoryBarrier::default()
+                    .buffer(bar.buffer.raw)
+                    .size(vk::WHOLE_SIZE)
+                    .src_access_mask(src_access)
+                    .dst_access_mask(dst_access),
+            );
+        }
+        for bar in texture_barriers {
+            if let Some(ref mut tracker) = self.encoding.usage_tracker {
+                tracker.record_texture(&bar);
+            }
+            let range = conv::map_barrier_subresource_range(
+                bar.texture,
+                &bar.range,
+                &self.device.private_caps,
+            );
+            let (_, src_access) = conv::map_texture_usage_to_barrier(bar.usage.start);
+            let src_layout = conv::derive_image_layout(bar.usage.start, bar.texture.format);
+            let (dst_stage, dst_access) = conv::map_texture_usage_to_barrier(bar.usage.end);
+            let dst_layout = conv::derive_image_layout(bar.usage.end, bar.texture.format);
+            dst_stages |= dst_stage;
+            push_image_barrier(
+                vk_image_barriers,
+                vk::ImageMemoryBarrier::default()
+                    .image(bar.texture.raw)
+                    .subresource_range(range)
+                    .src_access_mask(src_access)
+                    .dst_access_mask(dst_access)
+                    .old_layout(src_layout)
+                    .new_layout(dst_layout),
+            );
+        }
+
+        let vk_events = events
+            .iter()
+            .map(|event| event.raw)
+            .collect::<smallvec::SmallVec<[vk::Event; 8]>>();
+        unsafe {
+            self.device.raw.cmd_wait_events(
+                self.active,
+                &vk_events,
+                src_stages,
+                dst_stages,
+                &[],
+                vk_buffer_barriers,
+                vk_image_barriers,
+            )
+        };
+    }
+
+    /// Copies `regions` of `buffer` to `texture`, transitioning the whole
+    /// texture from `usage.start` to `COPY_DST` before the copy, and from
+    /// `COPY_DST` to `usage.end` after it.
+    ///
+    /// # Safety
+    ///
+    /// - All subresources of `texture` must be in the `usage.start` state.
+    /// - `buffer` must be in the `COPY_SRC` state.
+    /// - `regions` must follow the same rules as in
+    ///   [`crate::CommandEncoder::copy_buffer_to_texture`].
+    pub unsafe fn upload_to_texture<T>(
+        &mut self,
+        buffer: &super::Buffer,
+        texture: &super::Texture,
+        regions: T,
+        usage: Range<crate::TextureUses>,
+    ) where
+        T: Iterator<Item = crate::BufferTextureCopy>,
+    {
+        use crate::CommandEncoder as _;
+
+        let barrier = |usage| crate::TextureBarrier {
+            texture,
+            range: wgt::ImageSubresourceRange::default(),
+            usage,
+        };
+        unsafe {
+            self.transition_textures(std::iter::once(barrier(
+                usage.start..crate::TextureUses::COPY_DST,
+            )));
+            self.copy_buffer_to_texture(buffer, texture, regions);
+            self.transition_textures(std::iter::once(barrier(
+                crate::TextureUses::COPY_DST..usage.end,
+            )));
+        }
+    }
+
+    /// Copies `regions` of `texture` to `buffer`, then transitions `buffer`
+    /// from `COPY_DST` to `MAP_READ`.
+    ///
+    /// The transition is a barrier to the `HOST` stage with `HOST_READ`
+    /// access, without which the host may not see the copied data, even
+    /// once the submission completed. Memory that isn't `HOST_COHERENT`
+    /// also needs [`crate::Device::invalidate_mapped_ranges`] before reading
+    /// the mapping.
+    ///
+    /// # Safety
+    ///
+    /// - `texture` must be in the `src_usage` state, which includes
+    ///   `COPY_SRC`.
+    /// - `buffer` must be in the `COPY_DST` state.
+    /// - `regions` must follow the same rules as in
+    ///   [`crate::CommandEncoder::copy_texture_to_buffer`].
+    pub unsafe fn read_back_texture<T>(
+        &mut self,
+        texture: &super::Texture,
+        src_usage: crate::TextureUses,
+        buffer: &super::Buffer,
+        regions: T,
+    ) where
+        T: Iterator<Item = crate::BufferTextureCopy>,
+    {
+        use crate::CommandEncoder as _;
+
+        let barrier = crate::BufferBarrier {
+            buffer,
+            usage: crate::BufferUses::COPY_DST..crate::BufferUses::MAP_READ,
+        };
+        unsafe {
+            self.copy_texture_to_buffer(texture, src_usage, buffer, regions);
+            self.transition_buffers(std::iter::once(barrier));
+        }
+    }
+
+    /// Transitions the whole of `buffer` from `usage.start` to `usage.end`,
+    /// e.g. from `STORAGE_READ_WRITE` written by a compute pass to `VERTEX`.
+    ///
+    /// # Safety
+    ///
+    /// - `buffer` must be in the `usage.start` state.
+    pub unsafe fn transition_buffer(
+        &mut self,
+        buffer: &super::Buffer,
+        usage: Range<crate::BufferUses>,
+    ) {
+        use crate::CommandEncoder as _;
+
+        let barrier = crate::BufferBarrier { buffer, usage };
+        unsafe { self.transition_buffers(std::iter::once(barrier)) };
+    }
+
+    /// Transitions all mip levels, layers and aspects of `texture` from
+    /// `usage.start` to `usage.end`.
+    ///
+    /// # Safety
+    ///
+    /// - All subresources of `texture` must be in the `usage.start` state.
+    pub unsafe fn transition_texture_full(
+        &mut self,
+        texture: &super::Texture,
+        usage: Range<crate::TextureUses>,
+    ) {
+        use crate::CommandEncoder as _;
+
+        let barrier = crate::TextureBarrier {
+            texture,
+            range: wgt::ImageSubresourceRange::default(),
+            usage,
+        };
+        unsafe { self.transition_textures(std::iter::once(barrier)) };
+    }
+
+    /// Transitions all layers of mip level `level` of `texture` from
+    /// `usage.start` to `usage.end`, e.g. between the blits generating mips.
+    ///
+    /// # Safety
+    ///
+    /// - All layers of mip level `level` must be in the `usage.start` state.
+    pub unsafe fn transition_mip_level(
+        &mut self,
+        texture: &super::Texture,
+        level: u32,
+        usage: Range<crate::TextureUses>,
+    ) {
+        use crate::CommandEncoder as _;
+
+        let barrier = crate::TextureBarrier {
+            texture,
+            range: wgt::ImageSubresourceRange {
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            },
+            usage,
+        };
+        unsafe { self.transition_textures(std::iter::once(barrier)) };
+    }
+
+    /// Makes the submission of the command buffer being recorded signal
+    /// `fence` with `value` once it completes.
+    ///
+    /// This allows other submissions, possibly on other queues, to wait on
+    /// the work of this encoder, without a `Fence` per submission. Like the
+    /// fence of [`crate::Queue::submit`], it's signaled after all command
+    /// buffers of that submission.
+    ///
+    /// # Safety
+    ///
+    /// - `fence` must be a [`super::Fence::TimelineSemaphore`], alive until
+    ///   the submission completes.
+    /// - `value` must be greater than any value `fence` is signaled with by
+    ///   earlier submissions.
+    pub unsafe fn signal_on_completion(&mut self, fence: &super::Fence, value: crate::FenceValue) {
+        self.expect_active();
+        let super::Fence::TimelineSemaphore(raw) = *fence else {
+            super::hal_usage_error("only timeline semaphores can be signaled on completion");
+        };
+        if self.active_is_secondary {
+            super::hal_usage_error("secondary command buffers are not submitted");
+        }
+        self.signal = Some((raw, value));
+    }
+    /// Clears `rects` of the attachments of the current render pass.
+    ///
+    /// Unlike the `Clear` load op, this can clear parts of the attachments
+    /// at any point of the pass. The rectangles are in framebuffer
+    /// coordinates and aren't affected by the viewport or scissor.
+    ///
+    /// # Safety
+    ///
+    /// - `rects` must lie within the render area of the pass.
+    pub unsafe fn clear_attachments(
+        &mut self,
+        clears: &[super::AttachmentClear],
+        rects: &[crate::Rect<u32>],
+    ) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "clear_attachments");
+        if clears.is_empty() || rects.is_empty() {
+            return;
+        }
+
+        let vk_clears = clears
+            .iter()
+            .map(|clear| match *clear {
+                super::AttachmentClear::Color { attachment, value } => {
+                    let Some(&Some(format)) =
+                        self.render_pass_color_formats.get(attachment as usize)
+                    else {
+                        super::hal_usage_error(format!(
+                            "color attachment {attachment} is not used by the render pass"
+                        ));
+                    };
+                    vk::ClearAttachment {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        color_attachment: attachment,
+                        clear_value: vk::ClearValue {
+                            color: conv::map_clear_color(format, &value),
+                        },
+                    }
+                }
+                super::AttachmentClear::DepthStencil { depth, stencil } => {
+                    let has_depth_stencil = self
+                        .render_pass_signature
+                        .as_ref()
+                        .is_some_and(|signature| signature.depth_stencil.is_some());
+                    if cfg!(debug_assertions) && !has_depth_stencil {
+                        super::hal_usage_error("the render pass has no depth-stencil attachment");
+                    }
+                    let mut aspect_mask = vk::ImageAspectFlags::empty();
+                    if depth.is_some() {
+                        aspect_mask |= vk::ImageAspectFlags::DEPTH;
+                    }
+                    if stencil.is_some() {
+                        aspect_mask |= vk::ImageAspectFlags::STENCIL;
+                    }
+                    vk::ClearAttachment {
+                        aspect_mask,
+                        color_attachment: vk::ATTACHMENT_UNUSED,
+                        clear_value: vk::ClearValue {
+                            depth_stencil: vk::ClearDepthStencilValue {
+                                depth: depth.unwrap_or(0.0),
+                                stencil: stencil.unwrap_or(0),
+                            },
+                        },
+                    }
+                }
+            })
+            .filter(|vk_clear| !vk_clear.aspect_mask.is_empty())
+            .collect::<ArrayVec<_, { super::MAX_TOTAL_ATTACHMENTS }>>();
+        if vk_clears.is_empty() {
+            return;
+        }
+        let vk_rects = rects
+            .iter()
+            .map(|rect| vk::ClearRect {
+                rect: vk::Rect2D {
+                    offset: vk::Offset2D {
+                        x: rect.x as i32,
+                        y: rect.y as i32,
+                    },
+                    extent: vk::Extent2D {
+                        width: rect.w,
+                        height: rect.h,
+                    },
+                },
+                // With multiview, layer 0 stands for all views.
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .collect::<smallvec::SmallVec<[_; 4]>>();
+
+        unsafe {
+            self.device
+                .raw
+                .cmd_clear_attachments(self.active, &vk_clears, &vk_rects)
+        };
+    }
+
+    /// Returns the numbers of commands recorded since the last call, and
+    /// resets them.
+    ///
+    /// Commands are only counted in debug builds, release builds always
+    /// return zeroes.
+    pub fn take_stats(&mut self) -> super::EncoderStats {
+        mem::take(&mut self.stats)
+    }
+}
+
+impl std::ops::Deref for super::DebugMarkerGuard<'_> {
+    type Target = super::CommandEncoder;
+
+    fn deref(&self) -> &Self::Target {
+        self.encoder
+    }
+}
+
+impl std::ops::DerefMut for super::DebugMarkerGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.encoder
+    }
+}
+
+impl Drop for super::DebugMarkerGuard<'_> {
+    fn drop(&mut self) {
+        unsafe { crate::CommandEncoder::end_debug_marker(self.encoder) };
+    }
+}
+
+impl super::QueueOwnershipTracker {
+    pub(super) fn new(texture_s8: bool) -> Self {
+        Self {
+            buffers: Default::default(),
+            textures: Default::default(),
+            texture_s8,
+        }
+    }
+
+    /// Records an access to `buffer` from a queue of `family_index`.
+    ///
+    /// Returns the ownership transfer to record if the buffer was last
+    /// accessed from a different queue family.
+    pub fn access_buffer(
+        &mut self,
+        buffer: &super::Buffer,
+        family_index: u32,
+        usage: Range<crate::BufferUses>,
+    ) -> Option<super::BufferOwnershipTransfer> {
+        let old_family_index = self.buffers.insert(buffer.raw, family_index)?;
+        if old_family_index == family_index {
+            return None;
+        }
+        Some(self.transfer_buffer(buffer, old_family_index..family_index, usage))
+    }
+
+    /// Makes the transfer of `buffer` between the queue families
+    /// `family_indices.start` and `family_indices.end`, without tracking it.
+    ///
+    /// This is for resources whose ownership is known by the caller, e.g.
+    /// uploads through a dedicated transfer queue.
+    pub fn transfer_buffer(
+        &self,
+        buffer: &super::Buffer,
+        family_indices: Range<u32>,
+        usage: Range<crate::BufferUses>,
+    ) -> super::BufferOwnershipTransfer {
+        let (src_stages, src_access) = conv::map_buffer_usage_to_barrier(usage.start);
+        let (dst_stages, dst_access) = conv::map_buffer_usage_to_barrier(usage.end);
+        let barrier = vk::BufferMemoryBarrier::default()
+            .buffer(buffer.raw)
+            .size(vk::WHOLE_SIZE)
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .src_queue_family_index(family_indices.start)
+            .dst_queue_family_index(family_indices.end);
+        super::OwnershipTransfer {
+            release: barrier,
+            acquire: barrier,
+            src_stages,
+            dst_stages,
+        }
+    }
+
+    /// Records an access to `range` of `texture` from a queue of `family_index`.
+    ///
+    /// Ownership is tracked for the image as a whole, so all subresources
+    /// accessed from the old queue family have to be covered by `range`.
+    pub fn access_texture(
+        &mut self,
+        texture: &super::Texture,
+        range: &wgt::ImageSubresourceRange,
+        family_index: u32,
+        usage: Range<crate::TextureUses>,
+    ) -> Option<super::TextureOwnershipTransfer> {
+        let old_family_index = self.textures.insert(texture.raw, family_index)?;
+        if old_family_index == family_index {
+            return None;
+        }
+        Some(self.transfer_texture(texture, range, old_family_index..family_index, usage))
+    }
+
+    /// Makes the transfer of `range` of `texture` between the queue families
+    /// `family_indices.start` and `family_indices.end`, without tracking it.
+    pub fn transfer_texture(
+        &self,
+        texture: &super::Texture,
+        range: &wgt::ImageSubresourceRange,
+        family_indices: Range<u32>,
+        usage: Range<crate::TextureUses>,
+    ) -> super::TextureOwnershipTransfer {
+        let mut vk_range = conv::map_subresource_range(range, texture.format);
+        if !self.texture_s8 && texture.format == wgt::TextureFormat::Stencil8 {
+            vk_range.aspect_mask |= vk::ImageAspectFlags::DEPTH;
+        }
+        let (src_stages, src_access) = conv::map_texture_usage_to_barrier(usage.start);
+        let (dst_stages, dst_access) = conv::map_texture_usage_to_barrier(usage.end);
+        let barrier = vk::ImageMemoryBarrier::default()
+            .image(texture.raw)
+            .subresource_range(vk_range)
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .old_layout(conv::derive_image_layout(usage.start, texture.format))
+            .new_layout(conv::derive_image_layout(usage.end, texture.format))
+            .src_queue_family_index(family_indices.start)
+            .dst_queue_family_index(family_indices.end);
+        super::OwnershipTransfer {
+            release: barrier,
+            acquire: barrier,
+            src_stages,
+            dst_stages,
+        }
+    }
+
+    /// Forgets about `buffer`, which must be done before its handle is reused.
+    pub fn remove_buffer(&mut self, buffer: &super::Buffer) {
+        self.buffers.remove(&buffer.raw);
+    }
+
+    /// Forgets about `texture`, which must be done before its handle is reused.
+    pub fn remove_texture(&mut self, texture: &super::Texture) {
+        self.textures.remove(&texture.raw);
+    }
+}
+
+impl super::CommandEncoder {
+    /// Records the release half of queue family ownership transfers.
+    ///
+    /// # Safety
+    ///
+    /// - The encoder must be recording commands for a queue of the family
+    ///   currently owning the resources.
+    pub unsafe fn release_ownership(
+        &mut self,
+        buffers: &[super::BufferOwnershipTransfer],
+        textures: &[super::TextureOwnershipTransfer],
+    ) {
+        self.expect_active();
+        let mut src_stages = vk::PipelineStageFlags::TOP_OF_PIPE;
+        let buffer_barriers = &mut self.temp.buffer_barriers;
+        buffer_barriers.clear();
+        for transfer in buffers {
+            src_stages |= transfer.src_stages;
+            buffer_barriers.push(transfer.release);
+        }
+        let image_barriers = &mut self.temp.image_barriers;
+        image_barriers.clear();
+        for transfer in textures {
+            src_stages |= transfer.src_stages;
+            image_barriers.push(transfer.release);
+        }
+
+        if !buffer_barriers.is_empty() || !image_barriers.is_empty() {
+            unsafe {
+                self.device.raw.cmd_pipeline_barrier(
+                    self.active,
+                    src_stages,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    buffer_barriers,
+                    image_barriers,
+                )
+            };
+        }
+    }
+
+    /// Records the acquire half of queue family ownership transfers.
+    ///
+    /// # Safety
+    ///
+    /// - The encoder must be recording commands for a queue of the family
+    ///   the resources are transferred to.
+    /// - The submission must wait for the one containing the matching release.
+    pub unsafe fn acquire_ownership(
+        &mut self,
+        buffers: &[super::BufferOwnershipTransfer],
+        textures: &[super::TextureOwnershipTransfer],
+    ) {
+        self.expect_active();
+        let mut dst_stages = vk::PipelineStageFlags::BOTTOM_OF_PIPE;
+        let buffer_barriers = &mut self.temp.buffer_barriers;
+        buffer_barriers.clear();
+        for transfer in buffers {
+            dst_stages |= transfer.dst_stages;
+            buffer_barriers.push(transfer.acquire);
+        }
+        let image_barriers = &mut self.temp.image_barriers;
+        image_barriers.clear();
+        for transfer in textures {
+            dst_stages |= transfer.dst_stages;
+            image_barriers.push(transfer.acquire);
+        }
+
+        if !buffer_barriers.is_empty() || !image_barriers.is_empty() {
+            unsafe {
+                self.device.raw.cmd_pipeline_barrier(
+                    self.active,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    dst_stages,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    buffer_barriers,
+                    image_barriers,
+                )
+            };
+        }
+    }
+}
+
+impl crate::CommandEncoder for super::CommandEncoder {
+    type A = super::Api;
+
+    unsafe fn begin_encoding(&mut self, label: crate::Label) -> Result<(), crate::DeviceError> {
+        unsafe { self.begin_encoding_impl(label, None, None) }
+    }
+
+    unsafe fn end_encoding(&mut self) -> Result<super::CommandBuffer, crate::DeviceError> {
+        self.expect_active();
+        self.flush_bind_groups();
+        let raw = self.active;
+        self.active = vk::CommandBuffer::null();
+        unsafe { self.device.raw.end_command_buffer(raw) }.map_err(map_err)?;
+        fn map_err(err: vk::Result) -> crate::DeviceError {
+            // We don't use VK_KHR_video_encode_queue
+            // VK_ERROR_INVALID_VIDEO_STD_PARAMETERS_KHR
+            super::map_host_device_oom_err(err)
+        }
+        Ok(super::CommandBuffer {
+            raw,
+            secondary: mem::take(&mut self.active_is_secondary),
+            external: mem::take(&mut self.active_is_external),
+            signal: self.signal.take(),
+        })
+    }
+
+    unsafe fn discard_encoding(&mut self) {
+        // Safe use requires this is not called in the "closed" state, so the buffer
+        // shouldn't be null. Assert this to make sure we're not pushing null
+        // buffers to the discard pile.
+        assert_ne!(self.active, vk::CommandBuffer::null());
+
+        // The buffer stays in the recording state until the pool is reset
+        // by `reset_all` or `reset_pool`, which is valid for any state but
+        // pending; ending it here would only be wasted work.
+        self.pending_bind_groups.clear();
+        self.signal = None;
+        let secondary = mem::take(&mut self.active_is_secondary);
+        if mem::take(&mut self.active_is_external) {
+            // The caller of `begin_encoding_with` recycles it.
+        } else if secondary {
+            self.discarded_secondary.push(self.active);
+        } else {
+            self.discarded.push(self.active);
+        }
+        self.active = vk::CommandBuffer::null();
+    }
+
+    unsafe fn reset_all<I>(&mut self, cmd_bufs: I)
+    where
+        I: Iterator<Item = super::CommandBuffer>,
+    {
+        unsafe {
+            self.recycle(cmd_bufs);
+            self.reset_pool();
+        }
+    }
+
+    unsafe fn transition_buffers<'a, T>(&mut self, barriers: T)
+    where
+        T: Iterator<Item = crate::BufferBarrier<'a, super::Buffer>>,
+    {
+        let conservative = wgt::ShaderStages::empty();
+        unsafe { self.transition_buffers_in_stages(barriers, conservative..conservative) };
+    }
+
+    unsafe fn transition_textures<'a, T>(&mut self, barriers: T)
+    where
+        T: Iterator<Item = crate::TextureBarrier<'a, super::Texture>>,
+    {
+        let conservative = wgt::ShaderStages::empty();
+        unsafe { self.transition_textures_in_stages(barriers, conservative..conservative) };
+    }
+
+    unsafe fn clear_buffer(&mut self, buffer: &super::Buffer, range: crate::MemoryRange) {
+        self.expect_active();
+        // `vkCmdFillBuffer` requires both the offset and the size to be multiples of 4.
+        if cfg!(debug_assertions)
+            && (range.start % wgt::COPY_BUFFER_ALIGNMENT != 0
+                || range.end % wgt::COPY_BUFFER_ALIGNMENT != 0)
+        {
+            super::hal_usage_error(format!(
+                "clear_buffer range {range:?} is not aligned to {}",
+                wgt::COPY_BUFFER_ALIGNMENT
+            ));
+        }
+        if range.start == range.end {
+            return;
+        }
+
+        let range_size = range.end - range.start;
+        if range.start == 0 && buffer.size == Some(range.end) {
+            unsafe {
+                self.device
+                    .raw
+                    .cmd_fill_buffer(self.active, buffer.raw, 0, vk::WHOLE_SIZE, 0)
+            };
+        } else if self.device.workarounds.contains(
+            super::Workarounds::FORCE_FILL_BUFFER_WITH_SIZE_GREATER_4096_ALIGNED_OFFSET_16,
+        ) && range_size >= 4096
+            && range.start % 16 != 0
+        {
+            let rounded_start = wgt::math::align_to(range.start, 16);
+            let prefix_size = rounded_start - range.start;
+
+            unsafe {
+                self.device.raw.cmd_fill_buffer(
+                    self.active,
+                    buffer.raw,
+                    range.start,
+                    prefix_size,
+                    0,
+                )
+            };
+
+            // This will never be zero, as rounding can only add up to 12 bytes, and the total size is 4096.
+            let suffix_size = range.end - rounded_start;
+
+            unsafe {
+                self.device.raw.cmd_fill_buffer(
+                    self.active,
+                    buffer.raw,
+                    rounded_start,
+                    suffix_size,
+                    0,
+                )
+            };
+        } else {
+            unsafe {
+                self.device
+                    .raw
+                    .cmd_fill_buffer(self.active, buffer.raw, range.start, range_size, 0)
+            };
+        }
+    }
+
+    unsafe fn copy_buffer_to_buffer<T>(
+        &mut self,
+        src: &super::Buffer,
+        dst: &super::Buffer,
+        regions: T,
+    ) where
+        T: Iterator<Item = crate::BufferCopy>,
+    {
+        self.expect_active();
+        self.check_buffer_usage(src, crate::BufferUses::COPY_SRC, "copy_buffer_to_buffer");
+        self.check_buffer_usage(dst, crate::BufferUses::COPY_DST, "copy_buffer_to_buffer");
+        let aliased = src.raw == dst.raw;
+        let vk_regions_iter = regions.map(|r| {
+            let size = r.size.get();
+            // Copying between overlapping parts of the same buffer is undefined.
+            if cfg!(debug_assertions)
+                && aliased
+                && r.src_offset < r.dst_offset + size
+                && r.dst_offset < r.src_offset + size
+            {
+                super::hal_usage_error(format!(
+                    "copy of {size} bytes from offset {} to offset {} overlaps within the same buffer",
+                    r.src_offset, r.dst_offset
+                ));
+            }
+            vk::BufferCopy {
+                src_offset: r.src_offset,
+                dst_offset: r.dst_offset,
+                size,
+            }
+        });
+        let vk_regions = smallvec::SmallVec::<[vk::BufferCopy; 32]>::from_iter(vk_regions_iter);
+        if vk_regions.is_empty() {
+            return;
+        }
+
+        unsafe {
+            self.device
+                .raw
+                .cmd_copy_buffer(self.active, src.raw, dst.raw, &vk_regions)
+        };
+        self.count(|stats| &mut stats.copies);
+    }
+
+    unsafe fn copy_texture_to_texture<T>(
+        &mut self,
+        src: &super::Texture,
+        src_usage: crate::TextureUses,
+        dst: &super::Texture,
+        regions: T,
+    ) where
+        T: Iterator<Item = crate::TextureCopy>,
+    {
+        unsafe {
+            self.copy_texture_to_texture_with_dst_usage(
+                src,
+                src_usage,
+                dst,
+                crate::TextureUses::COPY_DST,
+                regions,
+            )
+        };
+    }
+
+    unsafe fn copy_buffer_to_texture<T>(
+        &mut self,
+        src: &super::Buffer,
+        dst: &super::Texture,
+        regions: T,
+    ) where
+        T: Iterator<Item = crate::BufferTextureCopy>,
+    {
+        unsafe {
+            self.copy_buffer_to_texture_with_dst_usage(
+                src,
+                dst,
+                crate::TextureUses::COPY_DST,
+                regions,
+            )
+        };
+    }
+
+    unsafe fn copy_texture_to_buffer<T>(
+        &mut self,
+        src: &super::Texture,
+        src_usage: crate::TextureUses,
+        dst: &super::Buffer,
+        regions: T,
+    ) where
+        T: Iterator<Item = crate::BufferTextureCopy>,
+    {
+        self.expect_active();
+        self.check_texture_usage(src, crate::TextureUses::COPY_SRC, "copy_texture_to_buffer");
+        self.check_buffer_usage(dst, crate::BufferUses::COPY_DST, "copy_texture_to_buffer");
+        let src_layout = conv::derive_image_layout(src_usage, src.format);
+        let vk_regions = smallvec::SmallVec::<[vk::BufferImageCopy; 32]>::from_iter(
+            src.map_buffer_copies(regions),
+        );
+        if vk_regions.is_empty() {
+            return;
+        }
+
+        unsafe {
+            self.device.raw.cmd_copy_image_to_buffer(
+                self.active,
+                src.raw,
+                src_layout,
+                dst.raw,
+                &vk_regions,
+            )
+        };
+        self.count(|stats| &mut stats.copies);
+    }
+
+    unsafe fn begin_query(&mut self, set: &super::QuerySet, index: u32) {
+        self.expect_active();
+        self.reset_query_before_use(set.raw, index);
+        unsafe {
+            self.device.raw.cmd_begin_query(
+                self.active,
+                set.raw,
+                index,
+                vk::QueryControlFlags::empty(),
+            )
+        };
+    }
+    unsafe fn end_query(&mut self, set: &super::QuerySet, index: u32) {
+        self.expect_active();
+        unsafe { self.device.raw.cmd_end_query(self.active, set.raw, index) };
+    }
+    unsafe fn write_timestamp(&mut self, set: &super::QuerySet, index: u32) {
+        unsafe { self.write_timestamp_at(set, index, super::TimestampStage::BottomOfPipe) };
+    }
+    unsafe fn reset_queries(&mut self, set: &super::QuerySet, range: Range<u32>) {
+        self.expect_active();
+        set.check_range(&range);
+        if let Some(ref mut reset) = self.encoding.auto_reset_queries {
+            reset.extend(range.clone().map(|index| (set.raw, index)));
+        }
+        unsafe {
+            self.device.raw.cmd_reset_query_pool(
+                self.active,
+                set.raw,
+                range.start,
+                range.end - range.start,
+            )
+        };
+    }
+    unsafe fn copy_query_results(
+        &mut self,
+        set: &super::QuerySet,
+        range: Range<u32>,
+        buffer: &super::Buffer,
+        offset: wgt::BufferAddress,
+        stride: wgt::BufferSize,
+    ) {
+        unsafe {
+            self.copy_query_results_with(
+                set,
+                range,
+                buffer,
+                offset,
+                stride,
+                super::QueryResultMode::Wait,
+            )
+        }
+    }
+
+    unsafe fn build_acceleration_structures<'a, T>(&mut self, descriptor_count: u32, descriptors: T)
+    where
+        super::Api: 'a,
+        T: IntoIterator<
+            Item = crate::BuildAccelerationStructureDescriptor<
+                'a,
+                super::Buffer,
+                super::AccelerationStructure,
+            >,
+        >,
+    {
+        self.expect_active();
+        const CAPACITY_OUTER: usize = 8;
+        const CAPACITY_INNER: usize = 1;
+        let descriptor_count = descriptor_count as usize;
+
+        let ray_tracing_functions = self
+            .device
+            .extension_fns
+            .ray_tracing
+            .as_ref()
+            .expect("Feature `RAY_TRACING` not enabled");
+
+        let get_device_address = |buffer: Option<&super::Buffer>| unsafe {
+            match buffer {
+                Some(buffer) => ray_tracing_functions
+                    .buffer_device_address
+                    .get_buffer_device_address(
+                        &vk::BufferDeviceAddressInfo::default().buffer(buffer.raw),
+                    ),
+                None => panic!("Buffers are required to build acceleration structures"),
+            }
+        };
+
+        // storage to all the data required for cmd_build_acceleration_structures
+        let mut ranges_storage = smallvec::SmallVec::<
+            [smallvec::SmallVec<[vk::AccelerationStructureBuildRangeInfoKHR; CAPACITY_INNER]>;
+                CAPACITY_OUTER],
+        >::with_capacity(descriptor_count);
+        let mut geometries_storage = smallvec::SmallVec::<
+            [smallvec::SmallVec<[vk::AccelerationStructureGeometryKHR; CAPACITY_INNER]>;
+                CAPACITY_OUTER],
+        >::with_capacity(descriptor_count);
+
+        // pointers to all the data required for cmd_build_acceleration_structures
+        let mut geometry_infos = smallvec::SmallVec::<
+            [vk::AccelerationStructureBuildGeometryInfoKHR; CAPACITY_OUTER],
+        >::with_capacity(descriptor_count);
+        let mut ranges_ptrs = smallvec::SmallVec::<
+            [&[vk::AccelerationStructureBuildRangeInfoKHR]; CAPACITY_OUTER],
+        >::with_capacity(descriptor_count);
+
+        for desc in descriptors {
+            let (geometries, ranges) = match *desc.entries {
+                crate::AccelerationStructureEntries::Instances(ref instances) => {
+                    let instance_data = vk::AccelerationStructureGeometryInstancesDataKHR::default(
+                    // TODO: Code is so large that rustfmt refuses to treat this... :(
+                    )
+                    .data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: get_device_address(instances.buffer),
+                    });
+
+                    let geometry = vk::AccelerationStructureGeometryKHR::default()
                         .geometry_type(vk::GeometryTypeKHR::INSTANCES)
                         .geometry(vk::AccelerationStructureGeometryDataKHR {
                             instances: instance_data,
@@ -644,6 +4324,7 @@ impl crate::CommandEncoder for super::CommandEncoder {
         &mut self,
         barrier: crate::AccelerationStructureBarrier,
     ) {
+        self.expect_active();
         let (src_stage, src_access) =
             conv::map_acceleration_structure_usage_to_barrier(barrier.usage.start);
         let (dst_stage, dst_access) =
@@ -669,6 +4350,10 @@ impl crate::CommandEncoder for super::CommandEncoder {
         &mut self,
         desc: &crate::RenderPassDescriptor<super::QuerySet, super::TextureView>,
     ) {
+        self.expect_active();
+        // Indexed by attachment, so this follows the order of
+        // `make_render_pass`: each color attachment followed by its resolve
+        // attachment, if any, then the depth-stencil attachment.
         let mut vk_clear_values =
             ArrayVec::<vk::ClearValue, { super::MAX_TOTAL_ATTACHMENTS }>::new();
         let mut vk_image_views = ArrayVec::<vk::ImageView, { super::MAX_TOTAL_ATTACHMENTS }>::new();
@@ -677,17 +4362,67 @@ impl crate::CommandEncoder for super::CommandEncoder {
             attachments: ArrayVec::default(),
             extent: desc.extent,
             sample_count: desc.sample_count,
+            shading_rate_texel_size: None,
         };
         let caps = &self.device.private_caps;
+        if cfg!(debug_assertions) {
+            let colors = desc
+                .color_attachments
+                .iter()
+                .enumerate()
+                .filter_map(|(i, cat)| {
+                    let cat = cat.as_ref()?;
+                    Some((format!("color attachment {i}"), cat.target.view.size))
+                });
+            let resolves = desc
+                .color_attachments
+                .iter()
+                .enumerate()
+                .filter_map(|(i, cat)| {
+                    let target = cat.as_ref()?.resolve_target.as_ref()?;
+                    Some((
+                        format!("resolve target of color attachment {i}"),
+                        target.view.size,
+                    ))
+                });
+            let depth_stencil = desc
+                .depth_stencil_attachment
+                .as_ref()
+                .map(|ds| ("depth-stencil attachment".to_string(), ds.target.view.size));
+            if let Err(message) =
+                check_attachment_sizes(colors.chain(resolves).chain(depth_stencil))
+            {
+                super::hal_usage_error(message);
+            }
+        }
 
         for cat in desc.color_attachments {
             if let Some(cat) = cat.as_ref() {
+                let mut ops = cat.ops;
+                let mut vk_clear_color = unsafe { cat.make_vk_clear_color() };
+                let pending = self
+                    .pending_clears
+                    .iter()
+                    .position(|&(view, _)| view == cat.target.view.raw);
+                if let Some(index) = pending {
+                    let (_, color) = self.encoding.pending_clears.swap_remove(index);
+                    ops.remove(crate::AttachmentOps::LOAD);
+                    vk_clear_color =
+                        conv::map_clear_color(cat.target.view.attachment.view_format, &color);
+                }
                 vk_clear_values.push(vk::ClearValue {
-                    color: unsafe { cat.make_vk_clear_color() },
+                    color: vk_clear_color,
                 });
                 vk_image_views.push(cat.target.view.raw);
+                let mut base = cat.target.make_attachment_key(ops, caps);
+                if let Some((ref colors, _)) = self.encoding.next_attachment_layouts {
+                    let index = rp_key.colors.len();
+                    if let Some(&layouts) = colors.get(index) {
+                        apply_attachment_layouts(&mut base, layouts, &cat.target);
+                    }
+                }
                 let color = super::ColorAttachmentKey {
-                    base: cat.target.make_attachment_key(cat.ops, caps),
+                    base,
                     resolve: cat.resolve_target.as_ref().map(|target| {
                         target.make_attachment_key(crate::AttachmentOps::STORE, caps)
                     }),
@@ -721,9 +4456,14 @@ impl crate::CommandEncoder for super::CommandEncoder {
                 },
             });
             vk_image_views.push(ds.target.view.raw);
+            let mut base = ds.target.make_attachment_key(ds.depth_ops, caps);
+            if let Some((_, layouts)) = self.encoding.next_attachment_layouts {
+                apply_attachment_layouts(&mut base, layouts, &ds.target);
+            }
             rp_key.depth_stencil = Some(super::DepthStencilAttachmentKey {
-                base: ds.target.make_attachment_key(ds.depth_ops, caps),
+                base,
                 stencil_ops: ds.stencil_ops,
+                resolve: None,
             });
             fb_key.attachments.push(ds.target.view.attachment.clone());
 
@@ -733,6 +4473,43 @@ impl crate::CommandEncoder for super::CommandEncoder {
                 assert_eq!(ds.target.view.layers, multiview);
             }
         }
+        // Without multiview, the framebuffer has a layer per layer of the
+        // attachments, e.g. for layered rendering to a cube map.
+        fb_key.extent.depth_or_array_layers = if desc.multiview.is_some() {
+            1
+        } else {
+            let layers = fb_key.attachments.iter().map(|at| at.layers);
+            if cfg!(debug_assertions) {
+                if let Err(message) = check_attachment_layers(layers.clone().enumerate()) {
+                    super::hal_usage_error(message);
+                }
+            }
+            // All attachments have the same layer count if the check passes.
+            layers.min().unwrap_or(1)
+        };
+        if cfg!(debug_assertions) && !self.encoding.pending_clears.is_empty() {
+            super::hal_usage_error("clear_on_load for a view that isn't a color attachment");
+        }
+        self.encoding.pending_clears.clear();
+        self.encoding.next_attachment_layouts = None;
+        if let Some((key, raw_view, attachment)) = self.encoding.next_depth_stencil_resolve.take() {
+            if let Some(ref mut ds) = rp_key.depth_stencil {
+                ds.resolve = Some(key);
+                vk_clear_values.push(unsafe { mem::zeroed() });
+                vk_image_views.push(raw_view);
+                fb_key.attachments.push(attachment);
+            } else if cfg!(debug_assertions) {
+                super::hal_usage_error("depth-stencil resolve without a depth-stencil attachment");
+            }
+        }
+        if let Some((key, raw_view, attachment)) = self.encoding.next_shading_rate_attachment.take()
+        {
+            fb_key.shading_rate_texel_size = Some(key.texel_size);
+            rp_key.shading_rate = Some(key);
+            vk_clear_values.push(unsafe { mem::zeroed() });
+            vk_image_views.push(raw_view);
+            fb_key.attachments.push(attachment);
+        }
         rp_key.sample_count = fb_key.sample_count;
         rp_key.multiview = desc.multiview;
 
@@ -743,24 +4520,33 @@ impl crate::CommandEncoder for super::CommandEncoder {
                 height: desc.extent.height,
             },
         };
-        let vk_viewports = [vk::Viewport {
-            x: 0.0,
-            y: if self.device.private_caps.flip_y_requires_shift {
-                desc.extent.height as f32
-            } else {
-                0.0
-            },
-            width: desc.extent.width as f32,
-            height: -(desc.extent.height as f32),
-            min_depth: 0.0,
-            max_depth: 1.0,
-        }];
+        let vk_viewports = [self.make_pass_viewport(&desc.extent)];
 
+        self.render_pass_signature = Some(rp_key.signature());
+        self.render_pass_color_formats = desc
+            .color_attachments
+            .iter()
+            .map(|cat| {
+                cat.as_ref()
+                    .map(|cat| cat.target.view.attachment.view_format)
+            })
+            .collect();
         let raw_pass = self.device.make_render_pass(rp_key).unwrap();
         let raw_framebuffer = self
             .device
             .make_framebuffer(fb_key, raw_pass, desc.label)
             .unwrap();
+        let subpass_contents = mem::replace(
+            &mut self.encoding.next_subpass_contents,
+            vk::SubpassContents::INLINE,
+        );
+        self.subpass_contents = subpass_contents;
+        self.render_pass_inheritance = Some(super::CommandBufferInheritance {
+            render_pass: raw_pass,
+            subpass: 0,
+            framebuffer: raw_framebuffer,
+            extent: desc.extent,
+        });
 
         let mut vk_info = vk::RenderPassBeginInfo::default()
             .render_pass(raw_pass)
@@ -782,16 +4568,7 @@ impl crate::CommandEncoder for super::CommandEncoder {
         }
 
         // Start timestamp if any (before all other commands but after debug marker)
-        if let Some(timestamp_writes) = desc.timestamp_writes.as_ref() {
-            if let Some(index) = timestamp_writes.beginning_of_pass_write_index {
-                unsafe {
-                    self.write_timestamp(timestamp_writes.query_set, index);
-                }
-            }
-            self.end_of_pass_timer_query = timestamp_writes
-                .end_of_pass_write_index
-                .map(|index| (timestamp_writes.query_set.raw, index));
-        }
+        unsafe { self.write_pass_begin_timestamp(desc.timestamp_writes.as_ref()) };
 
         unsafe {
             self.device
@@ -800,19 +4577,29 @@ impl crate::CommandEncoder for super::CommandEncoder {
             self.device
                 .raw
                 .cmd_set_scissor(self.active, 0, &[render_area]);
-            self.device.raw.cmd_begin_render_pass(
-                self.active,
-                &vk_info,
-                vk::SubpassContents::INLINE,
-            );
+            self.device
+                .raw
+                .cmd_begin_render_pass(self.active, &vk_info, subpass_contents);
         };
 
+        self.viewport_count = 1;
+        self.scissor_count = 1;
         self.bind_point = vk::PipelineBindPoint::GRAPHICS;
+        self.active_pass = Some(vk::PipelineBindPoint::GRAPHICS);
+        self.reset_bound_pipelines();
     }
     unsafe fn end_render_pass(&mut self) {
+        self.expect_active();
+        self.flush_bind_groups();
         unsafe {
             self.device.raw.cmd_end_render_pass(self.active);
         }
+        self.reset_bound_pipelines();
+        self.render_pass_inheritance = None;
+        self.render_pass_signature = None;
+        self.render_pass_color_formats.clear();
+        self.subpass_contents = vk::SubpassContents::INLINE;
+        self.active_pass = None;
 
         // After all other commands but before debug marker, so this is still seen as part of this pass.
         self.write_pass_end_timestamp_if_requested();
@@ -832,17 +4619,30 @@ impl crate::CommandEncoder for super::CommandEncoder {
         group: &super::BindGroup,
         dynamic_offsets: &[wgt::DynamicOffset],
     ) {
-        let sets = [*group.set.raw()];
-        unsafe {
-            self.device.raw.cmd_bind_descriptor_sets(
-                self.active,
-                self.bind_point,
-                layout.raw,
-                index,
-                &sets,
-                dynamic_offsets,
-            )
-        };
+        self.expect_active();
+        if cfg!(debug_assertions) {
+            let max_sets = self.device.limits.max_bound_descriptor_sets;
+            if let Err(message) = check_bind_group_index(index, max_sets) {
+                super::hal_usage_error(message);
+            }
+        }
+        // Consecutive sets of the same layout are bound together by
+        // `flush_bind_groups`.
+        let pending = &self.pending_bind_groups;
+        let contiguous = !pending.sets.is_empty()
+            && pending.bind_point == self.bind_point
+            && pending.layout == layout.raw
+            && pending.first_set + pending.sets.len() as u32 == index;
+        if !contiguous {
+            self.flush_bind_groups();
+            let pending = &mut self.pending_bind_groups;
+            pending.bind_point = self.bind_point;
+            pending.layout = layout.raw;
+            pending.first_set = index;
+        }
+        let pending = &mut self.pending_bind_groups;
+        pending.sets.push(*group.set.raw());
+        pending.dynamic_offsets.extend_from_slice(dynamic_offsets);
     }
     unsafe fn set_push_constants(
         &mut self,
@@ -851,38 +4651,76 @@ impl crate::CommandEncoder for super::CommandEncoder {
         offset_bytes: u32,
         data: &[u32],
     ) {
-        unsafe {
-            self.device.raw.cmd_push_constants(
-                self.active,
-                layout.raw,
-                conv::map_shader_stage(stages),
-                offset_bytes,
-                slice::from_raw_parts(data.as_ptr().cast(), data.len() * 4),
-            )
-        };
+        self.expect_active();
+        let bytes: &[u8] = unsafe { slice::from_raw_parts(data.as_ptr().cast(), data.len() * 4) };
+        if cfg!(debug_assertions) {
+            let max_size = self.device.limits.max_push_constants_size;
+            if let Err(message) = check_push_constants_size(offset_bytes, bytes.len(), max_size) {
+                super::hal_usage_error(message);
+            }
+        }
+        let end = offset_bytes + bytes.len() as u32;
+        for (stages, range) in
+            split_push_constants(&layout.push_constant_ranges, stages, offset_bytes..end)
+        {
+            let start = (range.start - offset_bytes) as usize;
+            let end = (range.end - offset_bytes) as usize;
+            unsafe {
+                self.device.raw.cmd_push_constants(
+                    self.active,
+                    layout.raw,
+                    conv::map_shader_stage(stages),
+                    range.start,
+                    &bytes[start..end],
+                )
+            };
+        }
     }
 
     unsafe fn insert_debug_marker(&mut self, label: &str) {
+        self.expect_active();
         if let Some(ext) = self.device.extension_fns.debug_utils.as_ref() {
             let cstr = self.temp.make_c_str(label);
-            let vk_label = vk::DebugUtilsLabelEXT::default().label_name(cstr);
+            let vk_label = vk::DebugUtilsLabelEXT::default()
+                .label_name(cstr)
+                .color(label_to_color(label));
             unsafe { ext.cmd_insert_debug_utils_label(self.active, &vk_label) };
         }
     }
     unsafe fn begin_debug_marker(&mut self, group_label: &str) {
+        self.expect_active();
         if let Some(ext) = self.device.extension_fns.debug_utils.as_ref() {
             let cstr = self.temp.make_c_str(group_label);
-            let vk_label = vk::DebugUtilsLabelEXT::default().label_name(cstr);
+            let vk_label = vk::DebugUtilsLabelEXT::default()
+                .label_name(cstr)
+                .color(label_to_color(group_label));
             unsafe { ext.cmd_begin_debug_utils_label(self.active, &vk_label) };
         }
     }
     unsafe fn end_debug_marker(&mut self) {
+        self.expect_active();
         if let Some(ext) = self.device.extension_fns.debug_utils.as_ref() {
             unsafe { ext.cmd_end_debug_utils_label(self.active) };
         }
     }
 
     unsafe fn set_render_pipeline(&mut self, pipeline: &super::RenderPipeline) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "set_render_pipeline");
+        if cfg!(debug_assertions) {
+            if let Some(ref signature) = self.render_pass_signature {
+                if *signature != pipeline.render_pass_signature {
+                    super::hal_usage_error(format!(
+                        "render pipeline created for {:?} is not compatible with the active render pass {:?}",
+                        pipeline.render_pass_signature, signature
+                    ));
+                }
+            }
+        }
+        if self.bound_render_pipeline == pipeline.raw {
+            return;
+        }
+        self.bound_render_pipeline = pipeline.raw;
         unsafe {
             self.device.raw.cmd_bind_pipeline(
                 self.active,
@@ -890,6 +4728,61 @@ impl crate::CommandEncoder for super::CommandEncoder {
                 pipeline.raw,
             )
         };
+        if pipeline.dynamic_line_width {
+            unsafe {
+                self.device
+                    .raw
+                    .cmd_set_line_width(self.active, self.encoding.line_width)
+            };
+        }
+        if let Some(bias) = pipeline.depth_bias {
+            unsafe {
+                self.device.raw.cmd_set_depth_bias(
+                    self.active,
+                    bias.constant as f32,
+                    bias.clamp,
+                    bias.slope_scale,
+                )
+            };
+        }
+        if let Some(enabled) = pipeline.depth_clamp {
+            unsafe {
+                self.extended_dynamic_state3_fns()
+                    .cmd_set_depth_clamp_enable(self.active, enabled)
+            };
+        }
+        if let Some(topology) = pipeline.topology {
+            unsafe { self.cmd_set_primitive_topology(topology) };
+        }
+        if let Some((cull_mode, front_face)) = pipeline.cull_mode {
+            unsafe {
+                self.cmd_set_cull_mode(cull_mode);
+                self.cmd_set_front_face(front_face);
+            }
+        }
+        if pipeline.dynamic_sample_locations {
+            unsafe { self.apply_sample_locations(pipeline.render_pass_signature.sample_count) };
+        }
+        if pipeline.dynamic_fragment_shading_rate {
+            unsafe { self.apply_fragment_shading_rate() };
+        }
+        self.color_write_attachments = pipeline.color_write_attachments;
+        if let Some(count) = pipeline.color_write_attachments {
+            let enables = [vk::TRUE; crate::MAX_COLOR_ATTACHMENTS];
+            unsafe {
+                self.color_write_enable_fns()
+                    .cmd_set_color_write_enable(self.active, &enables[..count as usize])
+            };
+        }
+        if let Some(ref input) = pipeline.vertex_input {
+            unsafe { self.apply_vertex_input(input) };
+        }
+        if pipeline.dynamic_depth_bounds {
+            let [min, max] = self
+                .depth_bounds
+                .unwrap_or_else(|| self.full_depth_bounds());
+            unsafe { self.device.raw.cmd_set_depth_bounds(self.active, min, max) };
+        }
     }
 
     unsafe fn set_index_buffer<'a>(
@@ -897,74 +4790,83 @@ impl crate::CommandEncoder for super::CommandEncoder {
         binding: crate::BufferBinding<'a, super::Buffer>,
         format: wgt::IndexFormat,
     ) {
-        unsafe {
-            self.device.raw.cmd_bind_index_buffer(
-                self.active,
-                binding.buffer.raw,
-                binding.offset,
-                conv::map_index_format(format),
-            )
-        };
+        self.expect_active();
+        let index_type = conv::map_index_format(format);
+        let maintenance5 = self
+            .device
+            .extension_fns
+            .maintenance5
+            .as_ref()
+            .filter(|_| self.device.private_caps.bind_index_buffer2);
+        match (binding.size, maintenance5) {
+            // With a bound size, indices fetched past it read as zero under
+            // robust buffer access, instead of from the rest of the buffer.
+            (Some(size), Some(maintenance5)) => unsafe {
+                maintenance5.cmd_bind_index_buffer2(
+                    self.active,
+                    binding.buffer.raw,
+                    binding.offset,
+                    size.get(),
+                    index_type,
+                )
+            },
+            _ => unsafe {
+                self.device.raw.cmd_bind_index_buffer(
+                    self.active,
+                    binding.buffer.raw,
+                    binding.offset,
+                    index_type,
+                )
+            },
+        }
     }
     unsafe fn set_vertex_buffer<'a>(
         &mut self,
         index: u32,
         binding: crate::BufferBinding<'a, super::Buffer>,
     ) {
+        self.expect_active();
+        let max_bindings = self.device.limits.max_vertex_input_bindings;
+        if cfg!(debug_assertions) && index >= max_bindings {
+            super::hal_usage_error(format!(
+                "vertex buffer slot {index} exceeds the device limit of {max_bindings} bindings"
+            ));
+        }
         let vk_buffers = [binding.buffer.raw];
         let vk_offsets = [binding.offset];
-        unsafe {
-            self.device
-                .raw
-                .cmd_bind_vertex_buffers(self.active, index, &vk_buffers, &vk_offsets)
-        };
+        match binding.size {
+            // Binding the exact size lets robust buffer access catch reads past it.
+            Some(size) if self.device.private_caps.bind_vertex_buffers2 => unsafe {
+                self.device.raw.cmd_bind_vertex_buffers2(
+                    self.active,
+                    index,
+                    &vk_buffers,
+                    &vk_offsets,
+                    Some(&[size.get()]),
+                    None,
+                )
+            },
+            _ => unsafe {
+                self.device.raw.cmd_bind_vertex_buffers(
+                    self.active,
+                    index,
+                    &vk_buffers,
+                    &vk_offsets,
+                )
+            },
+        }
     }
     unsafe fn set_viewport(&mut self, rect: &crate::Rect<f32>, depth_range: Range<f32>) {
-        let vk_viewports = [vk::Viewport {
-            x: rect.x,
-            y: if self.device.private_caps.flip_y_requires_shift {
-                rect.y + rect.h
-            } else {
-                rect.y
-            },
-            width: rect.w,
-            height: -rect.h, // flip Y
-            min_depth: depth_range.start,
-            max_depth: depth_range.end,
-        }];
-        unsafe {
-            self.device
-                .raw
-                .cmd_set_viewport(self.active, 0, &vk_viewports)
-        };
+        unsafe { self.set_viewports(0, &[(rect.clone(), depth_range)]) };
     }
     unsafe fn set_scissor_rect(&mut self, rect: &crate::Rect<u32>) {
-        let vk_scissors = [vk::Rect2D {
-            offset: vk::Offset2D {
-                x: rect.x as i32,
-                y: rect.y as i32,
-            },
-            extent: vk::Extent2D {
-                width: rect.w,
-                height: rect.h,
-            },
-        }];
-        unsafe {
-            self.device
-                .raw
-                .cmd_set_scissor(self.active, 0, &vk_scissors)
-        };
+        unsafe { self.set_scissor_rects(0, &[rect.clone()]) };
     }
     unsafe fn set_stencil_reference(&mut self, value: u32) {
-        unsafe {
-            self.device.raw.cmd_set_stencil_reference(
-                self.active,
-                vk::StencilFaceFlags::FRONT_AND_BACK,
-                value,
-            )
-        };
+        unsafe { self.set_stencil_reference_faces(value, value) };
     }
     unsafe fn set_blend_constants(&mut self, color: &[f32; 4]) {
+        self.expect_active();
         unsafe { self.device.raw.cmd_set_blend_constants(self.active, color) };
     }
 
@@ -975,6 +4877,11 @@ impl crate::CommandEncoder for super::CommandEncoder {
         first_instance: u32,
         instance_count: u32,
     ) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "draw");
+        self.check_pipeline_bound(vk::PipelineBindPoint::GRAPHICS, "draw");
+        self.check_viewport_counts("draw");
+        self.flush_bind_groups();
         unsafe {
             self.device.raw.cmd_draw(
                 self.active,
@@ -984,6 +4891,7 @@ impl crate::CommandEncoder for super::CommandEncoder {
                 first_instance,
             )
         };
+        self.count(|stats| &mut stats.draws);
     }
     unsafe fn draw_indexed(
         &mut self,
@@ -993,6 +4901,11 @@ impl crate::CommandEncoder for super::CommandEncoder {
         first_instance: u32,
         instance_count: u32,
     ) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "draw_indexed");
+        self.check_pipeline_bound(vk::PipelineBindPoint::GRAPHICS, "draw_indexed");
+        self.check_viewport_counts("draw_indexed");
+        self.flush_bind_groups();
         unsafe {
             self.device.raw.cmd_draw_indexed(
                 self.active,
@@ -1003,6 +4916,7 @@ impl crate::CommandEncoder for super::CommandEncoder {
                 first_instance,
             )
         };
+        self.count(|stats| &mut stats.draws);
     }
     unsafe fn draw_indirect(
         &mut self,
@@ -1010,15 +4924,24 @@ impl crate::CommandEncoder for super::CommandEncoder {
         offset: wgt::BufferAddress,
         draw_count: u32,
     ) {
-        unsafe {
-            self.device.raw.cmd_draw_indirect(
-                self.active,
-                buffer.raw,
-                offset,
-                draw_count,
-                size_of::<wgt::DrawIndirectArgs>() as u32,
-            )
-        };
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "draw_indirect");
+        self.check_pipeline_bound(vk::PipelineBindPoint::GRAPHICS, "draw_indirect");
+        self.check_viewport_counts("draw_indirect");
+        self.flush_bind_groups();
+        // `buffer` is expected to be in the `INDIRECT` usage here.
+        let stride = size_of::<wgt::DrawIndirectArgs>() as u32;
+        if cfg!(debug_assertions) {
+            if let Err(message) =
+                check_indirect_buffer_size(buffer.size, offset, draw_count, stride)
+            {
+                super::hal_usage_error(message);
+            }
+        }
+        self.record_indirect_draws(offset, draw_count, stride, |raw, active, offset, count| {
+            unsafe { raw.cmd_draw_indirect(active, buffer.raw, offset, count, stride) };
+        });
+        self.count(|stats| &mut stats.draws);
     }
     unsafe fn draw_indexed_indirect(
         &mut self,
@@ -1026,15 +4949,24 @@ impl crate::CommandEncoder for super::CommandEncoder {
         offset: wgt::BufferAddress,
         draw_count: u32,
     ) {
-        unsafe {
-            self.device.raw.cmd_draw_indexed_indirect(
-                self.active,
-                buffer.raw,
-                offset,
-                draw_count,
-                size_of::<wgt::DrawIndexedIndirectArgs>() as u32,
-            )
-        };
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "draw_indexed_indirect");
+        self.check_pipeline_bound(vk::PipelineBindPoint::GRAPHICS, "draw_indexed_indirect");
+        self.check_viewport_counts("draw_indexed_indirect");
+        self.flush_bind_groups();
+        // `buffer` is expected to be in the `INDIRECT` usage here.
+        let stride = size_of::<wgt::DrawIndexedIndirectArgs>() as u32;
+        if cfg!(debug_assertions) {
+            if let Err(message) =
+                check_indirect_buffer_size(buffer.size, offset, draw_count, stride)
+            {
+                super::hal_usage_error(message);
+            }
+        }
+        self.record_indirect_draws(offset, draw_count, stride, |raw, active, offset, count| {
+            unsafe { raw.cmd_draw_indexed_indirect(active, buffer.raw, offset, count, stride) };
+        });
+        self.count(|stats| &mut stats.draws);
     }
     unsafe fn draw_indirect_count(
         &mut self,
@@ -1044,23 +4976,29 @@ impl crate::CommandEncoder for super::CommandEncoder {
         count_offset: wgt::BufferAddress,
         max_count: u32,
     ) {
-        let stride = size_of::<wgt::DrawIndirectArgs>() as u32;
-        match self.device.extension_fns.draw_indirect_count {
-            Some(ref t) => {
-                unsafe {
-                    t.cmd_draw_indirect_count(
-                        self.active,
-                        buffer.raw,
-                        offset,
-                        count_buffer.raw,
-                        count_offset,
-                        max_count,
-                        stride,
-                    )
-                };
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::GRAPHICS, "draw_indirect_count");
+        self.check_pipeline_bound(vk::PipelineBindPoint::GRAPHICS, "draw_indirect_count");
+        self.check_viewport_counts("draw_indirect_count");
+        if cfg!(debug_assertions) {
+            if let Err(message) = check_indirect_count_offsets(offset, count_offset) {
+                super::hal_usage_error(message);
             }
-            None => panic!("Feature `DRAW_INDIRECT_COUNT` not enabled"),
         }
+        self.flush_bind_groups();
+        let stride = size_of::<wgt::DrawIndirectArgs>() as u32;
+        unsafe {
+            self.draw_indirect_count_fns().cmd_draw_indirect_count(
+                self.active,
+                buffer.raw,
+                offset,
+                count_buffer.raw,
+                count_offset,
+                max_count,
+                stride,
+            )
+        };
+        self.count(|stats| &mut stats.draws);
     }
     unsafe fn draw_indexed_indirect_count(
         &mut self,
@@ -1070,23 +5008,36 @@ impl crate::CommandEncoder for super::CommandEncoder {
         count_offset: wgt::BufferAddress,
         max_count: u32,
     ) {
-        let stride = size_of::<wgt::DrawIndexedIndirectArgs>() as u32;
-        match self.device.extension_fns.draw_indirect_count {
-            Some(ref t) => {
-                unsafe {
-                    t.cmd_draw_indexed_indirect_count(
-                        self.active,
-                        buffer.raw,
-                        offset,
-                        count_buffer.raw,
-                        count_offset,
-                        max_count,
-                        stride,
-                    )
-                };
+        self.expect_active();
+        self.check_pass(
+            vk::PipelineBindPoint::GRAPHICS,
+            "draw_indexed_indirect_count",
+        );
+        self.check_pipeline_bound(
+            vk::PipelineBindPoint::GRAPHICS,
+            "draw_indexed_indirect_count",
+        );
+        self.check_viewport_counts("draw_indexed_indirect_count");
+        if cfg!(debug_assertions) {
+            if let Err(message) = check_indirect_count_offsets(offset, count_offset) {
+                super::hal_usage_error(message);
             }
-            None => panic!("Feature `DRAW_INDIRECT_COUNT` not enabled"),
         }
+        self.flush_bind_groups();
+        let stride = size_of::<wgt::DrawIndexedIndirectArgs>() as u32;
+        unsafe {
+            self.draw_indirect_count_fns()
+                .cmd_draw_indexed_indirect_count(
+                    self.active,
+                    buffer.raw,
+                    offset,
+                    count_buffer.raw,
+                    count_offset,
+                    max_count,
+                    stride,
+                )
+        };
+        self.count(|stats| &mut stats.draws);
     }
 
     // compute
@@ -1095,56 +5046,659 @@ impl crate::CommandEncoder for super::CommandEncoder {
         &mut self,
         desc: &crate::ComputePassDescriptor<'_, super::QuerySet>,
     ) {
+        self.expect_active();
         self.bind_point = vk::PipelineBindPoint::COMPUTE;
+        self.active_pass = Some(vk::PipelineBindPoint::COMPUTE);
+        self.reset_bound_pipelines();
         if let Some(label) = desc.label {
             unsafe { self.begin_debug_marker(label) };
             self.rpass_debug_marker_active = true;
         }
 
-        if let Some(timestamp_writes) = desc.timestamp_writes.as_ref() {
-            if let Some(index) = timestamp_writes.beginning_of_pass_write_index {
-                unsafe {
-                    self.write_timestamp(timestamp_writes.query_set, index);
-                }
+        unsafe { self.write_pass_begin_timestamp(desc.timestamp_writes.as_ref()) };
+    }
+    unsafe fn end_compute_pass(&mut self) {
+        self.expect_active();
+        self.flush_bind_groups();
+        self.active_pass = None;
+        self.reset_bound_pipelines();
+        self.write_pass_end_timestamp_if_requested();
+
+        if self.rpass_debug_marker_active {
+            unsafe { self.end_debug_marker() };
+            self.rpass_debug_marker_active = false
+        }
+    }
+
+    unsafe fn set_compute_pipeline(&mut self, pipeline: &super::ComputePipeline) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::COMPUTE, "set_compute_pipeline");
+        if self.bound_compute_pipeline == pipeline.raw {
+            return;
+        }
+        self.bound_compute_pipeline = pipeline.raw;
+        unsafe {
+            self.device.raw.cmd_bind_pipeline(
+                self.active,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline.raw,
+            )
+        };
+    }
+
+    unsafe fn dispatch(&mut self, count: [u32; 3]) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::COMPUTE, "dispatch");
+        self.check_pipeline_bound(vk::PipelineBindPoint::COMPUTE, "dispatch");
+        self.flush_bind_groups();
+        unsafe {
+            self.device
+                .raw
+                .cmd_dispatch(self.active, count[0], count[1], count[2])
+        };
+        self.count(|stats| &mut stats.dispatches);
+    }
+    unsafe fn dispatch_indirect(&mut self, buffer: &super::Buffer, offset: wgt::BufferAddress) {
+        self.expect_active();
+        self.check_pass(vk::PipelineBindPoint::COMPUTE, "dispatch_indirect");
+        self.check_pipeline_bound(vk::PipelineBindPoint::COMPUTE, "dispatch_indirect");
+        self.flush_bind_groups();
+        unsafe {
+            self.device
+                .raw
+                .cmd_dispatch_indirect(self.active, buffer.raw, offset)
+        }
+        self.count(|stats| &mut stats.dispatches);
+    }
+}
+
+/// Maps transform feedback counters to buffer and offset arrays, with null
+/// buffers for the missing counters.
+fn map_transform_feedback_counters(
+    counters: &[Option<(&super::Buffer, wgt::BufferAddress)>],
+) -> (
+    smallvec::SmallVec<[vk::Buffer; 4]>,
+    smallvec::SmallVec<[vk::DeviceSize; 4]>,
+) {
+    counters
+        .iter()
+        .map(|counter| match *counter {
+            Some((buffer, offset)) => (buffer.raw, offset),
+            None => (vk::Buffer::null(), 0),
+        })
+        .unzip()
+}
+
+/// Splits a push of `bytes` for `stages` into pushes that each use exactly the
+/// stages of the `layout_ranges` they overlap, as Vulkan requires.
+///
+/// Push constants are shared by all stages, so the stages only need to match
+/// the layout. A push within a single range stays a single push.
+fn split_push_constants(
+    layout_ranges: &[wgt::PushConstantRange],
+    stages: wgt::ShaderStages,
+    bytes: Range<u32>,
+) -> smallvec::SmallVec<[(wgt::ShaderStages, Range<u32>); 2]> {
+    let mut bounds = layout_ranges
+        .iter()
+        .flat_map(|r| [r.range.start, r.range.end])
+        .filter(|&bound| bytes.start < bound && bound < bytes.end)
+        .chain([bytes.start, bytes.end])
+        .collect::<smallvec::SmallVec<[u32; 8]>>();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut pushes = smallvec::SmallVec::<[(wgt::ShaderStages, Range<u32>); 2]>::new();
+    for segment in bounds.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        let segment_stages = layout_ranges
+            .iter()
+            .filter(|r| r.range.start <= start && end <= r.range.end)
+            .fold(wgt::ShaderStages::NONE, |acc, r| acc | r.stages);
+        // Bytes outside of all ranges can't be pushed, keep the
+        // requested stages so that validation layers report it.
+        let segment_stages = if segment_stages.is_empty() {
+            stages
+        } else {
+            segment_stages
+        };
+        match pushes.last_mut() {
+            Some((last_stages, last)) if *last_stages == segment_stages => last.end = end,
+            _ => pushes.push((segment_stages, start..end)),
+        }
+    }
+    pushes
+}
+
+/// Checks that the offsets of an indirect draw with count are multiples of 4,
+/// as Vulkan requires of both.
+fn check_indirect_count_offsets(
+    offset: wgt::BufferAddress,
+    count_offset: wgt::BufferAddress,
+) -> Result<(), String> {
+    if offset % 4 != 0 || count_offset % 4 != 0 {
+        return Err(format!(
+            "indirect draw offset {offset} and count offset {count_offset} must be multiples of 4"
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that `draw_count` indirect draws of `stride` bytes at `offset` fit
+/// in an indirect buffer of `size` bytes, if it's known.
+fn check_indirect_buffer_size(
+    size: Option<wgt::BufferAddress>,
+    offset: wgt::BufferAddress,
+    draw_count: u32,
+    stride: u32,
+) -> Result<(), String> {
+    let Some(size) = size else {
+        return Ok(());
+    };
+    let end = offset + u64::from(stride) * u64::from(draw_count);
+    if end <= size {
+        Ok(())
+    } else {
+        Err(format!(
+            "{draw_count} indirect draws of {stride} bytes at offset {offset} \
+             end at byte {end}, past the buffer size of {size}"
+        ))
+    }
+}
+
+/// Checks that textures of formats `src` and `dst` can be copied between, in
+/// debug builds.
+///
+/// Vulkan requires their texel blocks to have the same size in bytes. The
+/// block dimensions may differ, see `copy_texture_to_texture`.
+fn check_copy_formats(src: wgt::TextureFormat, dst: wgt::TextureFormat) {
+    if cfg!(debug_assertions) && src.block_copy_size(None) != dst.block_copy_size(None) {
+        super::hal_usage_error(format!(
+            "texture formats {src:?} and {dst:?} have different block sizes"
+        ));
+    }
+}
+
+/// Checks that `scissors` scissor rectangles are set for `viewports`
+/// viewports.
+fn check_scissor_count(viewports: u32, scissors: u32) -> Result<(), String> {
+    if viewports == scissors {
+        Ok(())
+    } else {
+        Err(format!(
+            "{viewports} viewport(s) but {scissors} scissor rectangle(s) are set, \
+             the counts must match"
+        ))
+    }
+}
+
+/// Returns the number of layers shared by all attachments, given with their
+/// index in the framebuffer, or 1 without attachments.
+fn check_attachment_layers(
+    mut attachments: impl Iterator<Item = (usize, u32)>,
+) -> Result<u32, String> {
+    let Some((_, layers)) = attachments.next() else {
+        return Ok(1);
+    };
+    match attachments.find(|&(_, other)| other != layers) {
+        Some((index, other)) => Err(format!(
+            "attachment {index} has {other} layers, but attachment 0 has {layers}"
+        )),
+        None => Ok(layers),
+    }
+}
+
+/// Returns the layout of `texture` in `usage`, which copies can write to.
+fn copy_dst_layout(texture: &super::Texture, usage: crate::TextureUses) -> vk::ImageLayout {
+    let layout = conv::derive_image_layout(usage, texture.format);
+    if cfg!(debug_assertions)
+        && !matches!(
+            layout,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL | vk::ImageLayout::GENERAL
+        )
+    {
+        super::hal_usage_error(format!(
+            "textures can't be copied to in the {layout:?} layout of {usage:?}"
+        ));
+    }
+    layout
+}
+
+/// Checks that a resource last transitioned to `tracked`, if known, is in
+/// the `required` usage.
+fn check_tracked_usage<U>(tracked: Option<&U>, required: U) -> Result<(), String>
+where
+    U: bitflags::Flags + std::fmt::Debug,
+{
+    match tracked {
+        Some(usage) if !usage.contains(required) => Err(format!(
+            "is in the {usage:?} usage, instead of {required:?}"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that all of the named attachments of a render pass have the same
+/// size as the first one.
+fn check_attachment_sizes(
+    mut attachments: impl Iterator<Item = (String, [u32; 2])>,
+) -> Result<(), String> {
+    let Some((first_name, first_size)) = attachments.next() else {
+        return Ok(());
+    };
+    for (name, size) in attachments {
+        if size != first_size {
+            return Err(format!(
+                "{name} is {}x{}, but {first_name} is {}x{}",
+                size[0], size[1], first_size[0], first_size[1]
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that textures with `src` and `dst` samples can be copied between.
+fn check_copy_sample_counts(src: u32, dst: u32) -> Result<(), String> {
+    if src == dst {
+        Ok(())
+    } else {
+        Err(format!(
+            "textures with {src} and {dst} samples can't be copied between, \
+             use `resolve_texture` to resolve multisampled textures instead"
+        ))
+    }
+}
+
+/// Checks that textures of formats `src` and `dst` can be blitted between
+/// with `filter`.
+///
+/// Vulkan only blits depth-stencil formats to the same format, without
+/// filtering.
+fn check_blit_formats(
+    src: wgt::TextureFormat,
+    dst: wgt::TextureFormat,
+    filter: wgt::FilterMode,
+) -> Result<(), String> {
+    if !src.is_depth_stencil_format() && !dst.is_depth_stencil_format() {
+        return Ok(());
+    }
+    if src != dst {
+        Err(format!(
+            "depth-stencil textures can't be blitted between formats {src:?} and {dst:?}, \
+             use `copy_texture_to_texture` between compatible formats instead"
+        ))
+    } else if filter != wgt::FilterMode::Nearest {
+        Err(format!(
+            "depth-stencil textures of format {src:?} can only be blitted with nearest \
+             filtering, use a multisample resolve to average samples instead"
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Converts `extent` from texels of a format with `from` block dimensions to
+/// texels of one with `to` block dimensions, keeping the number of blocks.
+fn convert_block_extent(
+    extent: &crate::CopyExtent,
+    from: (u32, u32),
+    to: (u32, u32),
+) -> crate::CopyExtent {
+    if from == to {
+        return *extent;
+    }
+    crate::CopyExtent {
+        width: extent.width.div_ceil(from.0) * to.0,
+        height: extent.height.div_ceil(from.1) * to.1,
+        depth: extent.depth,
+    }
+}
+
+/// Splits a copy of all planes of a multi-planar texture into one copy per
+/// plane, with the planes one after another in the buffer.
+///
+/// Every plane uses the `bytes_per_row` of `region`, if any, so that a
+/// driver-required row pitch applies to all of them, and `rows_per_image`
+/// counts rows of the first plane. Other copies are returned unchanged.
+fn split_plane_copies(
+    format: wgt::TextureFormat,
+    region: crate::BufferTextureCopy,
+) -> ArrayVec<crate::BufferTextureCopy, 3> {
+    let planes = match format.planes() {
+        Some(planes) if region.texture_base.aspect == crate::FormatAspects::from(format) => planes,
+        _ => return ArrayVec::from_iter([region]),
+    };
+    let (subsampling_x, subsampling_y) = format.size_multiple_requirement();
+    let mut offset = region.buffer_layout.offset;
+    (0..planes)
+        .map(|plane| {
+            let (div_x, div_y) = if plane == 0 {
+                (1, 1)
+            } else {
+                (subsampling_x, subsampling_y)
+            };
+            let aspect = [
+                crate::FormatAspects::PLANE_0,
+                crate::FormatAspects::PLANE_1,
+                crate::FormatAspects::PLANE_2,
+            ][plane as usize];
+            let size = crate::CopyExtent {
+                width: region.size.width / div_x,
+                height: region.size.height / div_y,
+                depth: region.size.depth,
+            };
+            let block_size = format.block_copy_size(Some(aspect.map())).unwrap_or(1);
+            let bytes_per_row = region
+                .buffer_layout
+                .bytes_per_row
+                .unwrap_or(size.width * block_size);
+            let rows_per_image = region
+                .buffer_layout
+                .rows_per_image
+                .map_or(size.height, |rows| rows / div_y);
+            let copy = crate::BufferTextureCopy {
+                buffer_layout: wgt::ImageDataLayout {
+                    offset,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(rows_per_image),
+                },
+                texture_base: crate::TextureCopyBase {
+                    origin: wgt::Origin3d {
+                        x: region.texture_base.origin.x / div_x,
+                        y: region.texture_base.origin.y / div_y,
+                        z: region.texture_base.origin.z,
+                    },
+                    aspect,
+                    ..region.texture_base.clone()
+                },
+                size,
+            };
+            offset += bytes_per_row as wgt::BufferAddress
+                * rows_per_image as wgt::BufferAddress
+                * size.depth as wgt::BufferAddress;
+            copy
+        })
+        .collect()
+}
+
+/// Checks that a copy of `size` at `base` fits in the mip level of a texture
+/// of `full_size`, in debug builds. The mip level extent is rounded up to
+/// whole blocks of `format`.
+///
+/// The copied aspect of a `buffer_copy` must also be a single one of those
+/// in `format`, e.g. only the stencil of a depth-stencil texture. Copies
+/// between textures copy all aspects of `format` at once.
+fn check_copy_region(
+    format: wgt::TextureFormat,
+    full_size: &crate::CopyExtent,
+    base: &crate::TextureCopyBase,
+    size: &crate::CopyExtent,
+    buffer_copy: bool,
+) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let format_aspects = crate::FormatAspects::from(format);
+    if buffer_copy && (!base.aspect.is_one() || !format_aspects.contains(base.aspect)) {
+        super::hal_usage_error(format!(
+            "copy aspect {:?} is not a single aspect of texture format {format:?}",
+            base.aspect
+        ));
+    }
+    if !buffer_copy && base.aspect != format_aspects {
+        super::hal_usage_error(format!(
+            "copy aspect {:?} is not all aspects of texture format {format:?}",
+            base.aspect
+        ));
+    }
+    let mip = full_size.at_mip_level(base.mip_level);
+    let (block_width, block_height) = format.block_dimensions();
+    let physical_width = wgt::math::align_to(mip.width, block_width);
+    let physical_height = wgt::math::align_to(mip.height, block_height);
+    if base.origin.x + size.width > physical_width
+        || base.origin.y + size.height > physical_height
+        || base.origin.z + size.depth > mip.depth
+    {
+        super::hal_usage_error(format!(
+            "copy of {size:?} at {:?} overruns mip level {} of size {mip:?}",
+            base.origin, base.mip_level
+        ));
+    }
+}
+
+/// Checks that all of `required` is contained in the `enabled` device features.
+fn check_features(
+    enabled: wgt::Features,
+    required: wgt::Features,
+) -> Result<(), super::UnsupportedFeatureError> {
+    let missing = required - enabled;
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(super::UnsupportedFeatureError(missing))
+    }
+}
+
+/// Splits `range` into an unaligned head, a body aligned to
+/// [`wgt::COPY_BUFFER_ALIGNMENT`] and an unaligned tail, any of which may be
+/// empty.
+fn split_fill_range(range: crate::MemoryRange) -> [crate::MemoryRange; 3] {
+    let align = wgt::COPY_BUFFER_ALIGNMENT;
+    let body_start = wgt::math::align_to(range.start, align).min(range.end);
+    let body_end = (range.end - range.end % align).max(body_start);
+    [
+        range.start..body_start,
+        body_start..body_end,
+        body_end..range.end,
+    ]
+}
+
+/// Splits `region` into consecutive regions of at most `max_size` bytes.
+fn split_buffer_copy(
+    region: crate::BufferCopy,
+    max_size: wgt::BufferSize,
+) -> impl Iterator<Item = crate::BufferCopy> {
+    let size = region.size.get();
+    (0..size)
+        .step_by(usize::try_from(max_size.get()).unwrap_or(usize::MAX))
+        .map(move |start| crate::BufferCopy {
+            src_offset: region.src_offset + start,
+            dst_offset: region.dst_offset + start,
+            size: wgt::BufferSize::new((size - start).min(max_size.get())).unwrap(),
+        })
+}
+
+/// Overrides the initial and final layouts of `key` with those of `layouts`,
+/// after checking that `target` supports them.
+fn apply_attachment_layouts(
+    key: &mut super::AttachmentKey,
+    layouts: super::AttachmentLayouts,
+    target: &crate::Attachment<'_, super::TextureView>,
+) {
+    let attachment = &target.view.attachment;
+    if cfg!(debug_assertions) {
+        if let Err(message) = check_attachment_layouts(layouts, attachment.view_usage, key.ops) {
+            super::hal_usage_error(message);
+        }
+    }
+    if let Some(before) = layouts.before {
+        key.initial_layout = conv::derive_image_layout(before, attachment.view_format);
+    }
+    if let Some(after) = layouts.after {
+        key.final_layout = conv::derive_image_layout(after, attachment.view_format);
+    }
+}
+
+/// Checks that the usages of `layouts` are supported by a view of
+/// `view_usage`, and don't discard contents loaded with `ops`.
+fn check_attachment_layouts(
+    layouts: super::AttachmentLayouts,
+    view_usage: crate::TextureUses,
+    ops: crate::AttachmentOps,
+) -> Result<(), String> {
+    if let Some(before) = layouts.before {
+        if before == crate::TextureUses::UNINITIALIZED {
+            if ops.contains(crate::AttachmentOps::LOAD) {
+                return Err(
+                    "an attachment without contents before the pass can't be loaded".into(),
+                );
             }
-            self.end_of_pass_timer_query = timestamp_writes
-                .end_of_pass_write_index
-                .map(|index| (timestamp_writes.query_set.raw, index));
+        } else if !view_usage.contains(before) {
+            return Err(format!(
+                "usage {before:?} before the pass is not supported by a view of {view_usage:?}"
+            ));
         }
     }
-    unsafe fn end_compute_pass(&mut self) {
-        self.write_pass_end_timestamp_if_requested();
-
-        if self.rpass_debug_marker_active {
-            unsafe { self.end_debug_marker() };
-            self.rpass_debug_marker_active = false
+    if let Some(after) = layouts.after {
+        if after == crate::TextureUses::UNINITIALIZED || !view_usage.contains(after) {
+            return Err(format!(
+                "usage {after:?} after the pass is not supported by a view of {view_usage:?}"
+            ));
         }
     }
+    Ok(())
+}
 
-    unsafe fn set_compute_pipeline(&mut self, pipeline: &super::ComputePipeline) {
-        unsafe {
-            self.device.raw.cmd_bind_pipeline(
-                self.active,
-                vk::PipelineBindPoint::COMPUTE,
-                pipeline.raw,
-            )
-        };
+/// Derives a stable pastel color from `label`, so that debug regions are
+/// told apart by color in tools like RenderDoc, consistently across frames.
+fn label_to_color(label: &str) -> [f32; 4] {
+    // FNV-1a, which unlike `DefaultHasher` is stable across Rust versions.
+    let hash = label.bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x01000193)
+    });
+    let [r, g, b, _] = hash.to_le_bytes();
+    // Halving the range towards white keeps the colors light.
+    let pastel = |channel: u8| 0.5 + f32::from(channel) / 510.0;
+    [pastel(r), pastel(g), pastel(b), 1.0]
+}
+
+/// Checks that the bind group at `index` is within `maxBoundDescriptorSets`.
+fn check_bind_group_index(index: u32, max_sets: u32) -> Result<(), String> {
+    if index < max_sets {
+        Ok(())
+    } else {
+        Err(format!(
+            "bind group {index} is out of the {max_sets} descriptor sets the device can bind"
+        ))
     }
+}
 
-    unsafe fn dispatch(&mut self, count: [u32; 3]) {
-        unsafe {
-            self.device
-                .raw
-                .cmd_dispatch(self.active, count[0], count[1], count[2])
-        };
+/// Checks that `size` bytes of push constants at `offset` fit in
+/// `maxPushConstantsSize`.
+fn check_push_constants_size(offset: u32, size: usize, max_size: u32) -> Result<(), String> {
+    let end = u64::from(offset) + size as u64;
+    if end <= u64::from(max_size) {
+        Ok(())
+    } else {
+        Err(format!(
+            "push constants at bytes {offset}..{end} exceed the device limit of {max_size} bytes"
+        ))
     }
-    unsafe fn dispatch_indirect(&mut self, buffer: &super::Buffer, offset: wgt::BufferAddress) {
-        unsafe {
-            self.device
-                .raw
-                .cmd_dispatch_indirect(self.active, buffer.raw, offset)
+}
+
+/// Checks that a shading rate attachment texel size is a power of two within
+/// the `min..=max` sizes supported by the device.
+fn check_shading_rate_texel_size(
+    size: [u32; 2],
+    min: [u32; 2],
+    max: [u32; 2],
+) -> Result<(), String> {
+    let valid = (0..2).all(|i| size[i].is_power_of_two() && (min[i]..=max[i]).contains(&size[i]));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "shading rate attachment texel size {size:?} is not a power of two between {min:?} and {max:?}"
+        ))
+    }
+}
+
+/// Access flags that write memory.
+const WRITE_ACCESS_FLAGS: vk::AccessFlags = vk::AccessFlags::from_raw(
+    vk::AccessFlags::SHADER_WRITE.as_raw()
+        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::TRANSFER_WRITE.as_raw()
+        | vk::AccessFlags::HOST_WRITE.as_raw()
+        | vk::AccessFlags::MEMORY_WRITE.as_raw()
+        | vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR.as_raw(),
+);
+
+/// Pushes `barrier` to `barriers`, merging it into the previous barrier if
+/// both transition the same or adjacent subresources of one image the same way.
+fn push_image_barrier(
+    barriers: &mut Vec<vk::ImageMemoryBarrier<'static>>,
+    barrier: vk::ImageMemoryBarrier<'static>,
+) {
+    if let Some(last) = barriers.last_mut() {
+        if last.image == barrier.image
+            && last.old_layout == barrier.old_layout
+            && last.new_layout == barrier.new_layout
+            && last.src_access_mask == barrier.src_access_mask
+            && last.dst_access_mask == barrier.dst_access_mask
+            && last.src_queue_family_index == barrier.src_queue_family_index
+            && last.dst_queue_family_index == barrier.dst_queue_family_index
+            && merge_subresource_ranges(&mut last.subresource_range, &barrier.subresource_range)
+        {
+            return;
         }
     }
+    barriers.push(barrier);
+}
+
+/// Extends `range` to also cover `next`, if `next` is contained in it or their
+/// union is a single range.
+fn merge_subresource_ranges(
+    range: &mut vk::ImageSubresourceRange,
+    next: &vk::ImageSubresourceRange,
+) -> bool {
+    if range.aspect_mask != next.aspect_mask {
+        return false;
+    }
+    let same_levels =
+        range.base_mip_level == next.base_mip_level && range.level_count == next.level_count;
+    let same_layers =
+        range.base_array_layer == next.base_array_layer && range.layer_count == next.layer_count;
+    // Remaining counts are all-ones, so these ends can't overflow as `u64`.
+    let levels_end = |r: &vk::ImageSubresourceRange| r.base_mip_level as u64 + r.level_count as u64;
+    let layers_end =
+        |r: &vk::ImageSubresourceRange| r.base_array_layer as u64 + r.layer_count as u64;
+    let contained = next.base_mip_level >= range.base_mip_level
+        && levels_end(next) <= levels_end(range)
+        && next.base_array_layer >= range.base_array_layer
+        && layers_end(next) <= layers_end(range);
+    if contained {
+        true
+    } else if same_levels
+        && range.layer_count != vk::REMAINING_ARRAY_LAYERS
+        && range.base_array_layer + range.layer_count == next.base_array_layer
+    {
+        range.layer_count = match next.layer_count {
+            vk::REMAINING_ARRAY_LAYERS => vk::REMAINING_ARRAY_LAYERS,
+            count => range.layer_count + count,
+        };
+        true
+    } else if same_layers
+        && range.level_count != vk::REMAINING_MIP_LEVELS
+        && range.base_mip_level + range.level_count == next.base_mip_level
+    {
+        range.level_count = match next.level_count {
+            vk::REMAINING_MIP_LEVELS => vk::REMAINING_MIP_LEVELS,
+            count => range.level_count + count,
+        };
+        true
+    } else {
+        false
+    }
+}
+
+/// Returns the offsets of `draw_count` tightly packed indirect draws starting at `offset`.
+fn indirect_draw_offsets(
+    offset: wgt::BufferAddress,
+    draw_count: u32,
+    stride: u32,
+) -> impl Iterator<Item = wgt::BufferAddress> {
+    (0..draw_count).map(move |i| offset + i as wgt::BufferAddress * stride as wgt::BufferAddress)
 }
 
 #[test]
@@ -1154,3 +5708,799 @@ fn check_dst_image_layout() {
         DST_IMAGE_LAYOUT
     );
 }
+
+#[test]
+fn check_storage_image_layout() {
+    for usage in [
+        crate::TextureUses::STORAGE_READ,
+        crate::TextureUses::STORAGE_READ_WRITE,
+    ] {
+        assert_eq!(
+            conv::derive_image_layout(usage, wgt::TextureFormat::Rgba8Unorm),
+            vk::ImageLayout::GENERAL
+        );
+    }
+}
+
+#[test]
+fn check_unsupported_features() {
+    let enabled = wgt::Features::MULTI_DRAW_INDIRECT | wgt::Features::MULTI_DRAW_INDIRECT_COUNT;
+    assert_eq!(
+        check_features(enabled, wgt::Features::MULTI_DRAW_INDIRECT_COUNT),
+        Ok(())
+    );
+    assert_eq!(
+        check_features(
+            enabled,
+            wgt::Features::MULTI_DRAW_INDIRECT_COUNT
+                | wgt::Features::RAY_TRACING_ACCELERATION_STRUCTURE
+        ),
+        Err(super::UnsupportedFeatureError(
+            wgt::Features::RAY_TRACING_ACCELERATION_STRUCTURE
+        ))
+    );
+}
+
+/// Creates a texture with a dummy handle, for tests of the host-side
+/// bookkeeping of [`super::Texture`].
+#[cfg(test)]
+fn test_texture(
+    format: wgt::TextureFormat,
+    copy_size: crate::CopyExtent,
+    mip_level_count: u32,
+    array_layer_count: u32,
+) -> super::Texture {
+    use ash::vk::Handle as _;
+
+    super::Texture {
+        raw: vk::Image::from_raw(1),
+        drop_guard: None,
+        block: None,
+        usage: crate::TextureUses::COPY_SRC | crate::TextureUses::COPY_DST,
+        format,
+        raw_flags: vk::ImageCreateFlags::empty(),
+        copy_size,
+        sample_count: 1,
+        mip_level_count,
+        array_layer_count,
+        view_formats: Vec::new(),
+    }
+}
+
+#[test]
+fn check_compressed_buffer_row_length() {
+    // 256x256 BC7 texture: 64 blocks of 4x4 texels and 16 bytes per row.
+    let texture = test_texture(
+        wgt::TextureFormat::Bc7RgbaUnorm,
+        crate::CopyExtent {
+            width: 256,
+            height: 256,
+            depth: 1,
+        },
+        1,
+        1,
+    );
+    let region = crate::BufferTextureCopy {
+        buffer_layout: wgt::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(64 * 16),
+            rows_per_image: Some(64),
+        },
+        texture_base: crate::TextureCopyBase {
+            mip_level: 0,
+            array_layer: 0,
+            origin: wgt::Origin3d::ZERO,
+            aspect: crate::FormatAspects::COLOR,
+        },
+        size: crate::CopyExtent {
+            width: 256,
+            height: 256,
+            depth: 1,
+        },
+    };
+
+    let copies = texture
+        .map_buffer_copies(std::iter::once(region))
+        .collect::<Vec<_>>();
+    assert_eq!(copies[0].buffer_row_length, 256);
+    assert_eq!(copies[0].buffer_image_height, 256);
+}
+
+#[test]
+fn check_indirect_draw_stride() {
+    let stride = size_of::<wgt::DrawIndirectArgs>() as u32;
+    assert_eq!(stride, 16);
+    assert_eq!(stride as usize, size_of::<vk::DrawIndirectCommand>());
+    assert_eq!(
+        indirect_draw_offsets(256, 2, stride).collect::<Vec<_>>(),
+        [256, 272]
+    );
+}
+
+#[test]
+fn check_indexed_indirect_draw_offsets() {
+    let stride = size_of::<wgt::DrawIndexedIndirectArgs>() as u32;
+    assert_eq!(stride, 20);
+    assert_eq!(stride as usize, size_of::<vk::DrawIndexedIndirectCommand>());
+    assert_eq!(
+        indirect_draw_offsets(0, 3, stride).collect::<Vec<_>>(),
+        [0, 20, 40]
+    );
+}
+
+#[test]
+fn check_merge_image_barriers() {
+    use ash::vk::Handle as _;
+
+    let barrier = |image: u64, base_mip_level: u32, base_array_layer: u32| {
+        vk::ImageMemoryBarrier::default()
+            .image(vk::Image::from_raw(image))
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level,
+                level_count: 1,
+                base_array_layer,
+                layer_count: 1,
+            })
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    };
+
+    let mut barriers = Vec::new();
+    // Adjacent layers, a duplicate and then the next mip level of both layers.
+    push_image_barrier(&mut barriers, barrier(1, 0, 0));
+    push_image_barrier(&mut barriers, barrier(1, 0, 1));
+    push_image_barrier(&mut barriers, barrier(1, 0, 1));
+    assert_eq!(barriers.len(), 1);
+    assert_eq!(barriers[0].subresource_range.layer_count, 2);
+
+    // Non-adjacent layers and other images are kept apart.
+    push_image_barrier(&mut barriers, barrier(1, 0, 3));
+    push_image_barrier(&mut barriers, barrier(2, 0, 4));
+    assert_eq!(barriers.len(), 3);
+}
+
+#[test]
+fn check_1d_texture_copy_extent() {
+    // wgpu doesn't have 1D array textures, so the copy always targets layer 0.
+    let size = wgt::Extent3d {
+        width: 64,
+        height: 4,
+        depth_or_array_layers: 3,
+    };
+    let copy_size = crate::CopyExtent::map_extent_to_copy_size(&size, wgt::TextureDimension::D1);
+    let texture = test_texture(wgt::TextureFormat::R8Unorm, copy_size, 1, 1);
+    let region = crate::BufferTextureCopy {
+        buffer_layout: wgt::ImageDataLayout::default(),
+        texture_base: crate::TextureCopyBase {
+            mip_level: 0,
+            array_layer: 0,
+            origin: wgt::Origin3d::ZERO,
+            aspect: crate::FormatAspects::COLOR,
+        },
+        size: copy_size,
+    };
+
+    let copies = texture
+        .map_buffer_copies(std::iter::once(region))
+        .collect::<Vec<_>>();
+    assert_eq!(copies[0].image_extent.width, 64);
+    assert_eq!(copies[0].image_extent.height, 1);
+    assert_eq!(copies[0].image_extent.depth, 1);
+    assert_eq!(copies[0].image_subresource.base_array_layer, 0);
+    assert_eq!(copies[0].image_subresource.layer_count, 1);
+}
+
+#[test]
+fn check_3d_buffer_image_height() {
+    let texture = test_texture(
+        wgt::TextureFormat::Rgba8Unorm,
+        crate::CopyExtent {
+            width: 4,
+            height: 4,
+            depth: 4,
+        },
+        1,
+        1,
+    );
+    let region = |rows_per_image| crate::BufferTextureCopy {
+        buffer_layout: wgt::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(16),
+            rows_per_image,
+        },
+        texture_base: crate::TextureCopyBase {
+            mip_level: 0,
+            array_layer: 0,
+            origin: wgt::Origin3d::ZERO,
+            aspect: crate::FormatAspects::COLOR,
+        },
+        size: texture.copy_size,
+    };
+
+    let copies = texture
+        .map_buffer_copies([region(None), region(Some(8))].into_iter())
+        .collect::<Vec<_>>();
+    // A zero image height means the slices are tightly packed.
+    assert_eq!(copies[0].buffer_image_height, 0);
+    assert_eq!(copies[1].buffer_image_height, 8);
+    for copy in copies {
+        assert_eq!(copy.image_extent.depth, 4);
+        assert_eq!(copy.image_subresource.layer_count, 1);
+    }
+}
+
+#[test]
+fn check_stencil_aspect_buffer_copy() {
+    let texture = test_texture(
+        wgt::TextureFormat::Depth32FloatStencil8,
+        crate::CopyExtent {
+            width: 64,
+            height: 64,
+            depth: 1,
+        },
+        1,
+        1,
+    );
+    // Each plane of a combined format is copied on its own, with the texel
+    // size of that plane: 1 byte for stencil.
+    let region = crate::BufferTextureCopy {
+        buffer_layout: wgt::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(256),
+            rows_per_image: None,
+        },
+        texture_base: crate::TextureCopyBase {
+            mip_level: 0,
+            array_layer: 0,
+            origin: wgt::Origin3d::ZERO,
+            aspect: crate::FormatAspects::STENCIL,
+        },
+        size: texture.copy_size,
+    };
+
+    let copies = texture
+        .map_buffer_copies(std::iter::once(region))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        copies[0].image_subresource.aspect_mask,
+        vk::ImageAspectFlags::STENCIL
+    );
+    assert_eq!(copies[0].buffer_row_length, 256);
+}
+
+#[test]
+fn check_split_plane_copies() {
+    let region = crate::BufferTextureCopy {
+        buffer_layout: wgt::ImageDataLayout {
+            offset: 256,
+            bytes_per_row: Some(2048),
+            rows_per_image: None,
+        },
+        texture_base: crate::TextureCopyBase {
+            mip_level: 0,
+            array_layer: 0,
+            origin: wgt::Origin3d::ZERO,
+            aspect: crate::FormatAspects::PLANE_0 | crate::FormatAspects::PLANE_1,
+        },
+        size: crate::CopyExtent {
+            width: 1920,
+            height: 1080,
+            depth: 1,
+        },
+    };
+    let planes = split_plane_copies(wgt::TextureFormat::NV12, region.clone());
+    assert_eq!(planes.len(), 2);
+    assert_eq!(planes[0].texture_base.aspect, crate::FormatAspects::PLANE_0);
+    assert_eq!(planes[0].buffer_layout.offset, 256);
+    assert_eq!(planes[1].texture_base.aspect, crate::FormatAspects::PLANE_1);
+    assert_eq!(planes[1].buffer_layout.offset, 256 + 2048 * 1080);
+    assert_eq!(planes[1].buffer_layout.bytes_per_row, Some(2048));
+    assert_eq!((planes[1].size.width, planes[1].size.height), (960, 540));
+
+    // Copies of a single plane, or of other formats, are left alone.
+    let mut luma = region;
+    luma.texture_base.aspect = crate::FormatAspects::PLANE_0;
+    assert_eq!(split_plane_copies(wgt::TextureFormat::NV12, luma).len(), 1);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "overruns mip level 2")]
+fn check_copy_region_out_of_mip_bounds() {
+    let texture = test_texture(
+        wgt::TextureFormat::Rgba8Unorm,
+        crate::CopyExtent {
+            width: 64,
+            height: 64,
+            depth: 1,
+        },
+        7,
+        1,
+    );
+    // Mip level 2 is 16x16, so this copy overruns it by 8 texels.
+    let region = crate::BufferTextureCopy {
+        buffer_layout: wgt::ImageDataLayout::default(),
+        texture_base: crate::TextureCopyBase {
+            mip_level: 2,
+            array_layer: 0,
+            origin: wgt::Origin3d { x: 8, y: 0, z: 0 },
+            aspect: crate::FormatAspects::COLOR,
+        },
+        size: crate::CopyExtent {
+            width: 16,
+            height: 16,
+            depth: 1,
+        },
+    };
+
+    let _ = texture
+        .map_buffer_copies(std::iter::once(region))
+        .collect::<Vec<_>>();
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "is not a single aspect")]
+fn check_copy_aspect_missing_from_format() {
+    let texture = test_texture(
+        wgt::TextureFormat::Depth32Float,
+        crate::CopyExtent {
+            width: 64,
+            height: 64,
+            depth: 1,
+        },
+        1,
+        1,
+    );
+    // A depth-only format has no stencil plane to read back.
+    let region = crate::BufferTextureCopy {
+        buffer_layout: wgt::ImageDataLayout::default(),
+        texture_base: crate::TextureCopyBase {
+            mip_level: 0,
+            array_layer: 0,
+            origin: wgt::Origin3d::ZERO,
+            aspect: crate::FormatAspects::STENCIL,
+        },
+        size: texture.copy_size,
+    };
+
+    let _ = texture
+        .map_buffer_copies(std::iter::once(region))
+        .collect::<Vec<_>>();
+}
+
+#[test]
+fn check_convert_block_extent() {
+    let texels = crate::CopyExtent {
+        width: 64,
+        height: 30,
+        depth: 1,
+    };
+    // 16x8 blocks of 4x4 BC texels are 16x8 texels of an uncompressed format.
+    let blocks = convert_block_extent(&texels, (4, 4), (1, 1));
+    assert_eq!((blocks.width, blocks.height, blocks.depth), (16, 8, 1));
+    let back = convert_block_extent(&blocks, (1, 1), (4, 4));
+    assert_eq!((back.width, back.height, back.depth), (64, 32, 1));
+}
+
+#[test]
+fn check_empty_buffer_copies_are_skipped() {
+    let texture = test_texture(
+        wgt::TextureFormat::Rgba8Unorm,
+        crate::CopyExtent {
+            width: 64,
+            height: 64,
+            depth: 1,
+        },
+        1,
+        1,
+    );
+    let region = |width| crate::BufferTextureCopy {
+        buffer_layout: wgt::ImageDataLayout::default(),
+        texture_base: crate::TextureCopyBase {
+            mip_level: 0,
+            array_layer: 0,
+            origin: wgt::Origin3d::ZERO,
+            aspect: crate::FormatAspects::COLOR,
+        },
+        size: crate::CopyExtent {
+            width,
+            height: 4,
+            depth: 1,
+        },
+    };
+
+    let copies = texture
+        .map_buffer_copies([region(0), region(8), region(0)].into_iter())
+        .collect::<Vec<_>>();
+    assert_eq!(copies.len(), 1);
+    assert_eq!(copies[0].image_extent.width, 8);
+}
+
+#[test]
+fn check_misaligned_indirect_count_offset() {
+    assert_eq!(check_indirect_count_offsets(16, 8), Ok(()));
+    assert!(check_indirect_count_offsets(16, 6)
+        .unwrap_err()
+        .contains("count offset 6 must be multiples of 4"));
+}
+
+#[test]
+fn check_copy_compatible_formats() {
+    use wgt::TextureFormat as Tf;
+    check_copy_formats(Tf::Rgba8Unorm, Tf::Bgra8Unorm);
+    check_copy_formats(Tf::Bc1RgbaUnorm, Tf::Rg32Uint);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "have different block sizes")]
+fn check_copy_incompatible_formats() {
+    check_copy_formats(
+        wgt::TextureFormat::Rgba8Unorm,
+        wgt::TextureFormat::Rgba16Float,
+    );
+}
+
+#[test]
+fn check_split_push_constants() {
+    use wgt::ShaderStages as Ss;
+    let range = |stages, range| wgt::PushConstantRange { stages, range };
+
+    let single = [range(Ss::VERTEX | Ss::FRAGMENT, 0..128)];
+    assert_eq!(
+        split_push_constants(&single, Ss::VERTEX | Ss::FRAGMENT, 16..64).as_slice(),
+        &[(Ss::VERTEX | Ss::FRAGMENT, 16..64)]
+    );
+
+    let disjoint = [range(Ss::VERTEX, 0..64), range(Ss::FRAGMENT, 64..128)];
+    assert_eq!(
+        split_push_constants(&disjoint, Ss::VERTEX | Ss::FRAGMENT, 0..128).as_slice(),
+        &[(Ss::VERTEX, 0..64), (Ss::FRAGMENT, 64..128)]
+    );
+
+    let overlapping = [range(Ss::VERTEX, 0..128), range(Ss::FRAGMENT, 64..128)];
+    assert_eq!(
+        split_push_constants(&overlapping, Ss::VERTEX | Ss::FRAGMENT, 32..128).as_slice(),
+        &[(Ss::VERTEX, 32..64), (Ss::VERTEX | Ss::FRAGMENT, 64..128)]
+    );
+}
+
+#[test]
+fn check_padded_bytes_per_row() {
+    let texture = |format| {
+        test_texture(
+            format,
+            crate::CopyExtent {
+                width: 100,
+                height: 100,
+                depth: 1,
+            },
+            1,
+            1,
+        )
+    };
+
+    // 100 texels of 4 bytes, padded to 256 bytes.
+    let rgba = texture(wgt::TextureFormat::Rgba8Unorm);
+    assert_eq!(
+        rgba.padded_bytes_per_row(100, wgt::TextureAspect::All, 256),
+        512
+    );
+    assert_eq!(
+        rgba.padded_bytes_per_row(64, wgt::TextureAspect::All, 1),
+        256
+    );
+
+    // 25 blocks of 16 bytes, padded to 96 bytes, then to the block size.
+    let bc7 = texture(wgt::TextureFormat::Bc7RgbaUnorm);
+    assert_eq!(
+        bc7.padded_bytes_per_row(100, wgt::TextureAspect::All, 96),
+        480
+    );
+
+    let depth_stencil = texture(wgt::TextureFormat::Depth32FloatStencil8);
+    assert_eq!(
+        depth_stencil.padded_bytes_per_row(100, wgt::TextureAspect::StencilOnly, 1),
+        100
+    );
+}
+
+#[test]
+fn check_push_constants_limit() {
+    assert_eq!(check_push_constants_size(0, 128, 128), Ok(()));
+    assert_eq!(check_push_constants_size(64, 64, 128), Ok(()));
+    assert!(check_push_constants_size(0, 256, 128).is_err());
+    assert!(check_push_constants_size(124, 8, 128).is_err());
+}
+
+#[test]
+fn check_bind_group_limit() {
+    assert_eq!(check_bind_group_index(3, 4), Ok(()));
+    assert!(check_bind_group_index(4, 4).is_err());
+}
+
+#[test]
+fn check_copy_usages() {
+    let copy_src = crate::BufferUses::COPY_SRC;
+    assert_eq!(check_tracked_usage(None, copy_src), Ok(()));
+    assert_eq!(check_tracked_usage(Some(&copy_src), copy_src), Ok(()));
+    assert!(check_tracked_usage(Some(&crate::BufferUses::COPY_DST), copy_src).is_err());
+    assert!(check_tracked_usage(
+        Some(&crate::TextureUses::RESOURCE),
+        crate::TextureUses::COPY_DST
+    )
+    .is_err());
+}
+
+#[test]
+fn check_viewport_scissor_counts() {
+    assert_eq!(check_scissor_count(1, 1), Ok(()));
+    assert_eq!(check_scissor_count(4, 4), Ok(()));
+    assert!(check_scissor_count(4, 1).is_err());
+    assert!(check_scissor_count(1, 4).is_err());
+}
+
+#[test]
+fn check_render_pass_attachment_layers() {
+    assert_eq!(check_attachment_layers(std::iter::empty()), Ok(1));
+    assert_eq!(check_attachment_layers([(0, 6), (1, 6)].into_iter()), Ok(6));
+    assert_eq!(
+        check_attachment_layers([(0, 6), (1, 6), (2, 1)].into_iter()),
+        Err("attachment 2 has 1 layers, but attachment 0 has 6".to_string())
+    );
+}
+
+#[test]
+fn check_render_pass_attachment_sizes() {
+    let full = ("color attachment 0".to_string(), [1920, 1080]);
+    let depth = ("depth-stencil attachment".to_string(), [960, 540]);
+    assert_eq!(check_attachment_sizes([full.clone()].into_iter()), Ok(()));
+    assert_eq!(
+        check_attachment_sizes([full, depth].into_iter()),
+        Err("depth-stencil attachment is 960x540, but color attachment 0 is 1920x1080".to_string())
+    );
+}
+
+#[test]
+fn check_multisampled_copies() {
+    assert_eq!(check_copy_sample_counts(4, 4), Ok(()));
+    assert!(check_copy_sample_counts(4, 1).is_err());
+}
+
+#[test]
+fn check_depth_stencil_blits() {
+    use wgt::{FilterMode as Fm, TextureFormat as Tf};
+    assert!(check_blit_formats(Tf::Rgba8Unorm, Tf::Bgra8UnormSrgb, Fm::Linear).is_ok());
+    assert!(check_blit_formats(Tf::Depth32Float, Tf::Depth32Float, Fm::Nearest).is_ok());
+    assert!(check_blit_formats(Tf::Depth32Float, Tf::Depth32Float, Fm::Linear).is_err());
+    assert!(check_blit_formats(Tf::Depth32Float, Tf::R32Float, Fm::Nearest).is_err());
+}
+
+#[test]
+fn check_split_buffer_copy() {
+    let region = crate::BufferCopy {
+        src_offset: 4,
+        dst_offset: 100,
+        size: wgt::BufferSize::new(10).unwrap(),
+    };
+    let chunks = split_buffer_copy(region, wgt::BufferSize::new(4).unwrap())
+        .map(|r| (r.src_offset, r.dst_offset, r.size.get()))
+        .collect::<Vec<_>>();
+    assert_eq!(chunks, [(4, 100, 4), (8, 104, 4), (12, 108, 2)]);
+    assert_eq!(
+        split_buffer_copy(region, wgt::BufferSize::new(64).unwrap()).count(),
+        1
+    );
+}
+
+#[test]
+fn check_split_fill_range() {
+    assert_eq!(split_fill_range(0..16), [0..0, 0..16, 16..16]);
+    assert_eq!(split_fill_range(1..15), [1..4, 4..12, 12..15]);
+    assert_eq!(split_fill_range(5..7), [5..7, 7..7, 7..7]);
+    assert_eq!(split_fill_range(4..7), [4..4, 4..4, 4..7]);
+}
+
+#[test]
+fn check_indirect_buffer_bounds() {
+    let stride = size_of::<wgt::DrawIndirectArgs>() as u32;
+    assert_eq!(check_indirect_buffer_size(Some(64), 16, 3, stride), Ok(()));
+    assert!(check_indirect_buffer_size(Some(64), 20, 3, stride).is_err());
+    assert!(check_indirect_buffer_size(Some(64), 0, 5, stride).is_err());
+    assert_eq!(check_indirect_buffer_size(None, 0, 5, stride), Ok(()));
+}
+
+#[test]
+fn check_label_to_color() {
+    assert_eq!(label_to_color("shadows"), label_to_color("shadows"));
+    assert_ne!(label_to_color("shadows"), label_to_color("lighting"));
+    for channel in label_to_color("lighting") {
+        assert!((0.5..=1.0).contains(&channel));
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "overruns mip level 2")]
+fn check_copy_region_unscaled_to_smaller_mip() {
+    let full_size = crate::CopyExtent {
+        width: 64,
+        height: 64,
+        depth: 1,
+    };
+    // The extent of mip level 0 of the source doesn't fit in mip level 2 of
+    // the destination, which is 16x16.
+    let dst_base = crate::TextureCopyBase {
+        mip_level: 2,
+        array_layer: 0,
+        origin: wgt::Origin3d::ZERO,
+        aspect: crate::FormatAspects::COLOR,
+    };
+    check_copy_region(
+        wgt::TextureFormat::Rgba8Unorm,
+        &full_size,
+        &dst_base,
+        &full_size,
+        false,
+    );
+}
+
+#[test]
+fn check_depth_stencil_texture_copy_region() {
+    let full_size = crate::CopyExtent {
+        width: 64,
+        height: 64,
+        depth: 1,
+    };
+    // Copies between depth-stencil textures copy both aspects at once.
+    let base = crate::TextureCopyBase {
+        mip_level: 0,
+        array_layer: 0,
+        origin: wgt::Origin3d::ZERO,
+        aspect: crate::FormatAspects::DEPTH | crate::FormatAspects::STENCIL,
+    };
+    check_copy_region(
+        wgt::TextureFormat::Depth24PlusStencil8,
+        &full_size,
+        &base,
+        &full_size,
+        false,
+    );
+}
+
+#[test]
+fn check_attachment_layout_usages() {
+    use crate::{AttachmentOps as Ao, TextureUses as Tu};
+
+    let view_usage = Tu::COLOR_TARGET | Tu::RESOURCE;
+    let layouts = |before, after| super::AttachmentLayouts { before, after };
+    assert_eq!(
+        check_attachment_layouts(
+            layouts(Some(Tu::COLOR_TARGET), Some(Tu::RESOURCE)),
+            view_usage,
+            Ao::LOAD
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        check_attachment_layouts(
+            layouts(Some(Tu::UNINITIALIZED), None),
+            view_usage,
+            Ao::STORE
+        ),
+        Ok(())
+    );
+    assert!(
+        check_attachment_layouts(layouts(Some(Tu::UNINITIALIZED), None), view_usage, Ao::LOAD)
+            .is_err()
+    );
+    assert!(
+        check_attachment_layouts(layouts(None, Some(Tu::COPY_SRC)), view_usage, Ao::STORE).is_err()
+    );
+}
+
+#[test]
+fn check_shading_rate_texel_sizes() {
+    assert!(check_shading_rate_texel_size([8, 16], [8, 8], [32, 32]).is_ok());
+    assert!(check_shading_rate_texel_size([12, 16], [8, 8], [32, 32]).is_err());
+    assert!(check_shading_rate_texel_size([4, 16], [8, 8], [32, 32]).is_err());
+}
+
+#[test]
+fn check_mip_levels_barrier() {
+    let texture = test_texture(
+        wgt::TextureFormat::Rgba8Unorm,
+        crate::CopyExtent {
+            width: 64,
+            height: 64,
+            depth: 1,
+        },
+        7,
+        1,
+    );
+    let usage = crate::TextureUses::COPY_DST..crate::TextureUses::RESOURCE;
+    let barrier = texture.mip_levels_barrier(1..7, usage.clone());
+    assert_eq!(barrier.range.base_mip_level, 1);
+    assert_eq!(barrier.range.mip_level_count, Some(6));
+    assert_eq!(barrier.range.array_layer_count, None);
+    let barrier = texture.mip_levels_barrier(4..16, usage);
+    assert_eq!(barrier.range.mip_level_count, Some(3));
+}
+
+#[test]
+fn check_texture_subresources() {
+    let texture = test_texture(
+        wgt::TextureFormat::Depth24PlusStencil8,
+        crate::CopyExtent {
+            width: 64,
+            height: 64,
+            depth: 2,
+        },
+        3,
+        2,
+    );
+    let subresources = texture
+        .subresources()
+        .map(|range| (range.base_mip_level, range.base_array_layer))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        subresources,
+        [(0, 0), (0, 1), (1, 0), (1, 1), (2, 0), (2, 1)]
+    );
+    assert!(texture
+        .subresources()
+        .all(|range| range.aspect == wgt::TextureAspect::All));
+}
+
+#[test]
+fn check_full_subresource_ranges() {
+    let texture = test_texture(
+        wgt::TextureFormat::Depth24PlusStencil8,
+        crate::CopyExtent {
+            width: 64,
+            height: 64,
+            depth: 2,
+        },
+        3,
+        2,
+    );
+    let mut range = wgt::ImageSubresourceRange::default();
+    assert!(texture.covers_all_subresources(&range));
+    range.mip_level_count = Some(3);
+    range.array_layer_count = Some(2);
+    assert!(texture.covers_all_subresources(&range));
+    range.base_mip_level = 1;
+    range.mip_level_count = Some(2);
+    assert!(!texture.covers_all_subresources(&range));
+    range.base_mip_level = 0;
+    range.aspect = wgt::TextureAspect::DepthOnly;
+    assert!(!texture.covers_all_subresources(&range));
+}
+
+#[test]
+fn check_interleaved_query_resets() {
+    use ash::vk::Handle as _;
+    let pool = vk::QueryPool::from_raw(1);
+    let mut encoding = super::EncodingState::new();
+    encoding.auto_reset_queries = Some(Default::default());
+
+    let mut first = encoding.begin();
+    assert!(first.needs_query_reset(pool, 0));
+    // Suspending `first` begins `second`, which must reset the query again.
+    let mut second = first.begin();
+    assert!(second.needs_query_reset(pool, 0));
+    assert!(second.needs_query_reset(pool, 1));
+    assert!(!second.needs_query_reset(pool, 0));
+    // Resuming `first` doesn't see the resets of `second`.
+    let mut first = first.resume(&second);
+    assert!(!first.needs_query_reset(pool, 0));
+    assert!(first.needs_query_reset(pool, 1));
+
+    // Disabling the resets while `first` is suspended applies to it.
+    let second = first.begin();
+    let mut first = first.resume(&super::EncodingState::new());
+    assert!(!first.needs_query_reset(pool, 2));
+    assert!(second.auto_reset_queries.unwrap().is_empty());
+}
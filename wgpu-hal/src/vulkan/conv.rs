@@ -184,9 +184,12 @@ impl crate::Attachment<'_, super::TextureView> {
         ops: crate::AttachmentOps,
         caps: &super::PrivateCapabilities,
     ) -> super::AttachmentKey {
+        let layout = derive_image_layout(self.usage, self.view.attachment.view_format);
         super::AttachmentKey {
             format: caps.map_texture_format(self.view.attachment.view_format),
-            layout: derive_image_layout(self.usage, self.view.attachment.view_format),
+            layout,
+            initial_layout: layout,
+            final_layout: layout,
             ops,
         }
     }
@@ -194,26 +197,78 @@ impl crate::Attachment<'_, super::TextureView> {
 
 impl crate::ColorAttachment<'_, super::TextureView> {
     pub(super) unsafe fn make_vk_clear_color(&self) -> vk::ClearColorValue {
-        let cv = &self.clear_value;
-        match self
-            .target
-            .view
-            .attachment
-            .view_format
-            .sample_type(None, None)
-            .unwrap()
-        {
-            wgt::TextureSampleType::Float { .. } => vk::ClearColorValue {
-                float32: [cv.r as f32, cv.g as f32, cv.b as f32, cv.a as f32],
-            },
-            wgt::TextureSampleType::Sint => vk::ClearColorValue {
-                int32: [cv.r as i32, cv.g as i32, cv.b as i32, cv.a as i32],
-            },
-            wgt::TextureSampleType::Uint => vk::ClearColorValue {
-                uint32: [cv.r as u32, cv.g as u32, cv.b as u32, cv.a as u32],
-            },
-            wgt::TextureSampleType::Depth => unreachable!(),
-        }
+        map_clear_color(self.target.view.attachment.view_format, &self.clear_value)
+    }
+}
+
+/// The standard sample locations of `sample_count` samples, from the
+/// "Standard sample locations" table of the Vulkan specification.
+pub fn standard_sample_locations(sample_count: u32) -> &'static [[f32; 2]] {
+    match sample_count {
+        1 => &[[0.5, 0.5]],
+        2 => &[[0.75, 0.75], [0.25, 0.25]],
+        4 => &[
+            [0.375, 0.125],
+            [0.875, 0.375],
+            [0.125, 0.625],
+            [0.625, 0.875],
+        ],
+        8 => &[
+            [0.5625, 0.3125],
+            [0.4375, 0.6875],
+            [0.8125, 0.5625],
+            [0.3125, 0.1875],
+            [0.1875, 0.8125],
+            [0.0625, 0.4375],
+            [0.6875, 0.9375],
+            [0.9375, 0.0625],
+        ],
+        16 => &[
+            [0.5625, 0.5625],
+            [0.4375, 0.3125],
+            [0.3125, 0.625],
+            [0.75, 0.4375],
+            [0.1875, 0.375],
+            [0.625, 0.8125],
+            [0.8125, 0.6875],
+            [0.6875, 0.1875],
+            [0.375, 0.875],
+            [0.5, 0.0625],
+            [0.25, 0.125],
+            [0.125, 0.75],
+            [0.0, 0.5],
+            [0.9375, 0.25],
+            [0.875, 0.9375],
+            [0.0625, 0.0],
+        ],
+        _ => unreachable!(),
+    }
+}
+
+pub fn map_timestamp_stage(stage: super::TimestampStage) -> vk::PipelineStageFlags {
+    use super::TimestampStage as Ts;
+    match stage {
+        Ts::TopOfPipe => vk::PipelineStageFlags::TOP_OF_PIPE,
+        Ts::VertexShader => vk::PipelineStageFlags::VERTEX_SHADER,
+        Ts::FragmentShader => vk::PipelineStageFlags::FRAGMENT_SHADER,
+        Ts::ComputeShader => vk::PipelineStageFlags::COMPUTE_SHADER,
+        Ts::BottomOfPipe => vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+    }
+}
+
+/// Picks the member of the clear value union matching the sample type of `format`.
+pub fn map_clear_color(format: wgt::TextureFormat, cv: &wgt::Color) -> vk::ClearColorValue {
+    match format.sample_type(None, None).unwrap() {
+        wgt::TextureSampleType::Float { .. } => vk::ClearColorValue {
+            float32: [cv.r as f32, cv.g as f32, cv.b as f32, cv.a as f32],
+        },
+        wgt::TextureSampleType::Sint => vk::ClearColorValue {
+            int32: [cv.r as i32, cv.g as i32, cv.b as i32, cv.a as i32],
+        },
+        wgt::TextureSampleType::Uint => vk::ClearColorValue {
+            uint32: [cv.r as u32, cv.g as u32, cv.b as u32, cv.a as u32],
+        },
+        wgt::TextureSampleType::Depth => unreachable!(),
     }
 }
 
@@ -324,6 +379,45 @@ pub fn map_texture_usage_to_barrier(
     }
 }
 
+/// Replaces the shader stages of a barrier's `stages`, which conservatively
+/// include every shader stage, with the ones of `hint`.
+///
+/// Stages are left alone if `hint` is empty.
+pub fn narrow_shader_stages(
+    stages: vk::PipelineStageFlags,
+    hint: wgt::ShaderStages,
+) -> vk::PipelineStageFlags {
+    let shader_stages = vk::PipelineStageFlags::VERTEX_SHADER
+        | vk::PipelineStageFlags::FRAGMENT_SHADER
+        | vk::PipelineStageFlags::COMPUTE_SHADER;
+    if hint.is_empty() || !stages.intersects(shader_stages) {
+        return stages;
+    }
+    let mut narrowed = stages - shader_stages;
+    if hint.contains(wgt::ShaderStages::VERTEX) {
+        narrowed |= vk::PipelineStageFlags::VERTEX_SHADER;
+    }
+    if hint.contains(wgt::ShaderStages::FRAGMENT) {
+        narrowed |= vk::PipelineStageFlags::FRAGMENT_SHADER;
+    }
+    if hint.contains(wgt::ShaderStages::COMPUTE) {
+        narrowed |= vk::PipelineStageFlags::COMPUTE_SHADER;
+    }
+    narrowed
+}
+
+/// Converts stages to `VK_KHR_synchronization2` stages, which keep the
+/// values of the original bits.
+pub fn map_stage_flags2(stages: vk::PipelineStageFlags) -> vk::PipelineStageFlags2 {
+    vk::PipelineStageFlags2::from_raw(u64::from(stages.as_raw()))
+}
+
+/// Converts accesses to `VK_KHR_synchronization2` accesses, which keep the
+/// values of the original bits.
+pub fn map_access_flags2(access: vk::AccessFlags) -> vk::AccessFlags2 {
+    vk::AccessFlags2::from_raw(u64::from(access.as_raw()))
+}
+
 pub fn map_vk_image_usage(usage: vk::ImageUsageFlags) -> crate::TextureUses {
     let mut bits = crate::TextureUses::empty();
     if usage.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
@@ -362,6 +456,36 @@ pub fn map_index_format(index_format: wgt::IndexFormat) -> vk::IndexType {
     }
 }
 
+/// Maps vertex buffer layouts to the bindings and attributes of
+/// `VK_EXT_vertex_input_dynamic_state`, with buffer `i` at binding `i`.
+pub(super) fn map_vertex_input(
+    vertex_buffers: &[crate::VertexBufferLayout<'_>],
+) -> super::VertexInput {
+    let mut input = super::VertexInput::default();
+    for (i, vb) in vertex_buffers.iter().enumerate() {
+        input.bindings.push(
+            vk::VertexInputBindingDescription2EXT::default()
+                .binding(i as u32)
+                .stride(vb.array_stride as u32)
+                .input_rate(match vb.step_mode {
+                    wgt::VertexStepMode::Vertex => vk::VertexInputRate::VERTEX,
+                    wgt::VertexStepMode::Instance => vk::VertexInputRate::INSTANCE,
+                })
+                .divisor(1),
+        );
+        for at in vb.attributes {
+            input.attributes.push(
+                vk::VertexInputAttributeDescription2EXT::default()
+                    .location(at.shader_location)
+                    .binding(i as u32)
+                    .format(map_vertex_format(at.format))
+                    .offset(at.offset as u32),
+            );
+        }
+    }
+    input
+}
+
 pub fn map_vertex_format(vertex_format: wgt::VertexFormat) -> vk::Format {
     use wgt::VertexFormat as Vf;
     match vertex_format {
@@ -442,6 +566,34 @@ pub fn map_attachment_ops(
     (load_op, store_op)
 }
 
+/// Like [`map_attachment_ops`], for a resolve attachment.
+///
+/// Resolve attachments are only written by the resolve at the end of the
+/// subpass, so their previous contents are never loaded nor cleared.
+pub fn map_resolve_attachment_ops(
+    op: crate::AttachmentOps,
+) -> (vk::AttachmentLoadOp, vk::AttachmentStoreOp) {
+    let (_, store_op) = map_attachment_ops(op);
+    (vk::AttachmentLoadOp::DONT_CARE, store_op)
+}
+
+/// Like [`map_attachment_ops`], for an attachment the pass never writes.
+///
+/// `STORE` becomes `NONE` when `store_op_none` is supported, so tilers skip
+/// writing back contents that can't have changed. A missing `STORE` still
+/// maps to `DONT_CARE`.
+pub fn map_read_only_attachment_ops(
+    op: crate::AttachmentOps,
+    store_op_none: bool,
+) -> (vk::AttachmentLoadOp, vk::AttachmentStoreOp) {
+    let (load_op, store_op) = map_attachment_ops(op);
+    if store_op_none && store_op == vk::AttachmentStoreOp::STORE {
+        (load_op, vk::AttachmentStoreOp::NONE)
+    } else {
+        (load_op, store_op)
+    }
+}
+
 pub fn map_present_mode(mode: wgt::PresentMode) -> vk::PresentModeKHR {
     match mode {
         wgt::PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
@@ -529,6 +681,15 @@ pub fn map_buffer_usage(usage: crate::BufferUses) -> vk::BufferUsageFlags {
         flags |= vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
             | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS;
     }
+    if usage.contains(crate::BufferUses::CONDITIONAL_RENDERING) {
+        flags |= vk::BufferUsageFlags::CONDITIONAL_RENDERING_EXT;
+    }
+    if usage.contains(crate::BufferUses::TRANSFORM_FEEDBACK) {
+        flags |= vk::BufferUsageFlags::TRANSFORM_FEEDBACK_BUFFER_EXT;
+    }
+    if usage.contains(crate::BufferUses::TRANSFORM_FEEDBACK_COUNTER) {
+        flags |= vk::BufferUsageFlags::TRANSFORM_FEEDBACK_COUNTER_BUFFER_EXT;
+    }
     flags
 }
 
@@ -590,6 +751,21 @@ pub fn map_buffer_usage_to_barrier(
         access |= vk::AccessFlags::ACCELERATION_STRUCTURE_READ_KHR
             | vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR;
     }
+    if usage.contains(crate::BufferUses::CONDITIONAL_RENDERING) {
+        stages |= vk::PipelineStageFlags::CONDITIONAL_RENDERING_EXT;
+        access |= vk::AccessFlags::CONDITIONAL_RENDERING_READ_EXT;
+    }
+    if usage.contains(crate::BufferUses::TRANSFORM_FEEDBACK) {
+        stages |= vk::PipelineStageFlags::TRANSFORM_FEEDBACK_EXT;
+        access |= vk::AccessFlags::TRANSFORM_FEEDBACK_WRITE_EXT;
+    }
+    if usage.contains(crate::BufferUses::TRANSFORM_FEEDBACK_COUNTER) {
+        // The counter is read when resuming, and written when pausing.
+        stages |=
+            vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::TRANSFORM_FEEDBACK_EXT;
+        access |= vk::AccessFlags::TRANSFORM_FEEDBACK_COUNTER_READ_EXT
+            | vk::AccessFlags::TRANSFORM_FEEDBACK_COUNTER_WRITE_EXT;
+    }
 
     (stages, access)
 }
@@ -642,6 +818,34 @@ pub(super) fn map_subresource_range_combined_aspect(
     range
 }
 
+/// Maps the subresource range of a barrier on `texture`, taking a shortcut
+/// for the common case of transitioning the whole texture.
+pub(super) fn map_barrier_subresource_range(
+    texture: &super::Texture,
+    range: &wgt::ImageSubresourceRange,
+    private_caps: &super::PrivateCapabilities,
+) -> vk::ImageSubresourceRange {
+    if !texture.covers_all_subresources(range) {
+        return map_subresource_range_combined_aspect(range, texture.format, private_caps);
+    }
+    let mut aspect_mask = map_aspects(crate::FormatAspects::from(texture.format));
+    if !private_caps.texture_s8 && texture.format == wgt::TextureFormat::Stencil8 {
+        aspect_mask |= vk::ImageAspectFlags::DEPTH;
+    }
+    vk::ImageSubresourceRange {
+        aspect_mask,
+        base_mip_level: 0,
+        level_count: vk::REMAINING_MIP_LEVELS,
+        base_array_layer: 0,
+        layer_count: vk::REMAINING_ARRAY_LAYERS,
+    }
+}
+
+/// Maps the copy location of `base` to a single layer of the image.
+///
+/// Copies address the layers of the image itself, not of any view, so face
+/// `f` of cube `c` is layer `6 * c + f` whether the texture is viewed as a
+/// cube or as a 2D array, and `CUBE_COMPATIBLE` needs no special handling.
 pub fn map_subresource_layers(
     base: &crate::TextureCopyBase,
 ) -> (vk::ImageSubresourceLayers, vk::Offset3D) {
@@ -659,6 +863,19 @@ pub fn map_subresource_layers(
     (subresource, offset)
 }
 
+pub fn map_blit_region(
+    base: &crate::TextureCopyBase,
+    size: &crate::CopyExtent,
+) -> (vk::ImageSubresourceLayers, [vk::Offset3D; 2]) {
+    let (subresource, offset) = map_subresource_layers(base);
+    let end = vk::Offset3D {
+        x: offset.x + size.width as i32,
+        y: offset.y + size.height as i32,
+        z: offset.z + size.depth as i32,
+    };
+    (subresource, [offset, end])
+}
+
 pub fn map_filter_mode(mode: wgt::FilterMode) -> vk::Filter {
     match mode {
         wgt::FilterMode::Nearest => vk::Filter::NEAREST,
@@ -755,6 +972,12 @@ pub fn map_topology(topology: wgt::PrimitiveTopology) -> vk::PrimitiveTopology {
     }
 }
 
+/// Maps a fragment shading rate in pixels, each of 1, 2 or 4.
+pub fn map_fragment_shading_rate([width, height]: [u32; 2]) -> Option<vk::Extent2D> {
+    let valid = |size: u32| matches!(size, 1 | 2 | 4);
+    (valid(width) && valid(height)).then_some(vk::Extent2D { width, height })
+}
+
 pub fn map_polygon_mode(mode: wgt::PolygonMode) -> vk::PolygonMode {
     match mode {
         wgt::PolygonMode::Fill => vk::PolygonMode::FILL,
@@ -850,6 +1073,31 @@ pub fn map_blend_component(
     (op, src, dst)
 }
 
+pub fn map_advanced_blend_op(operation: wgt::AdvancedBlendOperation) -> vk::BlendOp {
+    use wgt::AdvancedBlendOperation as Abo;
+    match operation {
+        Abo::Multiply => vk::BlendOp::MULTIPLY_EXT,
+        Abo::Screen => vk::BlendOp::SCREEN_EXT,
+        Abo::Overlay => vk::BlendOp::OVERLAY_EXT,
+        Abo::Darken => vk::BlendOp::DARKEN_EXT,
+        Abo::Lighten => vk::BlendOp::LIGHTEN_EXT,
+        Abo::ColorDodge => vk::BlendOp::COLORDODGE_EXT,
+        Abo::ColorBurn => vk::BlendOp::COLORBURN_EXT,
+        Abo::HardLight => vk::BlendOp::HARDLIGHT_EXT,
+        Abo::SoftLight => vk::BlendOp::SOFTLIGHT_EXT,
+        Abo::Difference => vk::BlendOp::DIFFERENCE_EXT,
+        Abo::Exclusion => vk::BlendOp::EXCLUSION_EXT,
+    }
+}
+
+pub fn map_blend_overlap(overlap: wgt::BlendOverlap) -> vk::BlendOverlapEXT {
+    match overlap {
+        wgt::BlendOverlap::Uncorrelated => vk::BlendOverlapEXT::UNCORRELATED,
+        wgt::BlendOverlap::Disjoint => vk::BlendOverlapEXT::DISJOINT,
+        wgt::BlendOverlap::Conjoint => vk::BlendOverlapEXT::CONJOINT,
+    }
+}
+
 pub fn map_pipeline_statistics(
     types: wgt::PipelineStatisticsTypes,
 ) -> vk::QueryPipelineStatisticFlags {
@@ -964,3 +1212,229 @@ pub fn map_acceleration_structure_usage_to_barrier(
 
     (stages, access)
 }
+
+#[test]
+fn check_indirect_buffer_barrier() {
+    assert_eq!(
+        map_buffer_usage_to_barrier(crate::BufferUses::INDIRECT),
+        (
+            vk::PipelineStageFlags::DRAW_INDIRECT,
+            vk::AccessFlags::INDIRECT_COMMAND_READ
+        )
+    );
+}
+
+#[test]
+fn check_conditional_rendering_barrier() {
+    // Occlusion predicates are copied, then read by conditional rendering.
+    assert_eq!(
+        map_buffer_usage_to_barrier(crate::BufferUses::COPY_DST),
+        (
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE
+        )
+    );
+    assert_eq!(
+        map_buffer_usage_to_barrier(crate::BufferUses::CONDITIONAL_RENDERING),
+        (
+            vk::PipelineStageFlags::CONDITIONAL_RENDERING_EXT,
+            vk::AccessFlags::CONDITIONAL_RENDERING_READ_EXT
+        )
+    );
+}
+
+#[test]
+fn check_cube_array_subresource_layers() {
+    let base = crate::TextureCopyBase {
+        mip_level: 1,
+        array_layer: 2 * 6 + 3,
+        origin: wgt::Origin3d { x: 4, y: 8, z: 0 },
+        aspect: crate::FormatAspects::COLOR,
+    };
+    let (subresource, offset) = map_subresource_layers(&base);
+    assert_eq!(subresource.mip_level, 1);
+    assert_eq!(subresource.base_array_layer, 15);
+    assert_eq!(subresource.layer_count, 1);
+    assert_eq!((offset.x, offset.y, offset.z), (4, 8, 0));
+}
+
+#[test]
+fn check_cube_face_subresource_layers() {
+    // +Y is the third face, so it's layer 2 of a 2D array aliasing the cube.
+    let base = crate::TextureCopyBase {
+        mip_level: 0,
+        array_layer: 2,
+        origin: wgt::Origin3d::ZERO,
+        aspect: crate::FormatAspects::COLOR,
+    };
+    let (subresource, _) = map_subresource_layers(&base);
+    assert_eq!(subresource.base_array_layer, 2);
+    assert_eq!(subresource.layer_count, 1);
+}
+
+#[test]
+fn check_attachment_ops() {
+    use crate::AttachmentOps as Ao;
+    use vk::{AttachmentLoadOp as L, AttachmentStoreOp as S};
+    assert_eq!(map_attachment_ops(Ao::empty()), (L::CLEAR, S::DONT_CARE));
+    assert_eq!(map_attachment_ops(Ao::LOAD), (L::LOAD, S::DONT_CARE));
+    assert_eq!(map_attachment_ops(Ao::STORE), (L::CLEAR, S::STORE));
+    assert_eq!(map_attachment_ops(Ao::all()), (L::LOAD, S::STORE));
+
+    assert_eq!(
+        map_read_only_attachment_ops(Ao::all(), true),
+        (L::LOAD, S::NONE)
+    );
+    assert_eq!(
+        map_read_only_attachment_ops(Ao::all(), false),
+        (L::LOAD, S::STORE)
+    );
+    assert_eq!(
+        map_read_only_attachment_ops(Ao::LOAD, true),
+        (L::LOAD, S::DONT_CARE)
+    );
+
+    // The ops of resolve attachments, as set by `begin_render_pass`.
+    assert_eq!(
+        map_resolve_attachment_ops(Ao::STORE),
+        (L::DONT_CARE, S::STORE)
+    );
+}
+
+#[test]
+fn check_array_layer_subresource_range() {
+    // A single layer of an array texture, e.g. one shadow map cascade.
+    let range = wgt::ImageSubresourceRange {
+        aspect: wgt::TextureAspect::DepthOnly,
+        base_mip_level: 0,
+        mip_level_count: None,
+        base_array_layer: 2,
+        array_layer_count: Some(1),
+    };
+    let vk_range = map_subresource_range(&range, wgt::TextureFormat::Depth32Float);
+    assert_eq!(vk_range.aspect_mask, vk::ImageAspectFlags::DEPTH);
+    assert_eq!(vk_range.level_count, vk::REMAINING_MIP_LEVELS);
+    assert_eq!(vk_range.base_array_layer, 2);
+    assert_eq!(vk_range.layer_count, 1);
+}
+
+#[test]
+fn check_mip_band_subresource_range() {
+    let range = wgt::ImageSubresourceRange {
+        aspect: wgt::TextureAspect::All,
+        base_mip_level: 2,
+        mip_level_count: Some(3),
+        base_array_layer: 0,
+        array_layer_count: None,
+    };
+    let vk_range = map_subresource_range(&range, wgt::TextureFormat::Rgba8Unorm);
+    assert_eq!(vk_range.base_mip_level, 2);
+    assert_eq!(vk_range.level_count, 3);
+    assert_eq!(vk_range.layer_count, vk::REMAINING_ARRAY_LAYERS);
+}
+
+#[test]
+fn check_map_read_barrier_is_host_visible() {
+    let (stage, access) = map_buffer_usage_to_barrier(crate::BufferUses::MAP_READ);
+    assert_eq!(stage, vk::PipelineStageFlags::HOST);
+    assert_eq!(access, vk::AccessFlags::HOST_READ);
+}
+
+#[test]
+fn check_dynamic_vertex_input() {
+    let attributes = [
+        wgt::VertexAttribute {
+            format: wgt::VertexFormat::Float32x3,
+            offset: 0,
+            shader_location: 0,
+        },
+        wgt::VertexAttribute {
+            format: wgt::VertexFormat::Unorm8x4,
+            offset: 12,
+            shader_location: 1,
+        },
+    ];
+    let input = map_vertex_input(&[
+        crate::VertexBufferLayout {
+            array_stride: 16,
+            step_mode: wgt::VertexStepMode::Vertex,
+            attributes: &attributes,
+        },
+        crate::VertexBufferLayout {
+            array_stride: 0,
+            step_mode: wgt::VertexStepMode::Instance,
+            attributes: &[],
+        },
+    ]);
+    assert_eq!(input.bindings.len(), 2);
+    assert_eq!(input.bindings[0].stride, 16);
+    assert_eq!(input.bindings[1].input_rate, vk::VertexInputRate::INSTANCE);
+    assert!(input.bindings.iter().all(|binding| binding.divisor == 1));
+    assert_eq!(input.attributes.len(), 2);
+    assert_eq!(input.attributes[1].format, vk::Format::R8G8B8A8_UNORM);
+    assert_eq!(input.attributes[1].offset, 12);
+}
+
+#[test]
+fn check_map_write_barrier_is_host_visible() {
+    // Host writes to a mapped buffer must be visible to a following copy,
+    // e.g. when uploading through a staging buffer.
+    let (src_stage, src_access) = map_buffer_usage_to_barrier(crate::BufferUses::MAP_WRITE);
+    assert_eq!(src_stage, vk::PipelineStageFlags::HOST);
+    assert_eq!(src_access, vk::AccessFlags::HOST_WRITE);
+    let (dst_stage, dst_access) = map_buffer_usage_to_barrier(crate::BufferUses::COPY_SRC);
+    assert_eq!(dst_stage, vk::PipelineStageFlags::TRANSFER);
+    assert_eq!(dst_access, vk::AccessFlags::TRANSFER_READ);
+}
+
+#[test]
+fn check_standard_sample_locations() {
+    for sample_count in [1, 2, 4, 8, 16] {
+        let locations = standard_sample_locations(sample_count);
+        assert_eq!(locations.len(), sample_count as usize);
+        for &[x, y] in locations {
+            assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+        }
+    }
+}
+
+#[test]
+fn check_narrow_shader_stages() {
+    let (stages, _) = map_texture_usage_to_barrier(crate::TextureUses::RESOURCE);
+    assert_eq!(
+        narrow_shader_stages(stages, wgt::ShaderStages::FRAGMENT),
+        vk::PipelineStageFlags::FRAGMENT_SHADER
+    );
+    assert_eq!(
+        narrow_shader_stages(stages, wgt::ShaderStages::empty()),
+        stages
+    );
+    let (stages, _) = map_texture_usage_to_barrier(crate::TextureUses::COLOR_TARGET);
+    assert_eq!(
+        narrow_shader_stages(stages, wgt::ShaderStages::FRAGMENT),
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+    );
+}
+
+#[test]
+fn check_compute_to_indirect_barrier() {
+    let (stages, access) = map_buffer_usage_to_barrier(crate::BufferUses::STORAGE_READ_WRITE);
+    assert_eq!(
+        narrow_shader_stages(stages, wgt::ShaderStages::COMPUTE),
+        vk::PipelineStageFlags::COMPUTE_SHADER
+    );
+    assert!(access.contains(vk::AccessFlags::SHADER_WRITE));
+}
+
+#[test]
+fn check_fragment_shading_rate() {
+    assert_eq!(
+        map_fragment_shading_rate([2, 4]),
+        Some(vk::Extent2D {
+            width: 2,
+            height: 4
+        })
+    );
+    assert_eq!(map_fragment_shading_rate([3, 1]), None);
+    assert_eq!(map_fragment_shading_rate([1, 8]), None);
+}
@@ -10,7 +10,7 @@ use std::{
     ffi::{CStr, CString},
     mem::MaybeUninit,
     num::NonZeroU32,
-    ptr,
+    ptr, slice,
     sync::Arc,
 };
 
@@ -66,6 +66,7 @@ impl super::DeviceShared {
                 let mut color_refs = Vec::with_capacity(e.key().colors.len());
                 let mut resolve_refs = Vec::with_capacity(color_refs.capacity());
                 let mut ds_ref = None;
+                let mut ds_resolve = None;
                 let samples = vk::SampleCountFlags::from_raw(e.key().sample_count);
                 let unused = vk::AttachmentReference {
                     attachment: vk::ATTACHMENT_UNUSED,
@@ -84,18 +85,18 @@ impl super::DeviceShared {
                                 .samples(samples)
                                 .load_op(load_op)
                                 .store_op(store_op)
-                                .initial_layout(cat.base.layout)
-                                .final_layout(cat.base.layout)
+                                .initial_layout(cat.base.initial_layout)
+                                .final_layout(cat.base.final_layout)
                         });
                         let resolve_ref = if let Some(ref rat) = cat.resolve {
-                            let (load_op, store_op) = conv::map_attachment_ops(rat.ops);
+                            let (load_op, store_op) = conv::map_resolve_attachment_ops(rat.ops);
                             let vk_attachment = vk::AttachmentDescription::default()
                                 .format(rat.format)
                                 .samples(vk::SampleCountFlags::TYPE_1)
                                 .load_op(load_op)
                                 .store_op(store_op)
-                                .initial_layout(rat.layout)
-                                .final_layout(rat.layout);
+                                .initial_layout(rat.initial_layout)
+                                .final_layout(rat.final_layout);
                             vk_attachments.push(vk_attachment);
 
                             vk::AttachmentReference {
@@ -120,9 +121,20 @@ impl super::DeviceShared {
                         attachment: vk_attachments.len() as u32,
                         layout: ds.base.layout,
                     });
-                    let (load_op, store_op) = conv::map_attachment_ops(ds.base.ops);
-                    let (stencil_load_op, stencil_store_op) =
-                        conv::map_attachment_ops(ds.stencil_ops);
+                    let (load_op, store_op, stencil_load_op, stencil_store_op) =
+                        if ds.base.layout == vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL {
+                            let store_op_none = self.private_caps.store_op_none;
+                            let (load_op, store_op) =
+                                conv::map_read_only_attachment_ops(ds.base.ops, store_op_none);
+                            let (stencil_load_op, stencil_store_op) =
+                                conv::map_read_only_attachment_ops(ds.stencil_ops, store_op_none);
+                            (load_op, store_op, stencil_load_op, stencil_store_op)
+                        } else {
+                            let (load_op, store_op) = conv::map_attachment_ops(ds.base.ops);
+                            let (stencil_load_op, stencil_store_op) =
+                                conv::map_attachment_ops(ds.stencil_ops);
+                            (load_op, store_op, stencil_load_op, stencil_store_op)
+                        };
                     let vk_attachment = vk::AttachmentDescription::default()
                         .format(ds.base.format)
                         .samples(samples)
@@ -130,9 +142,68 @@ impl super::DeviceShared {
                         .store_op(store_op)
                         .stencil_load_op(stencil_load_op)
                         .stencil_store_op(stencil_store_op)
-                        .initial_layout(ds.base.layout)
-                        .final_layout(ds.base.layout);
+                        .initial_layout(ds.base.initial_layout)
+                        .final_layout(ds.base.final_layout);
                     vk_attachments.push(vk_attachment);
+
+                    if let Some(ref resolve) = ds.resolve {
+                        let (load_op, store_op) =
+                            conv::map_resolve_attachment_ops(resolve.base.ops);
+                        let vk_attachment = vk::AttachmentDescription::default()
+                            .format(resolve.base.format)
+                            .samples(vk::SampleCountFlags::TYPE_1)
+                            .load_op(load_op)
+                            .store_op(store_op)
+                            .stencil_load_op(load_op)
+                            .stencil_store_op(store_op)
+                            .initial_layout(resolve.base.initial_layout)
+                            .final_layout(resolve.base.final_layout);
+                        let reference = vk::AttachmentReference {
+                            attachment: vk_attachments.len() as u32,
+                            layout: resolve.base.layout,
+                        };
+                        vk_attachments.push(vk_attachment);
+                        ds_resolve = Some((reference, resolve));
+                    }
+                }
+
+                let shading_rate = e.key().shading_rate.as_ref().map(|sr| {
+                    let layout = vk::ImageLayout::GENERAL;
+                    let reference = vk::AttachmentReference {
+                        attachment: vk_attachments.len() as u32,
+                        layout,
+                    };
+                    vk_attachments.push(
+                        vk::AttachmentDescription::default()
+                            .format(sr.format)
+                            .samples(vk::SampleCountFlags::TYPE_1)
+                            .load_op(vk::AttachmentLoadOp::LOAD)
+                            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                            .initial_layout(layout)
+                            .final_layout(layout),
+                    );
+                    (reference, sr.texel_size)
+                });
+
+                // Depth-stencil resolves and shading rate attachments can only
+                // be described with `vkCreateRenderPass2`.
+                if ds_resolve.is_some() || shading_rate.is_some() {
+                    let raw = unsafe {
+                        self.create_render_pass2(
+                            &vk_attachments,
+                            &color_refs,
+                            &resolve_refs,
+                            ds_ref.as_ref(),
+                            ds_resolve
+                                .as_ref()
+                                .map(|(reference, key)| (reference, *key)),
+                            shading_rate
+                                .as_ref()
+                                .map(|(reference, size)| (reference, *size)),
+                            e.key().multiview,
+                        )?
+                    };
+                    return Ok(*e.insert(raw));
                 }
 
                 let vk_subpasses = [{
@@ -189,6 +260,95 @@ impl super::DeviceShared {
         })
     }
 
+    /// Creates a render pass with `vkCreateRenderPass2` from the arguments
+    /// of `vkCreateRenderPass`, and the attachments it can't describe: a
+    /// depth-stencil resolve attachment and a shading rate attachment with
+    /// its texel size.
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn create_render_pass2(
+        &self,
+        attachments: &[vk::AttachmentDescription],
+        color_refs: &[vk::AttachmentReference],
+        resolve_refs: &[vk::AttachmentReference],
+        ds_ref: Option<&vk::AttachmentReference>,
+        ds_resolve: Option<(&vk::AttachmentReference, &super::DepthStencilResolveKey)>,
+        shading_rate: Option<(&vk::AttachmentReference, [u32; 2])>,
+        multiview: Option<NonZeroU32>,
+    ) -> Result<vk::RenderPass, crate::DeviceError> {
+        let vk_attachments = attachments
+            .iter()
+            .map(|at| {
+                vk::AttachmentDescription2::default()
+                    .flags(at.flags)
+                    .format(at.format)
+                    .samples(at.samples)
+                    .load_op(at.load_op)
+                    .store_op(at.store_op)
+                    .stencil_load_op(at.stencil_load_op)
+                    .stencil_store_op(at.stencil_store_op)
+                    .initial_layout(at.initial_layout)
+                    .final_layout(at.final_layout)
+            })
+            .collect::<Vec<_>>();
+        let map_reference = |reference: &vk::AttachmentReference| {
+            vk::AttachmentReference2::default()
+                .attachment(reference.attachment)
+                .layout(reference.layout)
+        };
+        let vk_color_refs = color_refs.iter().map(map_reference).collect::<Vec<_>>();
+        let vk_resolve_refs = resolve_refs.iter().map(map_reference).collect::<Vec<_>>();
+        let vk_ds_ref = ds_ref.map(map_reference);
+
+        // As in `make_render_pass`, all views are rendered, concurrently.
+        let view_mask = multiview.map_or(0, |multiview| (1 << multiview.get()) - 1);
+        let mut vk_subpass = vk::SubpassDescription2::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .view_mask(view_mask)
+            .color_attachments(&vk_color_refs)
+            .resolve_attachments(&vk_resolve_refs);
+
+        let vk_ds_resolve_ref;
+        let mut vk_ds_resolve;
+        if let Some((reference, resolve)) = ds_resolve {
+            vk_ds_resolve_ref = map_reference(reference);
+            vk_ds_resolve = vk::SubpassDescriptionDepthStencilResolve::default()
+                .depth_resolve_mode(resolve.depth_mode)
+                .stencil_resolve_mode(resolve.stencil_mode)
+                .depth_stencil_resolve_attachment(&vk_ds_resolve_ref);
+            vk_subpass = vk_subpass.push_next(&mut vk_ds_resolve);
+        }
+        let vk_shading_rate_ref;
+        let mut vk_shading_rate;
+        if let Some((reference, [width, height])) = shading_rate {
+            vk_shading_rate_ref = map_reference(reference);
+            vk_shading_rate = vk::FragmentShadingRateAttachmentInfoKHR::default()
+                .fragment_shading_rate_attachment(&vk_shading_rate_ref)
+                .shading_rate_attachment_texel_size(vk::Extent2D { width, height });
+            vk_subpass = vk_subpass.push_next(&mut vk_shading_rate);
+        }
+        if self
+            .workarounds
+            .contains(super::Workarounds::EMPTY_RESOLVE_ATTACHMENT_LISTS)
+            && vk_resolve_refs.is_empty()
+        {
+            vk_subpass.p_resolve_attachments = ptr::null();
+        }
+        if let Some(ref reference) = vk_ds_ref {
+            vk_subpass = vk_subpass.depth_stencil_attachment(reference);
+        }
+
+        let correlated_view_masks = [view_mask];
+        let mut vk_info = vk::RenderPassCreateInfo2::default()
+            .attachments(&vk_attachments)
+            .subpasses(slice::from_ref(&vk_subpass));
+        if multiview.is_some() {
+            vk_info = vk_info.correlated_view_masks(&correlated_view_masks);
+        }
+
+        unsafe { self.raw.create_render_pass2(&vk_info, None) }
+            .map_err(super::map_host_device_oom_err)
+    }
+
     pub fn make_framebuffer(
         &self,
         key: super::FramebufferKey,
@@ -223,12 +383,24 @@ impl super::DeviceShared {
                     .iter()
                     .enumerate()
                     .map(|(i, at)| {
+                        let mut usage = conv::map_texture_usage(at.view_usage);
+                        let mut width = e.key().extent.width;
+                        let mut height = e.key().extent.height;
+                        // The shading rate attachment has a texel per area
+                        // of the framebuffer.
+                        if let Some([texel_width, texel_height]) = e.key().shading_rate_texel_size {
+                            if i == e.key().attachments.len() - 1 {
+                                usage |= vk::ImageUsageFlags::FRAGMENT_SHADING_RATE_ATTACHMENT_KHR;
+                                width = width.div_ceil(texel_width);
+                                height = height.div_ceil(texel_height);
+                            }
+                        }
                         let mut info = vk::FramebufferAttachmentImageInfo::default()
-                            .usage(conv::map_texture_usage(at.view_usage))
+                            .usage(usage)
                             .flags(at.raw_image_flags)
-                            .width(e.key().extent.width)
-                            .height(e.key().extent.height)
-                            .layer_count(e.key().extent.depth_or_array_layers);
+                            .width(width)
+                            .height(height)
+                            .layer_count(at.layers);
                         // https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/VkRenderPassBeginInfo.html#VUID-VkRenderPassBeginInfo-framebuffer-03214
                         if vk_view_formats_list[i].is_empty() {
                             info = info.view_formats(&vk_view_formats[i..i + 1]);
@@ -681,6 +853,9 @@ impl super::Device {
             format: desc.format,
             raw_flags: vk::ImageCreateFlags::empty(),
             copy_size: desc.copy_extent(),
+            sample_count: desc.sample_count,
+            mip_level_count: desc.mip_level_count,
+            array_layer_count: desc.array_layer_count(),
             view_formats,
         }
     }
@@ -693,6 +868,7 @@ impl super::Device {
         super::Buffer {
             raw: vk_buffer,
             block: None,
+            size: None,
         }
     }
 
@@ -727,7 +903,20 @@ impl super::Device {
     ) -> Result<CompiledStage, crate::PipelineError> {
         let stage_flags = crate::auxil::map_naga_stage(naga_stage);
         let vk_module = match *stage.module {
-            super::ShaderModule::Raw(raw) => raw,
+            super::ShaderModule::Raw {
+                raw,
+                ref entry_points,
+            } => {
+                // A single module may contain several entry points, so make sure
+                // the requested one exists for this stage.
+                if !entry_points
+                    .iter()
+                    .any(|(ep_stage, name)| *ep_stage == naga_stage && name == stage.entry_point)
+                {
+                    return Err(crate::PipelineError::EntryPoint(naga_stage));
+                }
+                raw
+            }
             super::ShaderModule::Intermediate {
                 ref naga_shader,
                 runtime_checks,
@@ -807,7 +996,7 @@ impl super::Device {
             create_info,
             _entry_point: entry_point,
             temp_raw_module: match *stage.module {
-                super::ShaderModule::Raw(_) => None,
+                super::ShaderModule::Raw { .. } => None,
                 super::ShaderModule::Intermediate { .. } => Some(vk_module),
             },
         })
@@ -845,182 +1034,1517 @@ impl super::Device {
     pub fn shared_instance(&self) -> &super::InstanceShared {
         &self.shared.instance
     }
-}
 
-impl crate::Device for super::Device {
-    type A = super::Api;
+    /// Reads a region of `texture` back into tightly packed bytes.
+    ///
+    /// This records, submits and waits for its own commands, so it's meant for
+    /// tests and screenshots rather than for streaming data back every frame.
+    ///
+    /// # Safety
+    ///
+    /// - `queue` must belong to this device.
+    /// - `texture` must have `COPY_SRC` usage, and the region must be in
+    ///   `usage`. It's transitioned back to `usage` afterwards.
+    pub unsafe fn read_texture(
+        &self,
+        queue: &super::Queue,
+        texture: &super::Texture,
+        usage: crate::TextureUses,
+        base: &crate::TextureCopyBase,
+        size: &crate::CopyExtent,
+    ) -> Result<Vec<u8>, crate::DeviceError> {
+        let label = "(wgpu internal) texture readback";
+        let staging = unsafe {
+            self.create_staging_region(
+                label,
+                crate::BufferUses::MAP_READ | crate::BufferUses::COPY_DST,
+                texture,
+                base,
+                size,
+            )
+        }?;
 
-    unsafe fn exit(self, queue: super::Queue) {
-        unsafe { self.mem_allocator.into_inner().cleanup(&*self.shared) };
-        unsafe { self.desc_allocator.into_inner().cleanup(&*self.shared) };
-        unsafe {
-            queue
-                .relay_semaphores
-                .into_inner()
-                .destroy(&self.shared.raw)
-        };
-        unsafe { self.shared.free_resources() };
+        let result = unsafe {
+            self.submit_and_wait(queue, label, |encoder| {
+                use crate::CommandEncoder as _;
+
+                encoder.transition_textures(std::iter::once(crate::TextureBarrier {
+                    texture,
+                    range: staging.range.clone(),
+                    usage: usage..crate::TextureUses::COPY_SRC,
+                }));
+                encoder.copy_texture_to_buffer(
+                    texture,
+                    crate::TextureUses::COPY_SRC,
+                    &staging.buffer,
+                    std::iter::once(staging.copy.clone()),
+                );
+                encoder.transition_textures(std::iter::once(crate::TextureBarrier {
+                    texture,
+                    range: staging.range.clone(),
+                    usage: crate::TextureUses::COPY_SRC..usage,
+                }));
+                encoder.transition_buffers(std::iter::once(crate::BufferBarrier {
+                    buffer: &staging.buffer,
+                    usage: crate::BufferUses::COPY_DST..crate::BufferUses::MAP_READ,
+                }));
+            })
+        }
+        .and_then(|()| unsafe {
+            let mapping = crate::Device::map_buffer(self, &staging.buffer, 0..staging.size)?;
+            if !mapping.is_coherent {
+                crate::Device::invalidate_mapped_ranges(
+                    self,
+                    &staging.buffer,
+                    std::iter::once(0..staging.size),
+                );
+            }
+            let padded = slice::from_raw_parts(mapping.ptr.as_ptr(), staging.size as usize);
+            let data = strip_row_padding(
+                padded,
+                staging.packed_bytes_per_row,
+                staging.padded_bytes_per_row,
+            );
+            crate::Device::unmap_buffer(self, &staging.buffer);
+            Ok(data)
+        });
+
+        unsafe { crate::Device::destroy_buffer(self, staging.buffer) };
+        result
     }
 
-    unsafe fn create_buffer(
+    /// Records writing tightly packed bytes into a region of `texture` to
+    /// `encoder`, the converse of [`Self::read_texture`].
+    ///
+    /// `data` is copied right away into a staging buffer, with rows padded to
+    /// the alignment the device copies fastest. The staging buffer is
+    /// returned, and must be destroyed with [`crate::Device::destroy_buffer`]
+    /// once the commands recorded to `encoder` have finished executing.
+    ///
+    /// # Safety
+    ///
+    /// - `encoder` must be recording, outside of any pass.
+    /// - `texture` must have `COPY_DST` usage, and the region must be in
+    ///   `usage`. It's transitioned back to `usage` afterwards.
+    ///
+    /// `data` must hold the texel blocks of the region, row after row, or
+    /// this fails with [`crate::DeviceError::ResourceCreationFailed`].
+    pub unsafe fn write_texture(
         &self,
-        desc: &crate::BufferDescriptor,
+        encoder: &mut super::CommandEncoder,
+        texture: &super::Texture,
+        usage: crate::TextureUses,
+        base: &crate::TextureCopyBase,
+        size: &crate::CopyExtent,
+        data: &[u8],
     ) -> Result<super::Buffer, crate::DeviceError> {
-        let vk_info = vk::BufferCreateInfo::default()
-            .size(desc.size)
-            .usage(conv::map_buffer_usage(desc.usage))
-            .sharing_mode(vk::SharingMode::EXCLUSIVE);
-
-        let raw = unsafe {
-            self.shared
-                .raw
-                .create_buffer(&vk_info, None)
-                .map_err(super::map_host_device_oom_and_ioca_err)?
-        };
-        let req = unsafe { self.shared.raw.get_buffer_memory_requirements(raw) };
-
-        let mut alloc_usage = if desc
-            .usage
-            .intersects(crate::BufferUses::MAP_READ | crate::BufferUses::MAP_WRITE)
-        {
-            let mut flags = gpu_alloc::UsageFlags::HOST_ACCESS;
-            //TODO: find a way to use `crate::MemoryFlags::PREFER_COHERENT`
-            flags.set(
-                gpu_alloc::UsageFlags::DOWNLOAD,
-                desc.usage.contains(crate::BufferUses::MAP_READ),
+        let staging = unsafe {
+            self.create_staging_region(
+                "(wgpu internal) texture upload",
+                crate::BufferUses::MAP_WRITE | crate::BufferUses::COPY_SRC,
+                texture,
+                base,
+                size,
+            )
+        }?;
+        if data.len() as u64 != staging.packed_size {
+            log::error!(
+                "{} bytes written to a texture region of {} bytes",
+                data.len(),
+                staging.packed_size
             );
-            flags.set(
-                gpu_alloc::UsageFlags::UPLOAD,
-                desc.usage.contains(crate::BufferUses::MAP_WRITE),
+            unsafe { crate::Device::destroy_buffer(self, staging.buffer) };
+            return Err(crate::DeviceError::ResourceCreationFailed);
+        }
+
+        let mapping =
+            match unsafe { crate::Device::map_buffer(self, &staging.buffer, 0..staging.size) } {
+                Ok(mapping) => mapping,
+                Err(err) => {
+                    unsafe { crate::Device::destroy_buffer(self, staging.buffer) };
+                    return Err(err);
+                }
+            };
+        unsafe {
+            let padded = slice::from_raw_parts_mut(mapping.ptr.as_ptr(), staging.size as usize);
+            add_row_padding(
+                padded,
+                data,
+                staging.packed_bytes_per_row,
+                staging.padded_bytes_per_row,
             );
-            flags
-        } else {
-            gpu_alloc::UsageFlags::FAST_DEVICE_ACCESS
-        };
-        alloc_usage.set(
-            gpu_alloc::UsageFlags::TRANSIENT,
-            desc.memory_flags.contains(crate::MemoryFlags::TRANSIENT),
-        );
+            if !mapping.is_coherent {
+                crate::Device::flush_mapped_ranges(
+                    self,
+                    &staging.buffer,
+                    std::iter::once(0..staging.size),
+                );
+            }
+            crate::Device::unmap_buffer(self, &staging.buffer);
+        }
 
-        let alignment_mask = if desc.usage.intersects(
-            crate::BufferUses::TOP_LEVEL_ACCELERATION_STRUCTURE_INPUT
-                | crate::BufferUses::BOTTOM_LEVEL_ACCELERATION_STRUCTURE_INPUT,
-        ) {
-            16
-        } else {
-            req.alignment
-        } - 1;
+        // Host writes are made visible by the submission, so the buffer
+        // needs no barrier.
+        unsafe {
+            use crate::CommandEncoder as _;
+
+            encoder.transition_textures(std::iter::once(crate::TextureBarrier {
+                texture,
+                range: staging.range.clone(),
+                usage: usage..crate::TextureUses::COPY_DST,
+            }));
+            encoder.copy_buffer_to_texture(&staging.buffer, texture, std::iter::once(staging.copy));
+            encoder.transition_textures(std::iter::once(crate::TextureBarrier {
+                texture,
+                range: staging.range,
+                usage: crate::TextureUses::COPY_DST..usage,
+            }));
+        }
+        Ok(staging.buffer)
+    }
+
+    /// Creates a staging buffer with `usage` for copies of a single layer
+    /// region of `texture`, with rows padded to the alignment the device
+    /// copies fastest.
+    unsafe fn create_staging_region(
+        &self,
+        label: &str,
+        usage: crate::BufferUses,
+        texture: &super::Texture,
+        base: &crate::TextureCopyBase,
+        size: &crate::CopyExtent,
+    ) -> Result<StagingRegion, crate::DeviceError> {
+        let (block_width, block_height) = texture.format.block_dimensions();
+        let block_size = texture
+            .format
+            .block_copy_size(Some(base.aspect.map()))
+            .unwrap();
+        let rows_per_image = size.height.div_ceil(block_height);
+        let packed_bytes_per_row = size.width.div_ceil(block_width) * block_size;
+        let padded_bytes_per_row = texture.padded_bytes_per_row(
+            size.width,
+            base.aspect.map(),
+            self.shared.limits.optimal_buffer_copy_row_pitch_alignment as u32,
+        );
+        let rows = rows_per_image as u64 * size.depth as u64;
+        let buffer_size = padded_bytes_per_row as u64 * rows;
 
-        let block = unsafe {
-            self.mem_allocator.lock().alloc(
-                &*self.shared,
-                gpu_alloc::Request {
-                    size: req.size,
-                    align_mask: alignment_mask,
-                    usage: alloc_usage,
-                    memory_types: req.memory_type_bits & self.valid_ash_memory_types,
+        let buffer = unsafe {
+            crate::Device::create_buffer(
+                self,
+                &crate::BufferDescriptor {
+                    label: Some(label),
+                    size: buffer_size,
+                    usage,
+                    memory_flags: crate::MemoryFlags::TRANSIENT,
                 },
-            )?
-        };
+            )
+        }?;
+        Ok(StagingRegion {
+            buffer,
+            copy: crate::BufferTextureCopy {
+                buffer_layout: wgt::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(rows_per_image),
+                },
+                texture_base: base.clone(),
+                size: *size,
+            },
+            range: wgt::ImageSubresourceRange {
+                aspect: base.aspect.map(),
+                base_mip_level: base.mip_level,
+                mip_level_count: Some(1),
+                base_array_layer: base.array_layer,
+                array_layer_count: Some(1),
+            },
+            packed_bytes_per_row: packed_bytes_per_row as usize,
+            padded_bytes_per_row: padded_bytes_per_row as usize,
+            packed_size: packed_bytes_per_row as u64 * rows,
+            size: buffer_size,
+        })
+    }
 
-        unsafe {
-            self.shared
-                .raw
-                .bind_buffer_memory(raw, *block.memory(), block.offset())
-                .map_err(super::map_host_device_oom_and_ioca_err)?
+    /// Records commands with `record` into a new encoder, submits them to
+    /// `queue` and waits for them to finish executing.
+    unsafe fn submit_and_wait(
+        &self,
+        queue: &super::Queue,
+        label: &str,
+        record: impl FnOnce(&mut super::CommandEncoder),
+    ) -> Result<(), crate::DeviceError> {
+        use crate::CommandEncoder as _;
+
+        let mut encoder = unsafe {
+            crate::Device::create_command_encoder(
+                self,
+                &crate::CommandEncoderDescriptor {
+                    label: Some(label),
+                    queue,
+                },
+            )
+        }?;
+        let mut fence = match unsafe { crate::Device::create_fence(self) } {
+            Ok(fence) => fence,
+            Err(err) => {
+                unsafe { crate::Device::destroy_command_encoder(self, encoder) };
+                return Err(err);
+            }
         };
 
-        if let Some(label) = desc.label {
-            unsafe { self.shared.set_object_name(raw, label) };
-        }
+        let mut cmd_buf = None;
+        let result = (|| unsafe {
+            encoder.begin_encoding(Some(label))?;
+            record(&mut encoder);
+            let cmd_buf = &*cmd_buf.insert(encoder.end_encoding()?);
 
-        self.counters.buffer_memory.add(block.size() as isize);
-        self.counters.buffers.add(1);
+            crate::Queue::submit(queue, &[cmd_buf], &[], (&mut fence, 1))?;
+            crate::Device::wait(self, &fence, 1, !0)?;
+            Ok(())
+        })();
 
-        Ok(super::Buffer {
-            raw,
-            block: Some(Mutex::new(block)),
-        })
-    }
-    unsafe fn destroy_buffer(&self, buffer: super::Buffer) {
-        unsafe { self.shared.raw.destroy_buffer(buffer.raw, None) };
-        if let Some(block) = buffer.block {
-            let block = block.into_inner();
-            self.counters.buffer_memory.sub(block.size() as isize);
-            unsafe { self.mem_allocator.lock().dealloc(&*self.shared, block) };
+        unsafe {
+            encoder.reset_all(cmd_buf.into_iter());
+            crate::Device::destroy_command_encoder(self, encoder);
+            crate::Device::destroy_fence(self, fence);
         }
+        result
+    }
 
-        self.counters.buffers.sub(1);
+    /// Converts a timestamp query result from ticks to time, using the
+    /// `timestampPeriod` of the device.
+    ///
+    /// Only the `timestampValidBits` low bits of `ticks` are meaningful, the
+    /// others are ignored.
+    pub fn decode_timestamp(&self, ticks: u64) -> std::time::Duration {
+        let mask = timestamp_mask(self.shared.timestamp_valid_bits);
+        ticks_to_duration(ticks & mask, self.shared.timestamp_period)
     }
 
-    unsafe fn map_buffer(
-        &self,
-        buffer: &super::Buffer,
-        range: crate::MemoryRange,
-    ) -> Result<crate::BufferMapping, crate::DeviceError> {
-        if let Some(ref block) = buffer.block {
-            let size = range.end - range.start;
-            let mut block = block.lock();
-            let ptr = unsafe { block.map(&*self.shared, range.start, size as usize)? };
-            let is_coherent = block
-                .props()
-                .contains(gpu_alloc::MemoryPropertyFlags::HOST_COHERENT);
-            Ok(crate::BufferMapping { ptr, is_coherent })
-        } else {
-            super::hal_usage_error("tried to map external buffer")
-        }
+    /// Returns the time between the timestamp query results `start` and
+    /// `end`, even if the timestamp counter wrapped around in between.
+    pub fn decode_timestamp_delta(&self, start: u64, end: u64) -> std::time::Duration {
+        let ticks = timestamp_delta(start, end, self.shared.timestamp_valid_bits);
+        ticks_to_duration(ticks, self.shared.timestamp_period)
     }
-    unsafe fn unmap_buffer(&self, buffer: &super::Buffer) {
-        if let Some(ref block) = buffer.block {
-            unsafe { block.lock().unmap(&*self.shared) };
-        } else {
-            super::hal_usage_error("tried to unmap external buffer")
-        }
+
+    /// Creates an unsignaled event, see [`super::Event`].
+    pub fn create_event(&self) -> Result<super::Event, crate::DeviceError> {
+        let vk_info = vk::EventCreateInfo::default();
+        let raw = unsafe { self.shared.raw.create_event(&vk_info, None) }
+            .map_err(super::map_host_device_oom_err)?;
+        Ok(super::Event { raw })
     }
 
-    unsafe fn flush_mapped_ranges<I>(&self, buffer: &super::Buffer, ranges: I)
-    where
-        I: Iterator<Item = crate::MemoryRange>,
-    {
-        if let Some(vk_ranges) = self.shared.make_memory_ranges(buffer, ranges) {
-            unsafe {
-                self.shared
-                    .raw
-                    .flush_mapped_memory_ranges(
-                        &smallvec::SmallVec::<[vk::MappedMemoryRange; 32]>::from_iter(vk_ranges),
-                    )
-            }
-            .unwrap();
-        }
+    /// # Safety
+    ///
+    /// - No command buffer using `event` may be pending.
+    pub unsafe fn destroy_event(&self, event: super::Event) {
+        unsafe { self.shared.raw.destroy_event(event.raw, None) };
     }
-    unsafe fn invalidate_mapped_ranges<I>(&self, buffer: &super::Buffer, ranges: I)
-    where
-        I: Iterator<Item = crate::MemoryRange>,
-    {
-        if let Some(vk_ranges) = self.shared.make_memory_ranges(buffer, ranges) {
-            unsafe {
-                self.shared
-                    .raw
-                    .invalidate_mapped_memory_ranges(&smallvec::SmallVec::<
-                        [vk::MappedMemoryRange; 32],
-                    >::from_iter(vk_ranges))
-            }
-            .unwrap();
-        }
+
+    /// Creates an empty tracker for resources moved between queue families.
+    pub fn create_queue_ownership_tracker(&self) -> super::QueueOwnershipTracker {
+        super::QueueOwnershipTracker::new(self.shared.private_caps.texture_s8)
     }
 
-    unsafe fn create_texture(
+    fn create_bind_group_layout_impl(
         &self,
-        desc: &crate::TextureDescriptor,
-    ) -> Result<super::Texture, crate::DeviceError> {
-        let copy_size = desc.copy_extent();
-
-        let mut raw_flags = vk::ImageCreateFlags::empty();
-        if desc.is_cube_compatible() {
-            raw_flags |= vk::ImageCreateFlags::CUBE_COMPATIBLE;
+        desc: &crate::BindGroupLayoutDescriptor,
+        mut flags: vk::DescriptorSetLayoutCreateFlags,
+    ) -> Result<super::BindGroupLayout, crate::DeviceError> {
+        let update_after_bind = desc
+            .flags
+            .contains(crate::BindGroupLayoutFlags::UPDATE_AFTER_BIND);
+        if update_after_bind {
+            if !self.shared.private_caps.update_after_bind {
+                log::error!("descriptor update-after-bind is not supported");
+                return Err(crate::DeviceError::ResourceCreationFailed);
+            }
+            flags |= vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL;
         }
+        let mut desc_count = gpu_descriptor::DescriptorTotalCount::default();
+        let mut types = Vec::new();
+        for entry in desc.entries {
+            let count = entry.count.map_or(1, |c| c.get());
+            if entry.binding as usize >= types.len() {
+                types.resize(
+                    entry.binding as usize + 1,
+                    (vk::DescriptorType::INPUT_ATTACHMENT, 0),
+                );
+            }
+            types[entry.binding as usize] = (
+                conv::map_binding_type(entry.ty),
+                entry.count.map_or(1, |c| c.get()),
+            );
 
-        let original_format = self.shared.private_caps.map_texture_format(desc.format);
+            match entry.ty {
+                wgt::BindingType::Buffer {
+                    ty,
+                    has_dynamic_offset,
+                    ..
+                } => match ty {
+                    wgt::BufferBindingType::Uniform => {
+                        if has_dynamic_offset {
+                            desc_count.uniform_buffer_dynamic += count;
+                        } else {
+                            desc_count.uniform_buffer += count;
+                        }
+                    }
+                    wgt::BufferBindingType::Storage { .. } => {
+                        if has_dynamic_offset {
+                            desc_count.storage_buffer_dynamic += count;
+                        } else {
+                            desc_count.storage_buffer += count;
+                        }
+                    }
+                },
+                wgt::BindingType::Sampler { .. } => {
+                    desc_count.sampler += count;
+                }
+                wgt::BindingType::Texture { .. } => {
+                    desc_count.sampled_image += count;
+                }
+                wgt::BindingType::StorageTexture { .. } => {
+                    desc_count.storage_image += count;
+                }
+                wgt::BindingType::AccelerationStructure => {
+                    desc_count.acceleration_structure += count;
+                }
+            }
+        }
+
+        let (vk_bindings, binding_flag_vec) = map_bind_group_layout_bindings(desc);
+        let vk_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .flags(flags)
+            .bindings(&vk_bindings);
+
+        let binding_arrays = desc
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, entry)| entry.count.map(|count| (idx as u32, count)))
+            .collect();
+
+        let mut binding_flag_info;
+        let vk_info = if let Some(ref binding_flag_vec) = binding_flag_vec {
+            binding_flag_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::default()
+                .binding_flags(binding_flag_vec);
+
+            vk_info.push_next(&mut binding_flag_info)
+        } else {
+            vk_info
+        };
+
+        let raw = unsafe {
+            self.shared
+                .raw
+                .create_descriptor_set_layout(&vk_info, None)
+                .map_err(super::map_host_device_oom_err)?
+        };
+
+        if let Some(label) = desc.label {
+            unsafe { self.shared.set_object_name(raw, label) };
+        }
+
+        self.counters.bind_group_layouts.add(1);
+
+        Ok(super::BindGroupLayout {
+            raw,
+            desc_count,
+            types: types.into_boxed_slice(),
+            binding_arrays,
+            update_after_bind,
+        })
+    }
+
+    /// Returns `true` if `VK_KHR_push_descriptor` is enabled, see
+    /// [`Self::create_push_descriptor_bind_group_layout`].
+    pub fn supports_push_descriptors(&self) -> bool {
+        self.shared.extension_fns.push_descriptor.is_some()
+    }
+
+    /// Creates a bind group layout whose resources are pushed directly into
+    /// the command buffer with [`super::CommandEncoder::push_bind_group`],
+    /// instead of being bound from a bind group.
+    ///
+    /// A pipeline layout can have at most one such layout. It can't contain
+    /// dynamic buffers, and holds at most `maxPushDescriptors` descriptors,
+    /// which is at least 32.
+    ///
+    /// Fails with [`crate::DeviceError::ResourceCreationFailed`] if
+    /// [`Self::supports_push_descriptors`] is `false`.
+    ///
+    /// # Safety
+    ///
+    /// No bind group may be created with the returned layout.
+    pub unsafe fn create_push_descriptor_bind_group_layout(
+        &self,
+        desc: &crate::BindGroupLayoutDescriptor,
+    ) -> Result<super::BindGroupLayout, crate::DeviceError> {
+        if !self.supports_push_descriptors() {
+            log::error!("VK_KHR_push_descriptor is not enabled");
+            return Err(crate::DeviceError::ResourceCreationFailed);
+        }
+        self.create_bind_group_layout_impl(
+            desc,
+            vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR,
+        )
+    }
+
+    /// Overwrites descriptors of `group` with the resources of `desc`.
+    ///
+    /// Each entry of `desc` writes `count` elements of its binding array,
+    /// starting at `first_array_element`. This allows changing a few
+    /// textures of a large binding array without recreating the group.
+    ///
+    /// # Safety
+    ///
+    /// - `desc.layout` must be the layout of `group`.
+    /// - The written descriptors must not be used by commands that are
+    ///   recorded or executing, unless the layout was created with
+    ///   [`crate::BindGroupLayoutFlags::UPDATE_AFTER_BIND`]. In that case,
+    ///   they must not be in use by executing commands.
+    pub unsafe fn update_bind_group(
+        &self,
+        group: &super::BindGroup,
+        first_array_element: u32,
+        desc: &crate::BindGroupDescriptor<
+            super::BindGroupLayout,
+            super::Buffer,
+            super::Sampler,
+            super::TextureView,
+            super::AccelerationStructure,
+        >,
+    ) {
+        with_descriptor_writes(
+            desc,
+            *group.set.raw(),
+            first_array_element,
+            |writes| unsafe { self.shared.raw.update_descriptor_sets(writes, &[]) },
+        );
+    }
+
+    /// Queries whether a bind group layout with `desc` can be created, using
+    /// `vkGetDescriptorSetLayoutSupport`.
+    ///
+    /// This is useful to check large binding arrays against the device limits
+    /// before creating the layout. Returns `None` if `VK_KHR_maintenance3` is
+    /// not available.
+    pub fn get_descriptor_set_layout_support(
+        &self,
+        desc: &crate::BindGroupLayoutDescriptor,
+    ) -> Option<super::DescriptorSetLayoutSupport> {
+        let maintenance3 = self.shared.extension_fns.maintenance3.as_ref()?;
+        let max_per_set_descriptors = self.shared.private_caps.max_per_set_descriptors?;
+
+        let (vk_bindings, binding_flag_vec) = map_bind_group_layout_bindings(desc);
+        let mut binding_flag_info;
+        let mut vk_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&vk_bindings);
+        if let Some(ref binding_flag_vec) = binding_flag_vec {
+            binding_flag_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::default()
+                .binding_flags(binding_flag_vec);
+            vk_info = vk_info.push_next(&mut binding_flag_info);
+        }
+
+        let mut vk_support = vk::DescriptorSetLayoutSupport::default();
+        match *maintenance3 {
+            super::ExtensionFn::Extension(ref ext) => unsafe {
+                ext.get_descriptor_set_layout_support(&vk_info, &mut vk_support)
+            },
+            super::ExtensionFn::Promoted => unsafe {
+                self.shared
+                    .raw
+                    .get_descriptor_set_layout_support(&vk_info, &mut vk_support)
+            },
+        }
+
+        Some(super::DescriptorSetLayoutSupport {
+            supported: vk_support.supported == vk::TRUE,
+            descriptor_count: vk_bindings.iter().map(|b| b.descriptor_count).sum(),
+            max_per_set_descriptors,
+        })
+    }
+
+    /// Like [`crate::Device::create_render_pipeline`], with the Vulkan-specific
+    /// `options` of the pipeline.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`crate::Device::create_render_pipeline`].
+    pub unsafe fn create_render_pipeline_with_options(
+        &self,
+        desc: &crate::RenderPipelineDescriptor<
+            super::PipelineLayout,
+            super::ShaderModule,
+            super::PipelineCache,
+        >,
+        options: &super::RenderPipelineOptions,
+    ) -> Result<super::RenderPipeline, crate::PipelineError> {
+        self.check_render_pipeline_options(desc, options)?;
+        let mut dynamic_states = ArrayVec::<_, 15>::from_iter([
+            vk::DynamicState::VIEWPORT,
+            vk::DynamicState::SCISSOR,
+            vk::DynamicState::BLEND_CONSTANTS,
+            vk::DynamicState::STENCIL_REFERENCE,
+        ]);
+        // Without `wideLines` the only valid width is 1.0, so it stays static.
+        let dynamic_line_width = self.shared.private_caps.line_width_range.is_some()
+            && (matches!(
+                desc.primitive.topology,
+                wgt::PrimitiveTopology::LineList | wgt::PrimitiveTopology::LineStrip
+            ) || desc.primitive.polygon_mode == wgt::PolygonMode::Line);
+        if dynamic_line_width {
+            dynamic_states.push(vk::DynamicState::LINE_WIDTH);
+        }
+        let depth_clamp = if self.shared.private_caps.dynamic_depth_clamp {
+            dynamic_states.push(vk::DynamicState::DEPTH_CLAMP_ENABLE_EXT);
+            Some(desc.primitive.unclipped_depth)
+        } else {
+            None
+        };
+        let dynamic_fragment_shading_rate = options.dynamic_fragment_shading_rate;
+        if dynamic_fragment_shading_rate {
+            dynamic_states.push(vk::DynamicState::FRAGMENT_SHADING_RATE_KHR);
+        }
+        let (topology, cull_mode) = if self.shared.private_caps.extended_dynamic_state {
+            dynamic_states.extend([
+                vk::DynamicState::PRIMITIVE_TOPOLOGY,
+                vk::DynamicState::CULL_MODE,
+                vk::DynamicState::FRONT_FACE,
+            ]);
+            let cull_mode = desc
+                .primitive
+                .cull_mode
+                .map_or(vk::CullModeFlags::NONE, conv::map_cull_face);
+            (
+                Some(conv::map_topology(desc.primitive.topology)),
+                Some((cull_mode, conv::map_front_face(desc.primitive.front_face))),
+            )
+        } else {
+            (None, None)
+        };
+        let mut compatible_rp_key = super::RenderPassKey {
+            shading_rate: options
+                .shading_rate_attachment_texel_size
+                .map(|texel_size| super::ShadingRateAttachmentKey {
+                    format: self
+                        .shared
+                        .private_caps
+                        .map_texture_format(wgt::TextureFormat::R8Uint),
+                    texel_size,
+                }),
+            sample_count: desc.multisample.count,
+            multiview: desc.multiview,
+            ..Default::default()
+        };
+        let mut stages = ArrayVec::<_, { crate::MAX_CONCURRENT_SHADER_STAGES }>::new();
+        let mut vertex_buffers = Vec::with_capacity(desc.vertex_buffers.len());
+        let mut vertex_attributes = Vec::new();
+
+        for (i, vb) in desc.vertex_buffers.iter().enumerate() {
+            vertex_buffers.push(vk::VertexInputBindingDescription {
+                binding: i as u32,
+                stride: vb.array_stride as u32,
+                input_rate: match vb.step_mode {
+                    wgt::VertexStepMode::Vertex => vk::VertexInputRate::VERTEX,
+                    wgt::VertexStepMode::Instance => vk::VertexInputRate::INSTANCE,
+                },
+            });
+            for at in vb.attributes {
+                vertex_attributes.push(vk::VertexInputAttributeDescription {
+                    location: at.shader_location,
+                    binding: i as u32,
+                    format: conv::map_vertex_format(at.format),
+                    offset: at.offset as u32,
+                });
+            }
+        }
+
+        let vk_vertex_input = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&vertex_buffers)
+            .vertex_attribute_descriptions(&vertex_attributes);
+        // The static vertex input state above is ignored by the driver then.
+        let vertex_input = if options.dynamic_vertex_input {
+            dynamic_states.push(vk::DynamicState::VERTEX_INPUT_EXT);
+            Some(conv::map_vertex_input(desc.vertex_buffers))
+        } else {
+            None
+        };
+
+        let vk_input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(conv::map_topology(desc.primitive.topology))
+            .primitive_restart_enable(desc.primitive.strip_index_format.is_some());
+
+        let compiled_vs = self.compile_stage(
+            &desc.vertex_stage,
+            naga::ShaderStage::Vertex,
+            &desc.layout.binding_arrays,
+        )?;
+        stages.push(compiled_vs.create_info);
+        let compiled_fs = match desc.fragment_stage {
+            Some(ref stage) => {
+                let compiled = self.compile_stage(
+                    stage,
+                    naga::ShaderStage::Fragment,
+                    &desc.layout.binding_arrays,
+                )?;
+                stages.push(compiled.create_info);
+                Some(compiled)
+            }
+            None => None,
+        };
+
+        let mut vk_rasterization = vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(conv::map_polygon_mode(desc.primitive.polygon_mode))
+            .front_face(conv::map_front_face(desc.primitive.front_face))
+            .line_width(1.0)
+            .depth_clamp_enable(desc.primitive.unclipped_depth);
+        if let Some(face) = desc.primitive.cull_mode {
+            vk_rasterization = vk_rasterization.cull_mode(conv::map_cull_face(face))
+        }
+        let mut vk_rasterization_conservative_state =
+            vk::PipelineRasterizationConservativeStateCreateInfoEXT::default()
+                .conservative_rasterization_mode(
+                    vk::ConservativeRasterizationModeEXT::OVERESTIMATE,
+                );
+        if desc.primitive.conservative {
+            vk_rasterization = vk_rasterization.push_next(&mut vk_rasterization_conservative_state);
+        }
+
+        let mut vk_depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default();
+        let mut depth_bias = None;
+        let mut dynamic_depth_bounds = false;
+        if let Some(ref ds) = desc.depth_stencil {
+            let vk_format = self.shared.private_caps.map_texture_format(ds.format);
+            let vk_layout = if ds.is_read_only(desc.primitive.cull_mode) {
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+            } else {
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+            };
+            compatible_rp_key.depth_stencil = Some(super::DepthStencilAttachmentKey {
+                base: super::AttachmentKey::compatible(vk_format, vk_layout),
+                stencil_ops: crate::AttachmentOps::all(),
+                resolve: None,
+            });
+
+            if ds.is_depth_enabled() {
+                vk_depth_stencil = vk_depth_stencil
+                    .depth_test_enable(true)
+                    .depth_write_enable(ds.depth_write_enabled)
+                    .depth_compare_op(conv::map_comparison(ds.depth_compare));
+            }
+            if ds.stencil.is_enabled() {
+                let s = &ds.stencil;
+                let front = conv::map_stencil_face(&s.front, s.read_mask, s.write_mask);
+                let back = conv::map_stencil_face(&s.back, s.read_mask, s.write_mask);
+                vk_depth_stencil = vk_depth_stencil
+                    .stencil_test_enable(true)
+                    .front(front)
+                    .back(back);
+            }
+
+            if ds.bias.is_enabled() {
+                // The factors are set when the pipeline is bound, so that
+                // they can be changed with `set_depth_bias`.
+                vk_rasterization = vk_rasterization.depth_bias_enable(true);
+                dynamic_states.push(vk::DynamicState::DEPTH_BIAS);
+                depth_bias = Some(ds.bias);
+            }
+
+            // The bounds are set when the pipeline is bound, and cover all
+            // depths unless changed with `set_depth_bounds`.
+            if options.depth_bounds {
+                vk_depth_stencil = vk_depth_stencil.depth_bounds_test_enable(true);
+                dynamic_states.push(vk::DynamicState::DEPTH_BOUNDS);
+                dynamic_depth_bounds = true;
+            }
+        }
+
+        let vk_viewport = vk::PipelineViewportStateCreateInfo::default()
+            .flags(vk::PipelineViewportStateCreateFlags::empty())
+            .scissor_count(1)
+            .viewport_count(1);
+
+        let vk_sample_mask = [
+            desc.multisample.mask as u32,
+            (desc.multisample.mask >> 32) as u32,
+        ];
+        let vk_sample_count = vk::SampleCountFlags::from_raw(desc.multisample.count);
+        let mut vk_multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk_sample_count)
+            .alpha_to_coverage_enable(desc.multisample.alpha_to_coverage_enabled)
+            .sample_mask(&vk_sample_mask);
+        // The locations are set when the pipeline is bound, and are the
+        // standard ones unless changed with `set_sample_locations`.
+        let mut vk_sample_locations =
+            vk::PipelineSampleLocationsStateCreateInfoEXT::default().sample_locations_enable(true);
+        let dynamic_sample_locations = options.custom_sample_locations;
+        if dynamic_sample_locations {
+            vk_multisample = vk_multisample.push_next(&mut vk_sample_locations);
+            dynamic_states.push(vk::DynamicState::SAMPLE_LOCATIONS_EXT);
+        }
+
+        let mut vk_attachments = Vec::with_capacity(desc.color_targets.len());
+        let mut advanced_blend = None;
+        for cat in desc.color_targets {
+            let (key, attarchment) = if let Some(cat) = cat.as_ref() {
+                let mut vk_attachment = vk::PipelineColorBlendAttachmentState::default()
+                    .color_write_mask(vk::ColorComponentFlags::from_raw(cat.write_mask.bits()));
+                if let Some(ref blend) = cat.blend {
+                    let (color_op, color_src, color_dst) = conv::map_blend_component(&blend.color);
+                    let (alpha_op, alpha_src, alpha_dst) = conv::map_blend_component(&blend.alpha);
+                    vk_attachment = vk_attachment
+                        .blend_enable(true)
+                        .color_blend_op(color_op)
+                        .src_color_blend_factor(color_src)
+                        .dst_color_blend_factor(color_dst)
+                        .alpha_blend_op(alpha_op)
+                        .src_alpha_blend_factor(alpha_src)
+                        .dst_alpha_blend_factor(alpha_dst);
+
+                    if let Some(advanced) = blend.advanced {
+                        // Advanced blend operations ignore the blend factors and
+                        // must be used for both the color and alpha components.
+                        let op = conv::map_advanced_blend_op(advanced.operation);
+                        vk_attachment = vk_attachment.color_blend_op(op).alpha_blend_op(op);
+                        advanced_blend = Some(advanced);
+                    }
+                }
+
+                let vk_format = self.shared.private_caps.map_texture_format(cat.format);
+                (
+                    Some(super::ColorAttachmentKey {
+                        base: super::AttachmentKey::compatible(
+                            vk_format,
+                            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                        ),
+                        resolve: None,
+                    }),
+                    vk_attachment,
+                )
+            } else {
+                (None, vk::PipelineColorBlendAttachmentState::default())
+            };
+
+            compatible_rp_key.colors.push(key);
+            vk_attachments.push(attarchment);
+        }
+
+        let mut vk_color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&vk_attachments);
+        let color_write_attachments = if options.dynamic_color_write_enable {
+            dynamic_states.push(vk::DynamicState::COLOR_WRITE_ENABLE_EXT);
+            Some(vk_attachments.len() as u32)
+        } else {
+            None
+        };
+
+        let mut vk_advanced_blend;
+        if let Some(advanced) = advanced_blend {
+            self.validate_advanced_blend(desc.color_targets, &advanced)?;
+            vk_advanced_blend = vk::PipelineColorBlendAdvancedStateCreateInfoEXT::default()
+                .src_premultiplied(advanced.src_premultiplied)
+                .dst_premultiplied(advanced.dst_premultiplied)
+                .blend_overlap(conv::map_blend_overlap(advanced.overlap));
+            vk_color_blend = vk_color_blend.push_next(&mut vk_advanced_blend);
+        }
+
+        let vk_dynamic_state =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+        let render_pass_signature = compatible_rp_key.signature();
+        let raw_pass = self
+            .shared
+            .make_render_pass(compatible_rp_key)
+            .map_err(crate::DeviceError::from)?;
+
+        let vk_infos = [{
+            vk::GraphicsPipelineCreateInfo::default()
+                .layout(desc.layout.raw)
+                .stages(&stages)
+                .vertex_input_state(&vk_vertex_input)
+                .input_assembly_state(&vk_input_assembly)
+                .rasterization_state(&vk_rasterization)
+                .viewport_state(&vk_viewport)
+                .multisample_state(&vk_multisample)
+                .depth_stencil_state(&vk_depth_stencil)
+                .color_blend_state(&vk_color_blend)
+                .dynamic_state(&vk_dynamic_state)
+                .render_pass(raw_pass)
+        }];
+
+        let pipeline_cache = desc
+            .cache
+            .map(|it| it.raw)
+            .unwrap_or(vk::PipelineCache::null());
+
+        let mut raw_vec = {
+            profiling::scope!("vkCreateGraphicsPipelines");
+            unsafe {
+                self.shared
+                    .raw
+                    .create_graphics_pipelines(pipeline_cache, &vk_infos, None)
+                    .map_err(|(_, e)| super::map_pipeline_err(e))
+            }?
+        };
+
+        let raw = raw_vec.pop().unwrap();
+        if let Some(label) = desc.label {
+            unsafe { self.shared.set_object_name(raw, label) };
+        }
+
+        if let Some(raw_module) = compiled_vs.temp_raw_module {
+            unsafe { self.shared.raw.destroy_shader_module(raw_module, None) };
+        }
+        if let Some(CompiledStage {
+            temp_raw_module: Some(raw_module),
+            ..
+        }) = compiled_fs
+        {
+            unsafe { self.shared.raw.destroy_shader_module(raw_module, None) };
+        }
+
+        self.counters.render_pipelines.add(1);
+
+        Ok(super::RenderPipeline {
+            raw,
+            render_pass_signature,
+            dynamic_line_width,
+            depth_clamp,
+            topology,
+            cull_mode,
+            depth_bias,
+            dynamic_depth_bounds,
+            dynamic_sample_locations,
+            dynamic_fragment_shading_rate,
+            color_write_attachments,
+            vertex_input,
+        })
+    }
+
+    /// Checks that the device supports all of `options` for `desc`.
+    fn check_render_pipeline_options(
+        &self,
+        desc: &crate::RenderPipelineDescriptor<
+            super::PipelineLayout,
+            super::ShaderModule,
+            super::PipelineCache,
+        >,
+        options: &super::RenderPipelineOptions,
+    ) -> Result<(), crate::DeviceError> {
+        let caps = &self.shared.private_caps;
+        let error = |msg: &str| {
+            log::error!("{msg}");
+            Err(crate::DeviceError::ResourceCreationFailed)
+        };
+        if options.depth_bounds && !caps.depth_bounds {
+            return error("the `depthBounds` feature is not supported");
+        }
+        if options.custom_sample_locations
+            && (desc.multisample.count == 1
+                || !caps
+                    .sample_location_sample_counts
+                    .contains(vk::SampleCountFlags::from_raw(desc.multisample.count)))
+        {
+            return error("custom sample locations are not supported for this sample count");
+        }
+        if options.dynamic_color_write_enable && !caps.color_write_enable {
+            return error("color write enables require `VK_EXT_color_write_enable`");
+        }
+        if options.dynamic_vertex_input && !caps.vertex_input_dynamic_state {
+            return error("dynamic vertex input requires `VK_EXT_vertex_input_dynamic_state`");
+        }
+        if options.dynamic_fragment_shading_rate && caps.fragment_shading_rate.is_none() {
+            return error("fragment shading rates require `VK_KHR_fragment_shading_rate`");
+        }
+        if options.shading_rate_attachment_texel_size.is_some()
+            && caps
+                .fragment_shading_rate
+                .and_then(|caps| caps.attachment_texel_sizes)
+                .is_none()
+        {
+            return error("shading rate attachments are not supported");
+        }
+        Ok(())
+    }
+
+    /// Checks `color_targets` against the limits of `VK_EXT_blend_operation_advanced`.
+    ///
+    /// `advanced` is the advanced blend state shared by all targets using it.
+    /// Unsupported states are logged, and fail pipeline creation like other
+    /// missing device capabilities.
+    fn validate_advanced_blend(
+        &self,
+        color_targets: &[Option<wgt::ColorTargetState>],
+        advanced: &wgt::AdvancedBlendState,
+    ) -> Result<(), crate::DeviceError> {
+        let error = |msg: String| {
+            log::error!("{msg}");
+            crate::DeviceError::ResourceCreationFailed
+        };
+        let caps = self
+            .shared
+            .private_caps
+            .blend_operation_advanced
+            .ok_or_else(|| error("advanced blend operations are not supported".to_string()))?;
+
+        if color_targets.len() as u32 > caps.max_color_attachments {
+            return Err(error(format!(
+                "advanced blend operations support at most {} color targets, but {} were given",
+                caps.max_color_attachments,
+                color_targets.len()
+            )));
+        }
+        if !caps.non_premultiplied_src_color && !advanced.src_premultiplied {
+            return Err(error(
+                "non-premultiplied source colors are not supported".to_string(),
+            ));
+        }
+        if !caps.non_premultiplied_dst_color && !advanced.dst_premultiplied {
+            return Err(error(
+                "non-premultiplied destination colors are not supported".to_string(),
+            ));
+        }
+        if !caps.correlated_overlap && advanced.overlap != wgt::BlendOverlap::Uncorrelated {
+            return Err(error(format!(
+                "blend overlap {:?} is not supported",
+                advanced.overlap
+            )));
+        }
+        if !caps.independent_blend {
+            // Every enabled blend must use the same advanced operation.
+            let uniform = color_targets
+                .iter()
+                .flatten()
+                .filter_map(|cat| cat.blend)
+                .all(|blend| blend.advanced.map(|a| a.operation) == Some(advanced.operation));
+            if !uniform {
+                return Err(error(
+                    "advanced blend operations must be the same for all color targets".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collects the `OpEntryPoint` declarations of a SPIR-V module.
+///
+/// Entry points with execution models wgpu doesn't use are skipped.
+fn parse_spirv_entry_points(spv: &[u32]) -> Vec<(naga::ShaderStage, String)> {
+    const HEADER_WORDS: usize = 5;
+    const OP_ENTRY_POINT: u32 = 15;
+    const OP_FUNCTION: u32 = 54;
+
+    let mut entry_points = Vec::new();
+    let mut words = spv.get(HEADER_WORDS..).unwrap_or_default();
+    while let Some(&first) = words.first() {
+        let (word_count, opcode) = ((first >> 16) as usize, first & 0xffff);
+        if word_count == 0 || word_count > words.len() {
+            break;
+        }
+        // Entry points are declared before any function definition.
+        if opcode == OP_FUNCTION {
+            break;
+        }
+        if opcode == OP_ENTRY_POINT && word_count > 3 {
+            let stage = match words[1] {
+                0 => Some(naga::ShaderStage::Vertex),
+                4 => Some(naga::ShaderStage::Fragment),
+                5 => Some(naga::ShaderStage::Compute),
+                _ => None,
+            };
+            let name_bytes = words[3..word_count]
+                .iter()
+                .flat_map(|word| word.to_le_bytes())
+                .take_while(|&byte| byte != 0)
+                .collect::<Vec<_>>();
+            if let (Some(stage), Ok(name)) = (stage, String::from_utf8(name_bytes)) {
+                entry_points.push((stage, name));
+            }
+        }
+        words = &words[word_count..];
+    }
+    entry_points
+}
+
+/// Builds the descriptor writes of the resources in `desc` into `set`, and
+/// passes them to `f`. Each entry is written from `first_array_element` on.
+///
+/// The writes borrow local storage, so they can't outlive the call.
+pub(super) fn with_descriptor_writes<R>(
+    desc: &crate::BindGroupDescriptor<
+        super::BindGroupLayout,
+        super::Buffer,
+        super::Sampler,
+        super::TextureView,
+        super::AccelerationStructure,
+    >,
+    set: vk::DescriptorSet,
+    first_array_element: u32,
+    f: impl FnOnce(&[vk::WriteDescriptorSet]) -> R,
+) -> R {
+    /// Helper for splitting off and initializing a given number of elements on a pre-allocated
+    /// stack, based on items returned from an [`ExactSizeIterator`].  Typically created from a
+    /// [`MaybeUninit`] slice (see [`Vec::spare_capacity_mut()`]).
+    /// The updated [`ExtensionStack`] of remaining uninitialized elements is returned, safely
+    /// representing that the initialized and remaining elements are two independent mutable
+    /// borrows.
+    struct ExtendStack<'a, T> {
+        remainder: &'a mut [MaybeUninit<T>],
+    }
+
+    impl<'a, T> ExtendStack<'a, T> {
+        fn from_vec_capacity(vec: &'a mut Vec<T>) -> Self {
+            Self {
+                remainder: vec.spare_capacity_mut(),
+            }
+        }
+
+        fn extend_one(self, value: T) -> (Self, &'a mut T) {
+            let (to_init, remainder) = self.remainder.split_first_mut().unwrap();
+            let init = to_init.write(value);
+            (Self { remainder }, init)
+        }
+
+        fn extend(
+            self,
+            iter: impl IntoIterator<Item = T> + ExactSizeIterator,
+        ) -> (Self, &'a mut [T]) {
+            let (to_init, remainder) = self.remainder.split_at_mut(iter.len());
+
+            for (value, to_init) in iter.into_iter().zip(to_init.iter_mut()) {
+                to_init.write(value);
+            }
+
+            // we can't use the safe (yet unstable) MaybeUninit::write_slice() here because of having an iterator to write
+
+            let init = {
+                // SAFETY: The loop above has initialized exactly as many items as to_init is
+                // long, so it is safe to cast away the MaybeUninit<T> wrapper into T.
+
+                // Additional safety docs from unstable slice_assume_init_mut
+                // SAFETY: similar to safety notes for `slice_get_ref`, but we have a
+                // mutable reference which is also guaranteed to be valid for writes.
+                unsafe { std::mem::transmute::<&mut [MaybeUninit<T>], &mut [T]>(to_init) }
+            };
+            (Self { remainder }, init)
+        }
+    }
+
+    let mut writes = Vec::with_capacity(desc.entries.len());
+    let mut buffer_infos = Vec::with_capacity(desc.buffers.len());
+    let mut buffer_infos = ExtendStack::from_vec_capacity(&mut buffer_infos);
+    let mut image_infos = Vec::with_capacity(desc.samplers.len() + desc.textures.len());
+    let mut image_infos = ExtendStack::from_vec_capacity(&mut image_infos);
+    // TODO: This length could be reduced to just the number of top-level acceleration
+    // structure bindings, where multiple consecutive TLAS bindings that are set via
+    // one `WriteDescriptorSet` count towards one "info" struct, not the total number of
+    // acceleration structure bindings to write:
+    let mut acceleration_structure_infos = Vec::with_capacity(desc.acceleration_structures.len());
+    let mut acceleration_structure_infos =
+        ExtendStack::from_vec_capacity(&mut acceleration_structure_infos);
+    let mut raw_acceleration_structures = Vec::with_capacity(desc.acceleration_structures.len());
+    let mut raw_acceleration_structures =
+        ExtendStack::from_vec_capacity(&mut raw_acceleration_structures);
+    for entry in desc.entries {
+        let (ty, size) = desc.layout.types[entry.binding as usize];
+        if size == 0 {
+            continue; // empty slot
+        }
+        let mut write = vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_array_element(first_array_element)
+            .dst_binding(entry.binding)
+            .descriptor_type(ty);
+
+        write = match ty {
+            vk::DescriptorType::SAMPLER => {
+                let start = entry.resource_index;
+                let end = start + entry.count;
+                let local_image_infos;
+                (image_infos, local_image_infos) = image_infos.extend(
+                    desc.samplers[start as usize..end as usize]
+                        .iter()
+                        .map(|sampler| vk::DescriptorImageInfo::default().sampler(sampler.raw)),
+                );
+                write.image_info(local_image_infos)
+            }
+            vk::DescriptorType::SAMPLED_IMAGE | vk::DescriptorType::STORAGE_IMAGE => {
+                let start = entry.resource_index;
+                let end = start + entry.count;
+                let local_image_infos;
+                (image_infos, local_image_infos) =
+                    image_infos.extend(desc.textures[start as usize..end as usize].iter().map(
+                        |binding| {
+                            let layout = conv::derive_image_layout(
+                                binding.usage,
+                                binding.view.attachment.view_format,
+                            );
+                            vk::DescriptorImageInfo::default()
+                                .image_view(binding.view.raw)
+                                .image_layout(layout)
+                        },
+                    ));
+                write.image_info(local_image_infos)
+            }
+            vk::DescriptorType::UNIFORM_BUFFER
+            | vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+            | vk::DescriptorType::STORAGE_BUFFER
+            | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC => {
+                let start = entry.resource_index;
+                let end = start + entry.count;
+                let local_buffer_infos;
+                (buffer_infos, local_buffer_infos) =
+                    buffer_infos.extend(desc.buffers[start as usize..end as usize].iter().map(
+                        |binding| {
+                            vk::DescriptorBufferInfo::default()
+                                .buffer(binding.buffer.raw)
+                                .offset(binding.offset)
+                                .range(binding.size.map_or(vk::WHOLE_SIZE, wgt::BufferSize::get))
+                        },
+                    ));
+                write.buffer_info(local_buffer_infos)
+            }
+            vk::DescriptorType::ACCELERATION_STRUCTURE_KHR => {
+                let start = entry.resource_index;
+                let end = start + entry.count;
+
+                let local_raw_acceleration_structures;
+                (
+                    raw_acceleration_structures,
+                    local_raw_acceleration_structures,
+                ) = raw_acceleration_structures.extend(
+                    desc.acceleration_structures[start as usize..end as usize]
+                        .iter()
+                        .map(|acceleration_structure| acceleration_structure.raw),
+                );
+
+                let local_acceleration_structure_infos;
+                (
+                    acceleration_structure_infos,
+                    local_acceleration_structure_infos,
+                ) = acceleration_structure_infos.extend_one(
+                    vk::WriteDescriptorSetAccelerationStructureKHR::default()
+                        .acceleration_structures(local_raw_acceleration_structures),
+                );
+
+                write
+                    .descriptor_count(entry.count)
+                    .push_next(local_acceleration_structure_infos)
+            }
+            _ => unreachable!(),
+        };
+
+        writes.push(write);
+    }
+
+    f(&writes)
+}
+
+/// Maps the entries of `desc` to Vulkan descriptor set layout bindings.
+///
+/// Also returns the per-binding flags, if any of them need to be set.
+fn map_bind_group_layout_bindings(
+    desc: &crate::BindGroupLayoutDescriptor,
+) -> (
+    Vec<vk::DescriptorSetLayoutBinding<'static>>,
+    Option<Vec<vk::DescriptorBindingFlags>>,
+) {
+    //Note: not bothering with on stack array here as it's low frequency
+    let vk_bindings = desc
+        .entries
+        .iter()
+        .map(|entry| vk::DescriptorSetLayoutBinding {
+            binding: entry.binding,
+            descriptor_type: conv::map_binding_type(entry.ty),
+            descriptor_count: entry.count.map_or(1, |c| c.get()),
+            stage_flags: conv::map_shader_stage(entry.visibility),
+            p_immutable_samplers: ptr::null(),
+            _marker: Default::default(),
+        })
+        .collect::<Vec<_>>();
+
+    let partially_bound = desc
+        .flags
+        .contains(crate::BindGroupLayoutFlags::PARTIALLY_BOUND);
+    let update_after_bind = desc
+        .flags
+        .contains(crate::BindGroupLayoutFlags::UPDATE_AFTER_BIND);
+    let binding_flag_vec = (partially_bound || update_after_bind).then(|| {
+        desc.entries
+            .iter()
+            .map(|entry| {
+                let mut flags = vk::DescriptorBindingFlags::empty();
+
+                if partially_bound && entry.count.is_some() {
+                    flags |= vk::DescriptorBindingFlags::PARTIALLY_BOUND;
+                }
+                // Uniform and dynamic buffers can't be updated after bind.
+                let updatable = match entry.ty {
+                    wgt::BindingType::Buffer {
+                        ty: wgt::BufferBindingType::Storage { .. },
+                        has_dynamic_offset,
+                        ..
+                    } => !has_dynamic_offset,
+                    wgt::BindingType::Sampler { .. }
+                    | wgt::BindingType::Texture { .. }
+                    | wgt::BindingType::StorageTexture { .. } => true,
+                    wgt::BindingType::Buffer { .. } | wgt::BindingType::AccelerationStructure => {
+                        false
+                    }
+                };
+                if update_after_bind && entry.count.is_some() && updatable {
+                    flags |= vk::DescriptorBindingFlags::UPDATE_AFTER_BIND;
+                }
+
+                flags
+            })
+            .collect::<Vec<_>>()
+    });
+
+    (vk_bindings, binding_flag_vec)
+}
+
+/// A staging buffer for copies of a region of a texture, see
+/// [`super::Device::read_texture`] and [`super::Device::write_texture`].
+struct StagingRegion {
+    buffer: super::Buffer,
+    copy: crate::BufferTextureCopy,
+    range: wgt::ImageSubresourceRange,
+    packed_bytes_per_row: usize,
+    padded_bytes_per_row: usize,
+    /// The size of the region without row padding.
+    packed_size: u64,
+    size: u64,
+}
+
+/// Drops the padding at the end of each row of `padded`.
+fn strip_row_padding(padded: &[u8], packed_row_size: usize, padded_row_size: usize) -> Vec<u8> {
+    padded
+        .chunks(padded_row_size)
+        .flat_map(|row| &row[..packed_row_size])
+        .copied()
+        .collect()
+}
+
+/// Returns the mask of the `valid_bits` meaningful low bits of timestamps.
+///
+/// Queues without timestamps report 0 valid bits, which keeps all of them.
+fn timestamp_mask(valid_bits: u32) -> u64 {
+    match valid_bits {
+        1..=63 => (1 << valid_bits) - 1,
+        _ => u64::MAX,
+    }
+}
+
+/// Returns the ticks from `start` to `end`, wrapping around at
+/// `valid_bits` bits.
+fn timestamp_delta(start: u64, end: u64, valid_bits: u32) -> u64 {
+    let mask = timestamp_mask(valid_bits);
+    (end & mask).wrapping_sub(start & mask) & mask
+}
+
+/// Converts `ticks` of `period` nanoseconds each to time, in `f64` to avoid
+/// overflows.
+fn ticks_to_duration(ticks: u64, period: f32) -> std::time::Duration {
+    let nanos = ticks as f64 * period as f64;
+    // Saturates at `u64::MAX` nanoseconds, over 500 years.
+    std::time::Duration::from_nanos(nanos as u64)
+}
+
+/// Copies the rows of `packed` into `padded`, leaving the padding at the end
+/// of each row as is.
+fn add_row_padding(
+    padded: &mut [u8],
+    packed: &[u8],
+    packed_row_size: usize,
+    padded_row_size: usize,
+) {
+    for (dst, src) in padded
+        .chunks_mut(padded_row_size)
+        .zip(packed.chunks(packed_row_size))
+    {
+        dst[..packed_row_size].copy_from_slice(src);
+    }
+}
+
+impl crate::Device for super::Device {
+    type A = super::Api;
+
+    unsafe fn exit(self, queue: super::Queue) {
+        unsafe { self.mem_allocator.into_inner().cleanup(&*self.shared) };
+        unsafe { self.desc_allocator.into_inner().cleanup(&*self.shared) };
+        unsafe {
+            queue
+                .relay_semaphores
+                .into_inner()
+                .destroy(&self.shared.raw)
+        };
+        unsafe { self.shared.free_resources() };
+    }
+
+    unsafe fn create_buffer(
+        &self,
+        desc: &crate::BufferDescriptor,
+    ) -> Result<super::Buffer, crate::DeviceError> {
+        let vk_info = vk::BufferCreateInfo::default()
+            .size(desc.size)
+            .usage(conv::map_buffer_usage(desc.usage))
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let raw = unsafe {
+            self.shared
+                .raw
+                .create_buffer(&vk_info, None)
+                .map_err(super::map_host_device_oom_and_ioca_err)?
+        };
+        let req = unsafe { self.shared.raw.get_buffer_memory_requirements(raw) };
+
+        let mut alloc_usage = if desc
+            .usage
+            .intersects(crate::BufferUses::MAP_READ | crate::BufferUses::MAP_WRITE)
+        {
+            let mut flags = gpu_alloc::UsageFlags::HOST_ACCESS;
+            //TODO: find a way to use `crate::MemoryFlags::PREFER_COHERENT`
+            flags.set(
+                gpu_alloc::UsageFlags::DOWNLOAD,
+                desc.usage.contains(crate::BufferUses::MAP_READ),
+            );
+            flags.set(
+                gpu_alloc::UsageFlags::UPLOAD,
+                desc.usage.contains(crate::BufferUses::MAP_WRITE),
+            );
+            flags
+        } else {
+            gpu_alloc::UsageFlags::FAST_DEVICE_ACCESS
+        };
+        alloc_usage.set(
+            gpu_alloc::UsageFlags::TRANSIENT,
+            desc.memory_flags.contains(crate::MemoryFlags::TRANSIENT),
+        );
+
+        let alignment_mask = if desc.usage.intersects(
+            crate::BufferUses::TOP_LEVEL_ACCELERATION_STRUCTURE_INPUT
+                | crate::BufferUses::BOTTOM_LEVEL_ACCELERATION_STRUCTURE_INPUT,
+        ) {
+            16
+        } else {
+            req.alignment
+        } - 1;
+
+        let block = unsafe {
+            self.mem_allocator.lock().alloc(
+                &*self.shared,
+                gpu_alloc::Request {
+                    size: req.size,
+                    align_mask: alignment_mask,
+                    usage: alloc_usage,
+                    memory_types: req.memory_type_bits & self.valid_ash_memory_types,
+                },
+            )?
+        };
+
+        unsafe {
+            self.shared
+                .raw
+                .bind_buffer_memory(raw, *block.memory(), block.offset())
+                .map_err(super::map_host_device_oom_and_ioca_err)?
+        };
+
+        if let Some(label) = desc.label {
+            unsafe { self.shared.set_object_name(raw, label) };
+        }
+
+        self.counters.buffer_memory.add(block.size() as isize);
+        self.counters.buffers.add(1);
+
+        Ok(super::Buffer {
+            raw,
+            block: Some(Mutex::new(block)),
+            size: Some(desc.size),
+        })
+    }
+    unsafe fn destroy_buffer(&self, buffer: super::Buffer) {
+        unsafe { self.shared.raw.destroy_buffer(buffer.raw, None) };
+        if let Some(block) = buffer.block {
+            let block = block.into_inner();
+            self.counters.buffer_memory.sub(block.size() as isize);
+            unsafe { self.mem_allocator.lock().dealloc(&*self.shared, block) };
+        }
+
+        self.counters.buffers.sub(1);
+    }
+
+    unsafe fn map_buffer(
+        &self,
+        buffer: &super::Buffer,
+        range: crate::MemoryRange,
+    ) -> Result<crate::BufferMapping, crate::DeviceError> {
+        if let Some(ref block) = buffer.block {
+            let size = range.end - range.start;
+            let mut block = block.lock();
+            let ptr = unsafe { block.map(&*self.shared, range.start, size as usize)? };
+            let is_coherent = block
+                .props()
+                .contains(gpu_alloc::MemoryPropertyFlags::HOST_COHERENT);
+            Ok(crate::BufferMapping { ptr, is_coherent })
+        } else {
+            super::hal_usage_error("tried to map external buffer")
+        }
+    }
+    unsafe fn unmap_buffer(&self, buffer: &super::Buffer) {
+        if let Some(ref block) = buffer.block {
+            unsafe { block.lock().unmap(&*self.shared) };
+        } else {
+            super::hal_usage_error("tried to unmap external buffer")
+        }
+    }
+
+    unsafe fn flush_mapped_ranges<I>(&self, buffer: &super::Buffer, ranges: I)
+    where
+        I: Iterator<Item = crate::MemoryRange>,
+    {
+        if let Some(vk_ranges) = self.shared.make_memory_ranges(buffer, ranges) {
+            unsafe {
+                self.shared
+                    .raw
+                    .flush_mapped_memory_ranges(
+                        &smallvec::SmallVec::<[vk::MappedMemoryRange; 32]>::from_iter(vk_ranges),
+                    )
+            }
+            .unwrap();
+        }
+    }
+    unsafe fn invalidate_mapped_ranges<I>(&self, buffer: &super::Buffer, ranges: I)
+    where
+        I: Iterator<Item = crate::MemoryRange>,
+    {
+        if let Some(vk_ranges) = self.shared.make_memory_ranges(buffer, ranges) {
+            unsafe {
+                self.shared
+                    .raw
+                    .invalidate_mapped_memory_ranges(&smallvec::SmallVec::<
+                        [vk::MappedMemoryRange; 32],
+                    >::from_iter(vk_ranges))
+            }
+            .unwrap();
+        }
+    }
+
+    unsafe fn create_texture(
+        &self,
+        desc: &crate::TextureDescriptor,
+    ) -> Result<super::Texture, crate::DeviceError> {
+        let copy_size = desc.copy_extent();
+
+        let mut raw_flags = vk::ImageCreateFlags::empty();
+        if desc.is_cube_compatible() {
+            raw_flags |= vk::ImageCreateFlags::CUBE_COMPATIBLE;
+        }
+
+        let original_format = self.shared.private_caps.map_texture_format(desc.format);
         let mut vk_view_formats = vec![];
         let mut wgt_view_formats = vec![];
         if !desc.view_formats.is_empty() {
@@ -1040,6 +2564,18 @@ impl crate::Device for super::Device {
         if desc.format.is_multi_planar_format() {
             raw_flags |= vk::ImageCreateFlags::MUTABLE_FORMAT;
         }
+        // Multisampled depth may be rendered to by pipelines with custom
+        // sample locations, see `RenderPipelineOptions::custom_sample_locations`.
+        if desc.format.is_depth_stencil_format()
+            && desc.sample_count > 1
+            && self
+                .shared
+                .private_caps
+                .sample_location_sample_counts
+                .contains(vk::SampleCountFlags::from_raw(desc.sample_count))
+        {
+            raw_flags |= vk::ImageCreateFlags::SAMPLE_LOCATIONS_COMPATIBLE_DEPTH_EXT;
+        }
 
         let mut vk_info = vk::ImageCreateInfo::default()
             .flags(raw_flags)
@@ -1108,6 +2644,9 @@ impl crate::Device for super::Device {
             format: desc.format,
             raw_flags,
             copy_size,
+            sample_count: desc.sample_count,
+            mip_level_count: desc.mip_level_count,
+            array_layer_count: desc.array_layer_count(),
             view_formats: wgt_view_formats,
         })
     }
@@ -1136,8 +2675,17 @@ impl crate::Device for super::Device {
             .view_type(conv::map_view_dimension(desc.dimension))
             .format(self.shared.private_caps.map_texture_format(desc.format))
             .subresource_range(subresource_range);
-        let layers =
-            NonZeroU32::new(subresource_range.layer_count).expect("Unexpected zero layer count");
+        let layer_count = if subresource_range.layer_count == vk::REMAINING_ARRAY_LAYERS {
+            texture.array_layer_count - subresource_range.base_array_layer
+        } else {
+            subresource_range.layer_count
+        };
+        let layers = NonZeroU32::new(layer_count).expect("Unexpected zero layer count");
+        let mip_level = subresource_range.base_mip_level;
+        let size = [
+            (texture.copy_size.width >> mip_level).max(1),
+            (texture.copy_size.height >> mip_level).max(1),
+        ];
 
         let mut image_view_info;
         let view_usage = if self.shared.private_caps.image_view_usage && !desc.usage.is_empty() {
@@ -1165,6 +2713,7 @@ impl crate::Device for super::Device {
             raw_image_flags: texture.raw_flags,
             view_usage,
             view_format: desc.format,
+            layers: layers.get(),
             raw_view_formats: texture
                 .view_formats
                 .iter()
@@ -1177,6 +2726,7 @@ impl crate::Device for super::Device {
         Ok(super::TextureView {
             raw,
             layers,
+            size,
             attachment,
         })
     }
@@ -1253,6 +2803,9 @@ impl crate::Device for super::Device {
         &self,
         desc: &crate::CommandEncoderDescriptor<super::Queue>,
     ) -> Result<super::CommandEncoder, crate::DeviceError> {
+        // Command buffers are recorded once and recycled soon after, hence
+        // `TRANSIENT`. They are never reset one by one: `reset_all` resets the
+        // whole pool, so `RESET_COMMAND_BUFFER` would only add overhead.
         let vk_info = vk::CommandPoolCreateInfo::default()
             .queue_family_index(desc.queue.family_index)
             .flags(vk::CommandPoolCreateFlags::TRANSIENT);
@@ -1271,11 +2824,30 @@ impl crate::Device for super::Device {
             device: Arc::clone(&self.shared),
             active: vk::CommandBuffer::null(),
             bind_point: vk::PipelineBindPoint::default(),
+            encoding: super::EncodingState::new(),
             temp: super::Temp::default(),
             free: Vec::new(),
             discarded: Vec::new(),
+            free_secondary: Vec::new(),
+            discarded_secondary: Vec::new(),
+            active_is_secondary: false,
+            active_is_external: false,
+            subpass_contents: vk::SubpassContents::INLINE,
+            render_pass_inheritance: None,
+            bound_render_pipeline: vk::Pipeline::null(),
+            bound_compute_pipeline: vk::Pipeline::null(),
+            color_write_attachments: None,
+            render_pass_signature: None,
+            render_pass_color_formats: ArrayVec::new(),
+            viewport_count: 0,
+            scissor_count: 0,
+            stats: super::EncoderStats::default(),
+            active_pass: None,
+            pending_bind_groups: super::PendingBindGroups::default(),
+            signal: None,
             rpass_debug_marker_active: false,
             end_of_pass_timer_query: None,
+            barrier_elision: true,
         })
     }
     unsafe fn destroy_command_encoder(&self, cmd_encoder: super::CommandEncoder) {
@@ -1283,7 +2855,7 @@ impl crate::Device for super::Device {
             // `vkDestroyCommandPool` also frees any command buffers allocated
             // from that pool, so there's no need to explicitly call
             // `vkFreeCommandBuffers` on `cmd_encoder`'s `free` and `discarded`
-            // fields.
+            // fields, nor on their secondary counterparts.
             self.shared.raw.destroy_command_pool(cmd_encoder.raw, None);
         }
 
@@ -1294,129 +2866,7 @@ impl crate::Device for super::Device {
         &self,
         desc: &crate::BindGroupLayoutDescriptor,
     ) -> Result<super::BindGroupLayout, crate::DeviceError> {
-        let mut desc_count = gpu_descriptor::DescriptorTotalCount::default();
-        let mut types = Vec::new();
-        for entry in desc.entries {
-            let count = entry.count.map_or(1, |c| c.get());
-            if entry.binding as usize >= types.len() {
-                types.resize(
-                    entry.binding as usize + 1,
-                    (vk::DescriptorType::INPUT_ATTACHMENT, 0),
-                );
-            }
-            types[entry.binding as usize] = (
-                conv::map_binding_type(entry.ty),
-                entry.count.map_or(1, |c| c.get()),
-            );
-
-            match entry.ty {
-                wgt::BindingType::Buffer {
-                    ty,
-                    has_dynamic_offset,
-                    ..
-                } => match ty {
-                    wgt::BufferBindingType::Uniform => {
-                        if has_dynamic_offset {
-                            desc_count.uniform_buffer_dynamic += count;
-                        } else {
-                            desc_count.uniform_buffer += count;
-                        }
-                    }
-                    wgt::BufferBindingType::Storage { .. } => {
-                        if has_dynamic_offset {
-                            desc_count.storage_buffer_dynamic += count;
-                        } else {
-                            desc_count.storage_buffer += count;
-                        }
-                    }
-                },
-                wgt::BindingType::Sampler { .. } => {
-                    desc_count.sampler += count;
-                }
-                wgt::BindingType::Texture { .. } => {
-                    desc_count.sampled_image += count;
-                }
-                wgt::BindingType::StorageTexture { .. } => {
-                    desc_count.storage_image += count;
-                }
-                wgt::BindingType::AccelerationStructure => {
-                    desc_count.acceleration_structure += count;
-                }
-            }
-        }
-
-        //Note: not bothering with on stack array here as it's low frequency
-        let vk_bindings = desc
-            .entries
-            .iter()
-            .map(|entry| vk::DescriptorSetLayoutBinding {
-                binding: entry.binding,
-                descriptor_type: types[entry.binding as usize].0,
-                descriptor_count: types[entry.binding as usize].1,
-                stage_flags: conv::map_shader_stage(entry.visibility),
-                p_immutable_samplers: ptr::null(),
-                _marker: Default::default(),
-            })
-            .collect::<Vec<_>>();
-
-        let vk_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&vk_bindings);
-
-        let binding_arrays = desc
-            .entries
-            .iter()
-            .enumerate()
-            .filter_map(|(idx, entry)| entry.count.map(|count| (idx as u32, count)))
-            .collect();
-
-        let mut binding_flag_info;
-        let binding_flag_vec;
-
-        let partially_bound = desc
-            .flags
-            .contains(crate::BindGroupLayoutFlags::PARTIALLY_BOUND);
-
-        let vk_info = if partially_bound {
-            binding_flag_vec = desc
-                .entries
-                .iter()
-                .map(|entry| {
-                    let mut flags = vk::DescriptorBindingFlags::empty();
-
-                    if partially_bound && entry.count.is_some() {
-                        flags |= vk::DescriptorBindingFlags::PARTIALLY_BOUND;
-                    }
-
-                    flags
-                })
-                .collect::<Vec<_>>();
-
-            binding_flag_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::default()
-                .binding_flags(&binding_flag_vec);
-
-            vk_info.push_next(&mut binding_flag_info)
-        } else {
-            vk_info
-        };
-
-        let raw = unsafe {
-            self.shared
-                .raw
-                .create_descriptor_set_layout(&vk_info, None)
-                .map_err(super::map_host_device_oom_err)?
-        };
-
-        if let Some(label) = desc.label {
-            unsafe { self.shared.set_object_name(raw, label) };
-        }
-
-        self.counters.bind_group_layouts.add(1);
-
-        Ok(super::BindGroupLayout {
-            raw,
-            desc_count,
-            types: types.into_boxed_slice(),
-            binding_arrays,
-        })
+        self.create_bind_group_layout_impl(desc, vk::DescriptorSetLayoutCreateFlags::empty())
     }
     unsafe fn destroy_bind_group_layout(&self, bg_layout: super::BindGroupLayout) {
         unsafe {
@@ -1487,6 +2937,7 @@ impl crate::Device for super::Device {
         Ok(super::PipelineLayout {
             raw,
             binding_arrays,
+            push_constant_ranges: desc.push_constant_ranges.to_vec(),
         })
     }
     unsafe fn destroy_pipeline_layout(&self, pipeline_layout: super::PipelineLayout) {
@@ -1504,184 +2955,33 @@ impl crate::Device for super::Device {
         desc: &crate::BindGroupDescriptor<
             super::BindGroupLayout,
             super::Buffer,
-            super::Sampler,
-            super::TextureView,
-            super::AccelerationStructure,
-        >,
-    ) -> Result<super::BindGroup, crate::DeviceError> {
-        let mut vk_sets = unsafe {
-            self.desc_allocator.lock().allocate(
-                &*self.shared,
-                &desc.layout.raw,
-                gpu_descriptor::DescriptorSetLayoutCreateFlags::empty(),
-                &desc.layout.desc_count,
-                1,
-            )?
-        };
-
-        let set = vk_sets.pop().unwrap();
-        if let Some(label) = desc.label {
-            unsafe { self.shared.set_object_name(*set.raw(), label) };
-        }
-
-        /// Helper for splitting off and initializing a given number of elements on a pre-allocated
-        /// stack, based on items returned from an [`ExactSizeIterator`].  Typically created from a
-        /// [`MaybeUninit`] slice (see [`Vec::spare_capacity_mut()`]).
-        /// The updated [`ExtensionStack`] of remaining uninitialized elements is returned, safely
-        /// representing that the initialized and remaining elements are two independent mutable
-        /// borrows.
-        struct ExtendStack<'a, T> {
-            remainder: &'a mut [MaybeUninit<T>],
-        }
-
-        impl<'a, T> ExtendStack<'a, T> {
-            fn from_vec_capacity(vec: &'a mut Vec<T>) -> Self {
-                Self {
-                    remainder: vec.spare_capacity_mut(),
-                }
-            }
-
-            fn extend_one(self, value: T) -> (Self, &'a mut T) {
-                let (to_init, remainder) = self.remainder.split_first_mut().unwrap();
-                let init = to_init.write(value);
-                (Self { remainder }, init)
-            }
-
-            fn extend(
-                self,
-                iter: impl IntoIterator<Item = T> + ExactSizeIterator,
-            ) -> (Self, &'a mut [T]) {
-                let (to_init, remainder) = self.remainder.split_at_mut(iter.len());
-
-                for (value, to_init) in iter.into_iter().zip(to_init.iter_mut()) {
-                    to_init.write(value);
-                }
-
-                // we can't use the safe (yet unstable) MaybeUninit::write_slice() here because of having an iterator to write
-
-                let init = {
-                    // SAFETY: The loop above has initialized exactly as many items as to_init is
-                    // long, so it is safe to cast away the MaybeUninit<T> wrapper into T.
-
-                    // Additional safety docs from unstable slice_assume_init_mut
-                    // SAFETY: similar to safety notes for `slice_get_ref`, but we have a
-                    // mutable reference which is also guaranteed to be valid for writes.
-                    unsafe { std::mem::transmute::<&mut [MaybeUninit<T>], &mut [T]>(to_init) }
-                };
-                (Self { remainder }, init)
-            }
-        }
-
-        let mut writes = Vec::with_capacity(desc.entries.len());
-        let mut buffer_infos = Vec::with_capacity(desc.buffers.len());
-        let mut buffer_infos = ExtendStack::from_vec_capacity(&mut buffer_infos);
-        let mut image_infos = Vec::with_capacity(desc.samplers.len() + desc.textures.len());
-        let mut image_infos = ExtendStack::from_vec_capacity(&mut image_infos);
-        // TODO: This length could be reduced to just the number of top-level acceleration
-        // structure bindings, where multiple consecutive TLAS bindings that are set via
-        // one `WriteDescriptorSet` count towards one "info" struct, not the total number of
-        // acceleration structure bindings to write:
-        let mut acceleration_structure_infos =
-            Vec::with_capacity(desc.acceleration_structures.len());
-        let mut acceleration_structure_infos =
-            ExtendStack::from_vec_capacity(&mut acceleration_structure_infos);
-        let mut raw_acceleration_structures =
-            Vec::with_capacity(desc.acceleration_structures.len());
-        let mut raw_acceleration_structures =
-            ExtendStack::from_vec_capacity(&mut raw_acceleration_structures);
-        for entry in desc.entries {
-            let (ty, size) = desc.layout.types[entry.binding as usize];
-            if size == 0 {
-                continue; // empty slot
-            }
-            let mut write = vk::WriteDescriptorSet::default()
-                .dst_set(*set.raw())
-                .dst_binding(entry.binding)
-                .descriptor_type(ty);
-
-            write = match ty {
-                vk::DescriptorType::SAMPLER => {
-                    let start = entry.resource_index;
-                    let end = start + entry.count;
-                    let local_image_infos;
-                    (image_infos, local_image_infos) =
-                        image_infos.extend(desc.samplers[start as usize..end as usize].iter().map(
-                            |sampler| vk::DescriptorImageInfo::default().sampler(sampler.raw),
-                        ));
-                    write.image_info(local_image_infos)
-                }
-                vk::DescriptorType::SAMPLED_IMAGE | vk::DescriptorType::STORAGE_IMAGE => {
-                    let start = entry.resource_index;
-                    let end = start + entry.count;
-                    let local_image_infos;
-                    (image_infos, local_image_infos) =
-                        image_infos.extend(desc.textures[start as usize..end as usize].iter().map(
-                            |binding| {
-                                let layout = conv::derive_image_layout(
-                                    binding.usage,
-                                    binding.view.attachment.view_format,
-                                );
-                                vk::DescriptorImageInfo::default()
-                                    .image_view(binding.view.raw)
-                                    .image_layout(layout)
-                            },
-                        ));
-                    write.image_info(local_image_infos)
-                }
-                vk::DescriptorType::UNIFORM_BUFFER
-                | vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
-                | vk::DescriptorType::STORAGE_BUFFER
-                | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC => {
-                    let start = entry.resource_index;
-                    let end = start + entry.count;
-                    let local_buffer_infos;
-                    (buffer_infos, local_buffer_infos) =
-                        buffer_infos.extend(desc.buffers[start as usize..end as usize].iter().map(
-                            |binding| {
-                                vk::DescriptorBufferInfo::default()
-                                    .buffer(binding.buffer.raw)
-                                    .offset(binding.offset)
-                                    .range(
-                                        binding.size.map_or(vk::WHOLE_SIZE, wgt::BufferSize::get),
-                                    )
-                            },
-                        ));
-                    write.buffer_info(local_buffer_infos)
-                }
-                vk::DescriptorType::ACCELERATION_STRUCTURE_KHR => {
-                    let start = entry.resource_index;
-                    let end = start + entry.count;
-
-                    let local_raw_acceleration_structures;
-                    (
-                        raw_acceleration_structures,
-                        local_raw_acceleration_structures,
-                    ) = raw_acceleration_structures.extend(
-                        desc.acceleration_structures[start as usize..end as usize]
-                            .iter()
-                            .map(|acceleration_structure| acceleration_structure.raw),
-                    );
-
-                    let local_acceleration_structure_infos;
-                    (
-                        acceleration_structure_infos,
-                        local_acceleration_structure_infos,
-                    ) = acceleration_structure_infos.extend_one(
-                        vk::WriteDescriptorSetAccelerationStructureKHR::default()
-                            .acceleration_structures(local_raw_acceleration_structures),
-                    );
-
-                    write
-                        .descriptor_count(entry.count)
-                        .push_next(local_acceleration_structure_infos)
-                }
-                _ => unreachable!(),
-            };
+            super::Sampler,
+            super::TextureView,
+            super::AccelerationStructure,
+        >,
+    ) -> Result<super::BindGroup, crate::DeviceError> {
+        let mut vk_sets = unsafe {
+            self.desc_allocator.lock().allocate(
+                &*self.shared,
+                &desc.layout.raw,
+                if desc.layout.update_after_bind {
+                    gpu_descriptor::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND
+                } else {
+                    gpu_descriptor::DescriptorSetLayoutCreateFlags::empty()
+                },
+                &desc.layout.desc_count,
+                1,
+            )?
+        };
 
-            writes.push(write);
+        let set = vk_sets.pop().unwrap();
+        if let Some(label) = desc.label {
+            unsafe { self.shared.set_object_name(*set.raw(), label) };
         }
 
-        unsafe { self.shared.raw.update_descriptor_sets(&writes, &[]) };
+        with_descriptor_writes(desc, *set.raw(), 0, |writes| unsafe {
+            self.shared.raw.update_descriptor_sets(writes, &[])
+        });
 
         self.counters.bind_groups.add(1);
 
@@ -1747,6 +3047,7 @@ impl crate::Device for super::Device {
         };
 
         let raw = self.create_shader_module_impl(&spv)?;
+        let entry_points = parse_spirv_entry_points(&spv);
 
         if let Some(label) = desc.label {
             unsafe { self.shared.set_object_name(raw, label) };
@@ -1754,12 +3055,12 @@ impl crate::Device for super::Device {
 
         self.counters.shader_modules.add(1);
 
-        Ok(super::ShaderModule::Raw(raw))
+        Ok(super::ShaderModule::Raw { raw, entry_points })
     }
 
     unsafe fn destroy_shader_module(&self, module: super::ShaderModule) {
         match module {
-            super::ShaderModule::Raw(raw) => {
+            super::ShaderModule::Raw { raw, .. } => {
                 unsafe { self.shared.raw.destroy_shader_module(raw, None) };
             }
             super::ShaderModule::Intermediate { .. } => {}
@@ -1776,233 +3077,7 @@ impl crate::Device for super::Device {
             super::PipelineCache,
         >,
     ) -> Result<super::RenderPipeline, crate::PipelineError> {
-        let dynamic_states = [
-            vk::DynamicState::VIEWPORT,
-            vk::DynamicState::SCISSOR,
-            vk::DynamicState::BLEND_CONSTANTS,
-            vk::DynamicState::STENCIL_REFERENCE,
-        ];
-        let mut compatible_rp_key = super::RenderPassKey {
-            sample_count: desc.multisample.count,
-            multiview: desc.multiview,
-            ..Default::default()
-        };
-        let mut stages = ArrayVec::<_, { crate::MAX_CONCURRENT_SHADER_STAGES }>::new();
-        let mut vertex_buffers = Vec::with_capacity(desc.vertex_buffers.len());
-        let mut vertex_attributes = Vec::new();
-
-        for (i, vb) in desc.vertex_buffers.iter().enumerate() {
-            vertex_buffers.push(vk::VertexInputBindingDescription {
-                binding: i as u32,
-                stride: vb.array_stride as u32,
-                input_rate: match vb.step_mode {
-                    wgt::VertexStepMode::Vertex => vk::VertexInputRate::VERTEX,
-                    wgt::VertexStepMode::Instance => vk::VertexInputRate::INSTANCE,
-                },
-            });
-            for at in vb.attributes {
-                vertex_attributes.push(vk::VertexInputAttributeDescription {
-                    location: at.shader_location,
-                    binding: i as u32,
-                    format: conv::map_vertex_format(at.format),
-                    offset: at.offset as u32,
-                });
-            }
-        }
-
-        let vk_vertex_input = vk::PipelineVertexInputStateCreateInfo::default()
-            .vertex_binding_descriptions(&vertex_buffers)
-            .vertex_attribute_descriptions(&vertex_attributes);
-
-        let vk_input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
-            .topology(conv::map_topology(desc.primitive.topology))
-            .primitive_restart_enable(desc.primitive.strip_index_format.is_some());
-
-        let compiled_vs = self.compile_stage(
-            &desc.vertex_stage,
-            naga::ShaderStage::Vertex,
-            &desc.layout.binding_arrays,
-        )?;
-        stages.push(compiled_vs.create_info);
-        let compiled_fs = match desc.fragment_stage {
-            Some(ref stage) => {
-                let compiled = self.compile_stage(
-                    stage,
-                    naga::ShaderStage::Fragment,
-                    &desc.layout.binding_arrays,
-                )?;
-                stages.push(compiled.create_info);
-                Some(compiled)
-            }
-            None => None,
-        };
-
-        let mut vk_rasterization = vk::PipelineRasterizationStateCreateInfo::default()
-            .polygon_mode(conv::map_polygon_mode(desc.primitive.polygon_mode))
-            .front_face(conv::map_front_face(desc.primitive.front_face))
-            .line_width(1.0)
-            .depth_clamp_enable(desc.primitive.unclipped_depth);
-        if let Some(face) = desc.primitive.cull_mode {
-            vk_rasterization = vk_rasterization.cull_mode(conv::map_cull_face(face))
-        }
-        let mut vk_rasterization_conservative_state =
-            vk::PipelineRasterizationConservativeStateCreateInfoEXT::default()
-                .conservative_rasterization_mode(
-                    vk::ConservativeRasterizationModeEXT::OVERESTIMATE,
-                );
-        if desc.primitive.conservative {
-            vk_rasterization = vk_rasterization.push_next(&mut vk_rasterization_conservative_state);
-        }
-
-        let mut vk_depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default();
-        if let Some(ref ds) = desc.depth_stencil {
-            let vk_format = self.shared.private_caps.map_texture_format(ds.format);
-            let vk_layout = if ds.is_read_only(desc.primitive.cull_mode) {
-                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
-            } else {
-                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
-            };
-            compatible_rp_key.depth_stencil = Some(super::DepthStencilAttachmentKey {
-                base: super::AttachmentKey::compatible(vk_format, vk_layout),
-                stencil_ops: crate::AttachmentOps::all(),
-            });
-
-            if ds.is_depth_enabled() {
-                vk_depth_stencil = vk_depth_stencil
-                    .depth_test_enable(true)
-                    .depth_write_enable(ds.depth_write_enabled)
-                    .depth_compare_op(conv::map_comparison(ds.depth_compare));
-            }
-            if ds.stencil.is_enabled() {
-                let s = &ds.stencil;
-                let front = conv::map_stencil_face(&s.front, s.read_mask, s.write_mask);
-                let back = conv::map_stencil_face(&s.back, s.read_mask, s.write_mask);
-                vk_depth_stencil = vk_depth_stencil
-                    .stencil_test_enable(true)
-                    .front(front)
-                    .back(back);
-            }
-
-            if ds.bias.is_enabled() {
-                vk_rasterization = vk_rasterization
-                    .depth_bias_enable(true)
-                    .depth_bias_constant_factor(ds.bias.constant as f32)
-                    .depth_bias_clamp(ds.bias.clamp)
-                    .depth_bias_slope_factor(ds.bias.slope_scale);
-            }
-        }
-
-        let vk_viewport = vk::PipelineViewportStateCreateInfo::default()
-            .flags(vk::PipelineViewportStateCreateFlags::empty())
-            .scissor_count(1)
-            .viewport_count(1);
-
-        let vk_sample_mask = [
-            desc.multisample.mask as u32,
-            (desc.multisample.mask >> 32) as u32,
-        ];
-        let vk_multisample = vk::PipelineMultisampleStateCreateInfo::default()
-            .rasterization_samples(vk::SampleCountFlags::from_raw(desc.multisample.count))
-            .alpha_to_coverage_enable(desc.multisample.alpha_to_coverage_enabled)
-            .sample_mask(&vk_sample_mask);
-
-        let mut vk_attachments = Vec::with_capacity(desc.color_targets.len());
-        for cat in desc.color_targets {
-            let (key, attarchment) = if let Some(cat) = cat.as_ref() {
-                let mut vk_attachment = vk::PipelineColorBlendAttachmentState::default()
-                    .color_write_mask(vk::ColorComponentFlags::from_raw(cat.write_mask.bits()));
-                if let Some(ref blend) = cat.blend {
-                    let (color_op, color_src, color_dst) = conv::map_blend_component(&blend.color);
-                    let (alpha_op, alpha_src, alpha_dst) = conv::map_blend_component(&blend.alpha);
-                    vk_attachment = vk_attachment
-                        .blend_enable(true)
-                        .color_blend_op(color_op)
-                        .src_color_blend_factor(color_src)
-                        .dst_color_blend_factor(color_dst)
-                        .alpha_blend_op(alpha_op)
-                        .src_alpha_blend_factor(alpha_src)
-                        .dst_alpha_blend_factor(alpha_dst);
-                }
-
-                let vk_format = self.shared.private_caps.map_texture_format(cat.format);
-                (
-                    Some(super::ColorAttachmentKey {
-                        base: super::AttachmentKey::compatible(
-                            vk_format,
-                            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-                        ),
-                        resolve: None,
-                    }),
-                    vk_attachment,
-                )
-            } else {
-                (None, vk::PipelineColorBlendAttachmentState::default())
-            };
-
-            compatible_rp_key.colors.push(key);
-            vk_attachments.push(attarchment);
-        }
-
-        let vk_color_blend =
-            vk::PipelineColorBlendStateCreateInfo::default().attachments(&vk_attachments);
-
-        let vk_dynamic_state =
-            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
-
-        let raw_pass = self
-            .shared
-            .make_render_pass(compatible_rp_key)
-            .map_err(crate::DeviceError::from)?;
-
-        let vk_infos = [{
-            vk::GraphicsPipelineCreateInfo::default()
-                .layout(desc.layout.raw)
-                .stages(&stages)
-                .vertex_input_state(&vk_vertex_input)
-                .input_assembly_state(&vk_input_assembly)
-                .rasterization_state(&vk_rasterization)
-                .viewport_state(&vk_viewport)
-                .multisample_state(&vk_multisample)
-                .depth_stencil_state(&vk_depth_stencil)
-                .color_blend_state(&vk_color_blend)
-                .dynamic_state(&vk_dynamic_state)
-                .render_pass(raw_pass)
-        }];
-
-        let pipeline_cache = desc
-            .cache
-            .map(|it| it.raw)
-            .unwrap_or(vk::PipelineCache::null());
-
-        let mut raw_vec = {
-            profiling::scope!("vkCreateGraphicsPipelines");
-            unsafe {
-                self.shared
-                    .raw
-                    .create_graphics_pipelines(pipeline_cache, &vk_infos, None)
-                    .map_err(|(_, e)| super::map_pipeline_err(e))
-            }?
-        };
-
-        let raw = raw_vec.pop().unwrap();
-        if let Some(label) = desc.label {
-            unsafe { self.shared.set_object_name(raw, label) };
-        }
-
-        if let Some(raw_module) = compiled_vs.temp_raw_module {
-            unsafe { self.shared.raw.destroy_shader_module(raw_module, None) };
-        }
-        if let Some(CompiledStage {
-            temp_raw_module: Some(raw_module),
-            ..
-        }) = compiled_fs
-        {
-            unsafe { self.shared.raw.destroy_shader_module(raw_module, None) };
-        }
-
-        self.counters.render_pipelines.add(1);
-
-        Ok(super::RenderPipeline { raw })
+        unsafe { self.create_render_pipeline_with_options(desc, &Default::default()) }
     }
 
     unsafe fn destroy_render_pipeline(&self, pipeline: super::RenderPipeline) {
@@ -2025,8 +3100,15 @@ impl crate::Device for super::Device {
             &desc.layout.binding_arrays,
         )?;
 
+        // Allows the pipeline to be used with `CommandEncoder::dispatch_base`.
+        let flags = if self.shared.private_caps.dispatch_base {
+            vk::PipelineCreateFlags::DISPATCH_BASE
+        } else {
+            vk::PipelineCreateFlags::empty()
+        };
         let vk_infos = [{
             vk::ComputePipelineCreateInfo::default()
+                .flags(flags)
                 .layout(desc.layout.raw)
                 .stage(compiled.create_info)
         }];
@@ -2090,18 +3172,23 @@ impl crate::Device for super::Device {
         &self,
         desc: &wgt::QuerySetDescriptor<crate::Label>,
     ) -> Result<super::QuerySet, crate::DeviceError> {
-        let (vk_type, pipeline_statistics) = match desc.ty {
+        let (vk_type, pipeline_statistics, result_kind) = match desc.ty {
             wgt::QueryType::Occlusion => (
                 vk::QueryType::OCCLUSION,
                 vk::QueryPipelineStatisticFlags::empty(),
+                super::QueryResultKind::Occlusion,
             ),
             wgt::QueryType::PipelineStatistics(statistics) => (
                 vk::QueryType::PIPELINE_STATISTICS,
                 conv::map_pipeline_statistics(statistics),
+                super::QueryResultKind::PipelineStatistics {
+                    count: statistics.bits().count_ones(),
+                },
             ),
             wgt::QueryType::Timestamp => (
                 vk::QueryType::TIMESTAMP,
                 vk::QueryPipelineStatisticFlags::empty(),
+                super::QueryResultKind::Timestamp,
             ),
         };
 
@@ -2118,7 +3205,11 @@ impl crate::Device for super::Device {
 
         self.counters.query_sets.add(1);
 
-        Ok(super::QuerySet { raw })
+        Ok(super::QuerySet {
+            raw,
+            count: desc.count,
+            result_kind,
+        })
     }
 
     unsafe fn destroy_query_set(&self, set: super::QuerySet) {
@@ -2565,3 +3656,159 @@ impl From<gpu_descriptor::AllocationError> for crate::DeviceError {
 fn handle_unexpected(err: vk::Result) -> ! {
     panic!("Unexpected Vulkan error: `{err}`")
 }
+
+#[test]
+fn check_strip_row_padding() {
+    // A 100x100 RGBA8 texture, with rows padded to 512 bytes.
+    const PACKED_ROW_SIZE: usize = 100 * 4;
+    const PADDED_ROW_SIZE: usize = 512;
+    let expected = (0..100 * PACKED_ROW_SIZE)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    let mut padded = vec![0xFF; 100 * PADDED_ROW_SIZE];
+    for (dst, src) in padded
+        .chunks_mut(PADDED_ROW_SIZE)
+        .zip(expected.chunks(PACKED_ROW_SIZE))
+    {
+        dst[..PACKED_ROW_SIZE].copy_from_slice(src);
+    }
+
+    assert_eq!(
+        strip_row_padding(&padded, PACKED_ROW_SIZE, PADDED_ROW_SIZE),
+        expected
+    );
+}
+
+#[test]
+fn check_add_row_padding() {
+    // 3 rows of 6 bytes, padded to 8 bytes.
+    let packed = (0..18).collect::<Vec<u8>>();
+    let mut padded = vec![0xFF; 24];
+    add_row_padding(&mut padded, &packed, 6, 8);
+    assert_eq!(&padded[8..16], &[6, 7, 8, 9, 10, 11, 0xFF, 0xFF]);
+    assert_eq!(strip_row_padding(&padded, 6, 8), packed);
+}
+
+#[test]
+fn check_timestamp_deltas() {
+    // 36 valid bits, as on some Adreno GPUs.
+    let max = (1 << 36) - 1;
+    assert_eq!(timestamp_mask(36), max);
+    assert_eq!(timestamp_mask(0), u64::MAX);
+    assert_eq!(timestamp_mask(64), u64::MAX);
+    assert_eq!(timestamp_delta(100, 250, 36), 150);
+    // The counter wrapped around, and the invalid high bits are garbage.
+    assert_eq!(timestamp_delta(max - 9, 10, 36), 20);
+    assert_eq!(timestamp_delta(0xFFFF_0000_0000_0000 | 5, 8, 36), 3);
+    assert_eq!(timestamp_delta(u64::MAX - 1, 1, 64), 3);
+
+    assert_eq!(
+        ticks_to_duration(1000, 52.08),
+        std::time::Duration::from_nanos(52080)
+    );
+    // No overflow near `u64::MAX` ticks.
+    assert_eq!(
+        ticks_to_duration(u64::MAX, 2.0),
+        std::time::Duration::from_nanos(u64::MAX)
+    );
+}
+
+#[test]
+fn check_bindless_layout_bindings() {
+    let entries = [wgt::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgt::ShaderStages::FRAGMENT,
+        ty: wgt::BindingType::Texture {
+            sample_type: wgt::TextureSampleType::Float { filterable: true },
+            view_dimension: wgt::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: std::num::NonZeroU32::new(1 << 20),
+    }];
+    let desc = crate::BindGroupLayoutDescriptor {
+        label: None,
+        flags: crate::BindGroupLayoutFlags::PARTIALLY_BOUND,
+        entries: &entries,
+    };
+
+    let (vk_bindings, binding_flags) = map_bind_group_layout_bindings(&desc);
+    assert_eq!(vk_bindings.len(), 1);
+    assert_eq!(vk_bindings[0].descriptor_count, 1 << 20);
+    assert_eq!(
+        vk_bindings[0].descriptor_type,
+        vk::DescriptorType::SAMPLED_IMAGE
+    );
+    assert_eq!(
+        binding_flags,
+        Some(vec![vk::DescriptorBindingFlags::PARTIALLY_BOUND])
+    );
+}
+
+#[test]
+fn check_spirv_entry_points() {
+    fn entry_point(execution_model: u32, id: u32, name: &str) -> Vec<u32> {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize(name.len() / 4 * 4 + 4, 0);
+        let mut words = vec![0, execution_model, id];
+        words.extend(
+            bytes
+                .chunks(4)
+                .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])),
+        );
+        words[0] = ((words.len() as u32) << 16) | 15;
+        words
+    }
+
+    // Header, followed by two entry points and the start of a function.
+    let mut spv = vec![0x0723_0203, 0x0001_0000, 0, 16, 0];
+    spv.extend(entry_point(0, 1, "vs_main"));
+    spv.extend(entry_point(4, 2, "fs_main"));
+    spv.extend([(5 << 16) | 54, 0, 1, 0, 3]);
+    spv.extend(entry_point(5, 3, "ignored"));
+
+    assert_eq!(
+        parse_spirv_entry_points(&spv),
+        vec![
+            (naga::ShaderStage::Vertex, "vs_main".to_string()),
+            (naga::ShaderStage::Fragment, "fs_main".to_string()),
+        ]
+    );
+    assert!(parse_spirv_entry_points(&spv[..3]).is_empty());
+}
+
+#[test]
+fn check_update_after_bind_layout_bindings() {
+    let texture = wgt::BindingType::Texture {
+        sample_type: wgt::TextureSampleType::Float { filterable: true },
+        view_dimension: wgt::TextureViewDimension::D2,
+        multisampled: false,
+    };
+    let uniform = wgt::BindingType::Buffer {
+        ty: wgt::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+    };
+    let entries = [(texture, Some(256)), (texture, None), (uniform, Some(4))].map(|(ty, count)| {
+        wgt::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgt::ShaderStages::FRAGMENT,
+            ty,
+            count: count.and_then(std::num::NonZeroU32::new),
+        }
+    });
+    let desc = crate::BindGroupLayoutDescriptor {
+        label: None,
+        flags: crate::BindGroupLayoutFlags::UPDATE_AFTER_BIND,
+        entries: &entries,
+    };
+
+    let (_, binding_flags) = map_bind_group_layout_bindings(&desc);
+    assert_eq!(
+        binding_flags,
+        Some(vec![
+            vk::DescriptorBindingFlags::UPDATE_AFTER_BIND,
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+        ])
+    );
+}
@@ -1091,6 +1091,9 @@ impl crate::Surface for super::Surface {
                     height: swapchain.config.extent.height,
                     depth: 1,
                 },
+                sample_count: 1,
+                mip_level_count: 1,
+                array_layer_count: swapchain.config.extent.depth_or_array_layers,
                 view_formats: swapchain.view_formats.clone(),
             },
             surface_semaphores: swapchain_semaphores_arc,
@@ -45,7 +45,19 @@ use parking_lot::{Mutex, RwLock};
 use wgt::InternalCounter;
 
 const MILLIS_TO_NANOS: u64 = 1_000_000;
-const MAX_TOTAL_ATTACHMENTS: usize = crate::MAX_COLOR_ATTACHMENTS * 2 + 1;
+/// Each color attachment and its resolve, the depth-stencil attachment and
+/// its resolve, then the shading rate attachment.
+const MAX_TOTAL_ATTACHMENTS: usize = crate::MAX_COLOR_ATTACHMENTS * 2 + 3;
+/// The spec guarantees at least this many viewports with `multiViewport`.
+const MAX_VIEWPORTS: usize = 16;
+/// A rate of one fragment per pixel, which the pipelines' rate doesn't change.
+const DEFAULT_FRAGMENT_SHADING_RATE: (vk::Extent2D, [vk::FragmentShadingRateCombinerOpKHR; 2]) = (
+    vk::Extent2D {
+        width: 1,
+        height: 1,
+    },
+    [vk::FragmentShadingRateCombinerOpKHR::KEEP; 2],
+);
 
 #[derive(Clone, Debug)]
 pub struct Api;
@@ -420,6 +432,19 @@ struct DeviceExtensionFunctions {
     debug_utils: Option<ext::debug_utils::Device>,
     draw_indirect_count: Option<khr::draw_indirect_count::Device>,
     timeline_semaphore: Option<ExtensionFn<khr::timeline_semaphore::Device>>,
+    maintenance3: Option<ExtensionFn<khr::maintenance3::Device>>,
+    conditional_rendering: Option<ext::conditional_rendering::Device>,
+    push_descriptor: Option<khr::push_descriptor::Device>,
+    transform_feedback: Option<ext::transform_feedback::Device>,
+    extended_dynamic_state: Option<ExtensionFn<ext::extended_dynamic_state::Device>>,
+    extended_dynamic_state3: Option<ext::extended_dynamic_state3::Device>,
+    mesh_shader: Option<ext::mesh_shader::Device>,
+    maintenance5: Option<khr::maintenance5::Device>,
+    synchronization2: Option<ExtensionFn<khr::synchronization2::Device>>,
+    sample_locations: Option<ext::sample_locations::Device>,
+    fragment_shading_rate: Option<khr::fragment_shading_rate::Device>,
+    color_write_enable: Option<ext::color_write_enable::Device>,
+    vertex_input_dynamic_state: Option<ext::vertex_input_dynamic_state::Device>,
     ray_tracing: Option<RayTracingDeviceExtensionFunctions>,
 }
 
@@ -451,6 +476,89 @@ struct PrivateCapabilities {
     robust_image_access2: bool,
     zero_initialize_workgroup_memory: bool,
     image_format_list: bool,
+    /// `maxPerSetDescriptors` from `VK_KHR_maintenance3`, if it is available.
+    max_per_set_descriptors: Option<u32>,
+    /// Limits of `VK_EXT_blend_operation_advanced`, if it is enabled.
+    blend_operation_advanced: Option<AdvancedBlendCapabilities>,
+    /// `maxViewports` (capped to `MAX_VIEWPORTS`) if `multiViewport` is supported, otherwise 1.
+    max_viewports: u32,
+    /// `vkCmdBindVertexBuffers2`, promoted to Vulkan 1.3, can bind vertex
+    /// buffers with an explicit size.
+    bind_vertex_buffers2: bool,
+    /// `lineWidthRange` if `wideLines` is supported.
+    line_width_range: Option<[f32; 2]>,
+    depth_bias_clamp: bool,
+    /// Update-after-bind of sampled images, storage images and storage
+    /// buffers, from `VK_EXT_descriptor_indexing`.
+    update_after_bind: bool,
+    /// `VK_ATTACHMENT_STORE_OP_NONE`, from Vulkan 1.3 or
+    /// `VK_EXT_load_store_op_none`.
+    store_op_none: bool,
+    /// Viewport depth ranges outside `0.0..1.0`, from
+    /// `VK_EXT_depth_range_unrestricted`.
+    depth_range_unrestricted: bool,
+    /// Dynamic depth clamp enable, from the core `depthClamp` feature and
+    /// `VK_EXT_extended_dynamic_state3`.
+    dynamic_depth_clamp: bool,
+    /// The `depthBounds` feature, used by all pipelines with a depth-stencil
+    /// attachment as a dynamic state.
+    depth_bounds: bool,
+    /// The `meshShader` feature of `VK_EXT_mesh_shader`.
+    mesh_shader: bool,
+    /// `vkCmdBindIndexBuffer2KHR` from `VK_KHR_maintenance5`, which can bind
+    /// index buffers with an explicit size.
+    bind_index_buffer2: bool,
+    /// Sample counts supporting dynamic sample locations, from
+    /// `VK_EXT_sample_locations`.
+    sample_location_sample_counts: vk::SampleCountFlags,
+    /// Depth-stencil resolve modes of render passes, from Vulkan 1.2.
+    depth_stencil_resolve: Option<DepthStencilResolveCapabilities>,
+    /// `vkCmdDispatchBase`, from Vulkan 1.1.
+    dispatch_base: bool,
+    /// Per-barrier stage masks with `vkCmdPipelineBarrier2`, from
+    /// `VK_KHR_synchronization2`/1.3.
+    synchronization2: bool,
+    /// Dynamic primitive topology, cull mode and front face, from
+    /// `VK_EXT_extended_dynamic_state`/1.3.
+    extended_dynamic_state: bool,
+    /// Dynamic fragment shading rates, from `VK_KHR_fragment_shading_rate`.
+    fragment_shading_rate: Option<FragmentShadingRateCapabilities>,
+    /// Dynamic enables of color attachment writes, from
+    /// `VK_EXT_color_write_enable`.
+    color_write_enable: bool,
+    /// Dynamic vertex buffer layouts, from
+    /// `VK_EXT_vertex_input_dynamic_state`.
+    vertex_input_dynamic_state: bool,
+}
+
+/// Restrictions on advanced blend operations, from
+/// `VkPhysicalDeviceBlendOperationAdvancedPropertiesEXT`.
+#[derive(Clone, Copy, Debug)]
+struct AdvancedBlendCapabilities {
+    max_color_attachments: u32,
+    independent_blend: bool,
+    non_premultiplied_src_color: bool,
+    non_premultiplied_dst_color: bool,
+    correlated_overlap: bool,
+}
+
+/// Supported resolve modes of depth-stencil attachments, from
+/// `VkPhysicalDeviceDepthStencilResolveProperties`.
+#[derive(Clone, Copy, Debug)]
+struct DepthStencilResolveCapabilities {
+    depth_modes: vk::ResolveModeFlags,
+    stencil_modes: vk::ResolveModeFlags,
+    /// If false, formats with both aspects must resolve them with the same mode.
+    independent_resolve: bool,
+}
+
+/// Support of `VK_KHR_fragment_shading_rate`, for which the rate of the
+/// pipeline, set dynamically, is always supported.
+#[derive(Clone, Copy, Debug)]
+struct FragmentShadingRateCapabilities {
+    /// Minimum and maximum texel sizes of shading rate attachments, if
+    /// `attachmentFragmentShadingRate` is supported.
+    attachment_texel_sizes: Option<[[u32; 2]; 2]>,
 }
 
 bitflags::bitflags!(
@@ -490,7 +598,11 @@ bitflags::bitflags!(
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 struct AttachmentKey {
     format: vk::Format,
+    /// Layout of the attachment within the render pass.
     layout: vk::ImageLayout,
+    /// Layouts before and after the render pass, usually `layout`.
+    initial_layout: vk::ImageLayout,
+    final_layout: vk::ImageLayout,
     ops: crate::AttachmentOps,
 }
 
@@ -500,6 +612,8 @@ impl AttachmentKey {
         Self {
             format,
             layout,
+            initial_layout: layout,
+            final_layout: layout,
             ops: crate::AttachmentOps::all(),
         }
     }
@@ -515,12 +629,64 @@ struct ColorAttachmentKey {
 struct DepthStencilAttachmentKey {
     base: AttachmentKey,
     stencil_ops: crate::AttachmentOps,
+    resolve: Option<DepthStencilResolveKey>,
+}
+
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct ShadingRateAttachmentKey {
+    format: vk::Format,
+    /// Size of the framebuffer area covered by each texel.
+    texel_size: [u32; 2],
+}
+
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct DepthStencilResolveKey {
+    base: AttachmentKey,
+    depth_mode: vk::ResolveModeFlags,
+    stencil_mode: vk::ResolveModeFlags,
 }
 
 #[derive(Clone, Eq, Default, Hash, PartialEq)]
 struct RenderPassKey {
     colors: ArrayVec<Option<ColorAttachmentKey>, { crate::MAX_COLOR_ATTACHMENTS }>,
     depth_stencil: Option<DepthStencilAttachmentKey>,
+    shading_rate: Option<ShadingRateAttachmentKey>,
+    sample_count: u32,
+    multiview: Option<NonZeroU32>,
+}
+
+impl RenderPassKey {
+    /// Returns the parts of this key that decide render pass compatibility.
+    fn signature(&self) -> RenderPassSignature {
+        let mut colors = self
+            .colors
+            .iter()
+            .map(|color| color.as_ref().map(|color| color.base.format))
+            .collect::<ArrayVec<_, { crate::MAX_COLOR_ATTACHMENTS }>>();
+        // Trailing unused attachments don't affect compatibility.
+        while let Some(None) = colors.last() {
+            colors.pop();
+        }
+        RenderPassSignature {
+            colors,
+            depth_stencil: self.depth_stencil.as_ref().map(|ds| ds.base.format),
+            shading_rate_texel_size: self.shading_rate.as_ref().map(|sr| sr.texel_size),
+            sample_count: self.sample_count,
+            multiview: self.multiview,
+        }
+    }
+}
+
+/// Attachment formats, shading rate attachment, sample count and view count
+/// of a render pass.
+///
+/// Pipelines can only be used in render passes with a matching signature.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct RenderPassSignature {
+    colors: ArrayVec<Option<vk::Format>, { crate::MAX_COLOR_ATTACHMENTS }>,
+    depth_stencil: Option<vk::Format>,
+    /// Texel size of the shading rate attachment, if any.
+    shading_rate_texel_size: Option<[u32; 2]>,
     sample_count: u32,
     multiview: Option<NonZeroU32>,
 }
@@ -533,6 +699,8 @@ struct FramebufferAttachment {
     view_usage: crate::TextureUses,
     view_format: wgt::TextureFormat,
     raw_view_formats: Vec<vk::Format>,
+    /// Number of array layers of the view.
+    layers: u32,
 }
 
 #[derive(Clone, Eq, Hash, PartialEq)]
@@ -540,6 +708,8 @@ struct FramebufferKey {
     attachments: ArrayVec<FramebufferAttachment, { MAX_TOTAL_ATTACHMENTS }>,
     extent: wgt::Extent3d,
     sample_count: u32,
+    /// Texel size of the shading rate attachment, which is the last one.
+    shading_rate_texel_size: Option<[u32; 2]>,
 }
 
 struct DeviceShared {
@@ -555,6 +725,9 @@ struct DeviceShared {
     vendor_id: u32,
     pipeline_cache_validation_key: [u8; 16],
     timestamp_period: f32,
+    /// Number of meaningful low bits of timestamps written on the queue.
+    timestamp_valid_bits: u32,
+    limits: vk::PhysicalDeviceLimits,
     private_caps: PrivateCapabilities,
     workarounds: Workarounds,
     features: wgt::Features,
@@ -662,6 +835,8 @@ pub struct Queue {
 pub struct Buffer {
     raw: vk::Buffer,
     block: Option<Mutex<gpu_alloc::MemoryBlock<vk::DeviceMemory>>>,
+    /// The size the buffer was created with, unknown for external buffers.
+    size: Option<wgt::BufferAddress>,
 }
 
 impl crate::DynBuffer for Buffer {}
@@ -684,6 +859,9 @@ pub struct Texture {
     format: wgt::TextureFormat,
     raw_flags: vk::ImageCreateFlags,
     copy_size: crate::CopyExtent,
+    sample_count: u32,
+    mip_level_count: u32,
+    array_layer_count: u32,
     view_formats: Vec<wgt::TextureFormat>,
 }
 
@@ -696,12 +874,100 @@ impl Texture {
     pub unsafe fn raw_handle(&self) -> vk::Image {
         self.raw
     }
+
+    /// Returns the smallest `bytes_per_row` for copying rows of `width`
+    /// texels of `aspect` between `self` and a buffer, padded to
+    /// `row_pitch_alignment`.
+    ///
+    /// Pass [`crate::Alignments::buffer_copy_pitch`] as the alignment to
+    /// size staging buffers for the fastest copies. The result stays a
+    /// multiple of the block size, as Vulkan expresses the row length in
+    /// texels.
+    pub fn padded_bytes_per_row(
+        &self,
+        width: u32,
+        aspect: wgt::TextureAspect,
+        row_pitch_alignment: u32,
+    ) -> u32 {
+        let (block_width, _) = self.format.block_dimensions();
+        let Some(block_size) = self.format.block_copy_size(Some(aspect)) else {
+            hal_usage_error(format!(
+                "aspect {aspect:?} of {:?} can't be copied to a buffer",
+                self.format
+            ));
+        };
+        let packed_bytes_per_row = width.div_ceil(block_width) * block_size;
+        wgt::math::align_to(
+            wgt::math::align_to(packed_bytes_per_row, row_pitch_alignment),
+            block_size,
+        )
+    }
+
+    /// Returns the ranges of each subresource of the texture, covering all
+    /// of its aspects, one mip level after another and one array layer
+    /// after another within a level.
+    ///
+    /// The layers of 3D textures are their depth slices, which can't be
+    /// transitioned separately, so they have a single subresource per level.
+    pub fn subresources(&self) -> impl Iterator<Item = wgt::ImageSubresourceRange> {
+        let array_layer_count = self.array_layer_count;
+        (0..self.mip_level_count).flat_map(move |mip_level| {
+            (0..array_layer_count).map(move |array_layer| wgt::ImageSubresourceRange {
+                aspect: wgt::TextureAspect::All,
+                base_mip_level: mip_level,
+                mip_level_count: Some(1),
+                base_array_layer: array_layer,
+                array_layer_count: Some(1),
+            })
+        })
+    }
+
+    /// Returns `true` if `range` covers every aspect, mip level and array
+    /// layer of the texture.
+    fn covers_all_subresources(&self, range: &wgt::ImageSubresourceRange) -> bool {
+        range.aspect == wgt::TextureAspect::All
+            && range.base_mip_level == 0
+            && range.base_array_layer == 0
+            && range
+                .mip_level_count
+                .map_or(true, |count| count >= self.mip_level_count)
+            && range
+                .array_layer_count
+                .map_or(true, |count| count >= self.array_layer_count)
+    }
+
+    /// Returns a barrier transitioning all layers of the mip levels in
+    /// `mip_levels` from `usage.start` to `usage.end`, e.g. after generating
+    /// them.
+    ///
+    /// The range is clamped to the mip levels of the texture.
+    pub fn mip_levels_barrier(
+        &self,
+        mip_levels: std::ops::Range<u32>,
+        usage: std::ops::Range<crate::TextureUses>,
+    ) -> crate::TextureBarrier<'_, Self> {
+        let end = mip_levels.end.min(self.mip_level_count);
+        let start = mip_levels.start.min(end);
+        crate::TextureBarrier {
+            texture: self,
+            range: wgt::ImageSubresourceRange {
+                aspect: wgt::TextureAspect::All,
+                base_mip_level: start,
+                mip_level_count: Some(end - start),
+                base_array_layer: 0,
+                array_layer_count: None,
+            },
+            usage,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct TextureView {
     raw: vk::ImageView,
     layers: NonZeroU32,
+    /// Width and height of the base mip level of the view.
+    size: [u32; 2],
     attachment: FramebufferAttachment,
 }
 
@@ -730,6 +996,8 @@ pub struct BindGroupLayout {
     types: Box<[(vk::DescriptorType, u32)]>,
     /// Map of binding index to size,
     binding_arrays: Vec<(u32, NonZeroU32)>,
+    /// If true, bind groups must be allocated from update-after-bind pools.
+    update_after_bind: bool,
 }
 
 impl crate::DynBindGroupLayout for BindGroupLayout {}
@@ -738,6 +1006,8 @@ impl crate::DynBindGroupLayout for BindGroupLayout {}
 pub struct PipelineLayout {
     raw: vk::PipelineLayout,
     binding_arrays: naga::back::spv::BindingMap,
+    /// The push constant ranges the layout was created with.
+    push_constant_ranges: Vec<wgt::PushConstantRange>,
 }
 
 impl crate::DynPipelineLayout for PipelineLayout {}
@@ -753,8 +1023,15 @@ impl crate::DynBindGroup for BindGroup {}
 #[derive(Default)]
 struct Temp {
     marker: Vec<u8>,
+    /// Barriers of the current `transition_buffers` call. The vector is
+    /// cleared and refilled on every call, so its allocation is reused.
     buffer_barriers: Vec<vk::BufferMemoryBarrier<'static>>,
+    /// Barriers of the current `transition_textures` call, reused the same way.
     image_barriers: Vec<vk::ImageMemoryBarrier<'static>>,
+    /// Barriers of `transition_buffers` with `VK_KHR_synchronization2`.
+    buffer_barriers2: Vec<vk::BufferMemoryBarrier2<'static>>,
+    /// Barriers of `transition_textures` with `VK_KHR_synchronization2`.
+    image_barriers2: Vec<vk::ImageMemoryBarrier2<'static>>,
 }
 
 impl Temp {
@@ -762,6 +1039,8 @@ impl Temp {
         self.marker.clear();
         self.buffer_barriers.clear();
         self.image_barriers.clear();
+        self.buffer_barriers2.clear();
+        self.image_barriers2.clear();
     }
 
     fn make_c_str(&mut self, name: &str) -> &CStr {
@@ -772,6 +1051,25 @@ impl Temp {
     }
 }
 
+/// Consecutive bind groups of one pipeline layout, bound together by
+/// `CommandEncoder::flush_bind_groups`.
+#[derive(Default)]
+struct PendingBindGroups {
+    bind_point: vk::PipelineBindPoint,
+    layout: vk::PipelineLayout,
+    first_set: u32,
+    sets: ArrayVec<vk::DescriptorSet, { crate::MAX_BIND_GROUPS }>,
+    /// Dynamic offsets of all of `sets`, in order.
+    dynamic_offsets: Vec<wgt::DynamicOffset>,
+}
+
+impl PendingBindGroups {
+    fn clear(&mut self) {
+        self.sets.clear();
+        self.dynamic_offsets.clear();
+    }
+}
+
 pub struct CommandEncoder {
     raw: vk::CommandPool,
     device: Arc<DeviceShared>,
@@ -787,19 +1085,89 @@ pub struct CommandEncoder {
     /// What kind of pass we are currently within: compute or render.
     bind_point: vk::PipelineBindPoint,
 
+    /// State of the current recording, set aside along with the command
+    /// buffer by [`CommandEncoder::suspend_encoding`].
+    encoding: EncodingState,
+
     /// Allocation recycling pool for this encoder.
     temp: Temp,
 
     /// A pool of available command buffers.
     ///
-    /// These are all in the Vulkan "initial" state.
+    /// These are all in the Vulkan "initial" state, except between
+    /// `recycle` and `reset_pool`.
     free: Vec<vk::CommandBuffer>,
 
     /// A pool of discarded command buffers.
     ///
-    /// These could be in any Vulkan state except "pending".
+    /// These could be in any Vulkan state except "pending". Buffers
+    /// discarded while recording are never ended, and the pool is created
+    /// without `RESET_COMMAND_BUFFER`, so only resetting the whole pool
+    /// returns them to the "initial" state. They must not be reset or
+    /// begun individually.
     discarded: Vec<vk::CommandBuffer>,
 
+    /// Like `free`, but for command buffers of the secondary level.
+    ///
+    /// Primary and secondary command buffers can't be used in place of each
+    /// other, so they are recycled separately.
+    free_secondary: Vec<vk::CommandBuffer>,
+
+    /// Like `discarded`, but for command buffers of the secondary level.
+    discarded_secondary: Vec<vk::CommandBuffer>,
+
+    /// If true, `active` is a secondary command buffer.
+    active_is_secondary: bool,
+
+    /// If true, `active` was provided to [`CommandEncoder::begin_encoding_with`].
+    active_is_external: bool,
+
+    /// How the commands of the currently open render pass are provided.
+    subpass_contents: vk::SubpassContents,
+
+    /// Inheritance info for secondary command buffers executed within
+    /// the currently open render pass, if any.
+    render_pass_inheritance: Option<CommandBufferInheritance>,
+
+    /// Last graphics and compute pipelines bound in the current pass, or
+    /// null, used to skip redundant binds.
+    bound_render_pipeline: vk::Pipeline,
+    bound_compute_pipeline: vk::Pipeline,
+
+    /// Number of color attachments of the bound render pipeline, if their
+    /// write enables are a dynamic state.
+    color_write_attachments: Option<u32>,
+
+    /// Signature of the currently open render pass, used to check the
+    /// compatibility of bound pipelines in debug builds.
+    render_pass_signature: Option<RenderPassSignature>,
+
+    /// Formats of the color attachments of the currently open render pass,
+    /// used to pick the clear value type in `clear_attachments`.
+    render_pass_color_formats:
+        ArrayVec<Option<wgt::TextureFormat>, { crate::MAX_COLOR_ATTACHMENTS }>,
+
+    /// Numbers of viewports and scissor rectangles set in the currently open
+    /// render pass, which draws check against each other in debug builds.
+    viewport_count: u32,
+    scissor_count: u32,
+
+    /// Commands recorded since the last [`CommandEncoder::take_stats`],
+    /// only counted in debug builds.
+    stats: EncoderStats,
+
+    /// Bind point of the currently open pass: `GRAPHICS` for render passes
+    /// and `COMPUTE` for compute passes. Used to catch pass commands recorded
+    /// in the wrong kind of pass in debug builds.
+    active_pass: Option<vk::PipelineBindPoint>,
+
+    /// Bind groups set since the last draw or dispatch, not bound yet.
+    pending_bind_groups: PendingBindGroups,
+
+    /// Timeline semaphore value to signal once `active` completes, see
+    /// [`CommandEncoder::signal_on_completion`].
+    signal: Option<(vk::Semaphore, crate::FenceValue)>,
+
     /// If this is true, the active renderpass enabled a debug span,
     /// and needs to be disabled on renderpass close.
     rpass_debug_marker_active: bool,
@@ -807,12 +1175,154 @@ pub struct CommandEncoder {
     /// If set, the end of the next render/compute pass will write a timestamp at
     /// the given pool & location.
     end_of_pass_timer_query: Option<(vk::QueryPool, u32)>,
+
+    /// If false, texture barriers are recorded as requested, without being
+    /// skipped or merged, see [`CommandEncoder::set_barrier_elision`].
+    barrier_elision: bool,
+}
+
+/// State of a recording which doesn't outlive it, kept by the encoder for
+/// the current recording and by [`SuspendedEncoding`] for the ones set aside.
+#[derive(Debug)]
+struct EncodingState {
+    /// How the commands of the next render pass are going to be provided.
+    next_subpass_contents: vk::SubpassContents,
+
+    /// Depth-stencil resolve attachment of the next render pass, with its
+    /// view and framebuffer attachment.
+    next_depth_stencil_resolve:
+        Option<(DepthStencilResolveKey, vk::ImageView, FramebufferAttachment)>,
+
+    /// Color attachments that the next render pass clears instead of
+    /// loading, see [`CommandEncoder::clear_on_load`].
+    pending_clears: ArrayVec<(vk::ImageView, wgt::Color), { crate::MAX_COLOR_ATTACHMENTS }>,
+
+    /// Layouts of the color and depth-stencil attachments of the next
+    /// render pass, see [`CommandEncoder::begin_render_pass_with_layouts`].
+    next_attachment_layouts: Option<(
+        ArrayVec<AttachmentLayouts, { crate::MAX_COLOR_ATTACHMENTS }>,
+        AttachmentLayouts,
+    )>,
+
+    /// Shading rate attachment of the next render pass, with its view and
+    /// framebuffer attachment.
+    next_shading_rate_attachment: Option<(
+        ShadingRateAttachmentKey,
+        vk::ImageView,
+        FramebufferAttachment,
+    )>,
+
+    /// Fragment shading rate and combiner operations set by
+    /// [`CommandEncoder::set_fragment_shading_rate`].
+    fragment_shading_rate: (vk::Extent2D, [vk::FragmentShadingRateCombinerOpKHR; 2]),
+
+    /// Line width applied to pipelines with a dynamic line width.
+    line_width: f32,
+
+    /// Depth bounds applied to pipelines with dynamic depth bounds, if set
+    /// by `set_depth_bounds`.
+    depth_bounds: Option<[f32; 2]>,
+
+    /// Sample locations applied to pipelines with dynamic sample locations,
+    /// if set by `set_sample_locations`.
+    sample_locations: Option<SampleLocationsInfo>,
+
+    /// Queries reset in the current encoding, if queries are reset before
+    /// their first use, see [`CommandEncoder::set_auto_reset_queries`].
+    auto_reset_queries: Option<rustc_hash::FxHashSet<(vk::QueryPool, u32)>>,
+
+    /// Usages of the resources transitioned in the current encoding, if
+    /// copies check them, see [`CommandEncoder::set_usage_checks`].
+    usage_tracker: Option<UsageTracker>,
+}
+
+impl EncodingState {
+    fn new() -> Self {
+        Self {
+            next_subpass_contents: vk::SubpassContents::INLINE,
+            next_depth_stencil_resolve: None,
+            pending_clears: ArrayVec::new(),
+            next_attachment_layouts: None,
+            next_shading_rate_attachment: None,
+            fragment_shading_rate: DEFAULT_FRAGMENT_SHADING_RATE,
+            line_width: 1.0,
+            depth_bounds: None,
+            sample_locations: None,
+            auto_reset_queries: None,
+            usage_tracker: None,
+        }
+    }
+
+    /// Returns the state of a new recording, which resets queries and
+    /// tracks usages if `self` does.
+    fn begin(&self) -> Self {
+        Self {
+            auto_reset_queries: self.auto_reset_queries.as_ref().map(|_| Default::default()),
+            usage_tracker: self.usage_tracker.as_ref().map(|_| Default::default()),
+            ..Self::new()
+        }
+    }
+
+    /// Returns the state of a suspended recording resumed in place of
+    /// `current`, whose settings of the encoder apply to it.
+    fn resume(mut self, current: &Self) -> Self {
+        self.auto_reset_queries = current
+            .auto_reset_queries
+            .as_ref()
+            .map(|_| self.auto_reset_queries.take().unwrap_or_default());
+        self.usage_tracker = current
+            .usage_tracker
+            .as_ref()
+            .map(|_| self.usage_tracker.take().unwrap_or_default());
+        self
+    }
+
+    /// Returns `true` if the query must be reset before its use, i.e. if
+    /// queries are reset automatically and this one wasn't yet.
+    fn needs_query_reset(&mut self, pool: vk::QueryPool, index: u32) -> bool {
+        match self.auto_reset_queries {
+            Some(ref mut reset) => reset.insert((pool, index)),
+            None => false,
+        }
+    }
+}
+
+/// Usages that buffers and whole textures were last transitioned to.
+#[derive(Debug, Default)]
+struct UsageTracker {
+    buffers: rustc_hash::FxHashMap<vk::Buffer, crate::BufferUses>,
+    textures: rustc_hash::FxHashMap<vk::Image, crate::TextureUses>,
+}
+
+impl UsageTracker {
+    fn record_buffer(&mut self, barrier: &crate::BufferBarrier<'_, Buffer>) {
+        self.buffers.insert(barrier.buffer.raw, barrier.usage.end);
+    }
+
+    /// Records the usage of `barrier` if it covers the whole texture, and
+    /// forgets the usage of the texture otherwise.
+    fn record_texture(&mut self, barrier: &crate::TextureBarrier<'_, Texture>) {
+        if barrier.texture.covers_all_subresources(&barrier.range) {
+            self.textures.insert(barrier.texture.raw, barrier.usage.end);
+        } else {
+            self.textures.remove(&barrier.texture.raw);
+        }
+    }
 }
 
 impl CommandEncoder {
+    /// Returns the command buffer being recorded, or null if not recording.
+    ///
+    /// Commands can be recorded into it directly, for instance by a
+    /// third-party library, between calls to the encoder.
+    ///
     /// # Safety
     ///
     /// - The command buffer handle must not be manually destroyed
+    /// - The command buffer must not be ended, reset or freed, since the
+    ///   encoder still owns it.
+    /// - Recorded commands must leave resources in the states the encoder
+    ///   tracks, and must not leave a render pass open or close one.
     pub unsafe fn raw_handle(&self) -> vk::CommandBuffer {
         self.active
     }
@@ -826,17 +1336,238 @@ impl fmt::Debug for CommandEncoder {
     }
 }
 
+/// Error returned by the `try_*` methods of [`CommandEncoder`] when the
+/// command needs features the device was not created with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, thiserror::Error)]
+#[error("Recording this command requires features {0:?}, which are not enabled on the device")]
+pub struct UnsupportedFeatureError(pub wgt::Features);
+
+/// Result of [`Device::get_descriptor_set_layout_support`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DescriptorSetLayoutSupport {
+    /// Whether a layout with these entries can be created.
+    pub supported: bool,
+    /// The total number of descriptors in the layout.
+    pub descriptor_count: u32,
+    /// The device's `maxPerSetDescriptors` limit.
+    pub max_per_set_descriptors: u32,
+}
+
+/// The value written by [`CommandEncoder::clear_texture`].
+#[derive(Clone, Copy, Debug)]
+pub enum TextureClearValue {
+    /// Clear value for color formats.
+    Color(wgt::Color),
+    /// Clear values for depth and/or stencil formats.
+    DepthStencil { depth: f32, stencil: u32 },
+}
+
+/// Numbers of commands recorded by a [`CommandEncoder`], returned by
+/// [`CommandEncoder::take_stats`].
+///
+/// Each recording method counts as one command, so a multi-draw indirect
+/// call is a single draw, and a barrier is a single `vkCmdPipelineBarrier`
+/// whatever the number of resources it transitions.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct EncoderStats {
+    pub draws: u32,
+    pub dispatches: u32,
+    pub copies: u32,
+    pub barriers: u32,
+}
+
+/// Vulkan-specific options of a render pipeline, see
+/// [`Device::create_render_pipeline_with_options`].
+///
+/// Each option makes some state of the pipeline dynamic, which is set again
+/// whenever it's bound, so they're all disabled by default. Pipeline
+/// creation fails if an option isn't supported.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderPipelineOptions {
+    /// Test the depth bounds set with [`CommandEncoder::set_depth_bounds`],
+    /// if the pipeline has a depth-stencil attachment. Requires
+    /// [`CommandEncoder::supports_depth_bounds`].
+    pub depth_bounds: bool,
+    /// Use the sample locations set with
+    /// [`CommandEncoder::set_sample_locations`] instead of the standard
+    /// ones. Requires a multisampled pipeline, and
+    /// [`CommandEncoder::supports_sample_locations`] for its sample count.
+    pub custom_sample_locations: bool,
+    /// Enable or disable writes to each color attachment with
+    /// [`CommandEncoder::set_color_write_enable`]. Requires
+    /// [`CommandEncoder::supports_color_write_enable`].
+    pub dynamic_color_write_enable: bool,
+    /// Allow overriding the vertex buffer layouts with
+    /// [`CommandEncoder::set_vertex_input`]. Requires
+    /// [`CommandEncoder::supports_vertex_input_dynamic_state`].
+    pub dynamic_vertex_input: bool,
+    /// Use the fragment shading rate set with
+    /// [`CommandEncoder::set_fragment_shading_rate`]. Requires
+    /// [`CommandEncoder::supports_fragment_shading_rate`].
+    pub dynamic_fragment_shading_rate: bool,
+    /// Texel size of the shading rate attachment of the render passes the
+    /// pipeline is used in, if they're begun with
+    /// [`CommandEncoder::begin_render_pass_with_shading_rate_attachment`].
+    pub shading_rate_attachment_texel_size: Option<[u32; 2]>,
+}
+
+/// Custom sample locations set by [`CommandEncoder::set_sample_locations`].
+///
+/// The locations repeat over a grid of `grid_size` pixels, so that
+/// neighbouring pixels can use different patterns.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SampleLocationsInfo {
+    /// Number of samples per pixel, which must match the render pipelines.
+    pub sample_count: u32,
+    /// Width and height of the grid, in pixels.
+    pub grid_size: [u32; 2],
+    /// Location of each sample of each pixel of the grid, within the pixel
+    /// in `0.0..=1.0`. Pixels are in row-major order.
+    pub locations: Vec<[f32; 2]>,
+}
+
+/// Usages of a render pass attachment before and after the pass, see
+/// [`CommandEncoder::begin_render_pass_with_layouts`].
+///
+/// The layouts derived from them replace the initial and final layouts of
+/// the attachment, which are otherwise those of its usage in the pass.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AttachmentLayouts {
+    /// Usage of the attachment before the pass, e.g. `COLOR_TARGET` if it
+    /// is still in that layout from the previous frame.
+    pub before: Option<crate::TextureUses>,
+    /// Usage of the attachment after the pass, e.g. `RESOURCE` if it is
+    /// sampled next.
+    pub after: Option<crate::TextureUses>,
+}
+
+/// An attachment of the current render pass cleared by
+/// [`CommandEncoder::clear_attachments`].
+#[derive(Clone, Copy, Debug)]
+pub enum AttachmentClear {
+    /// Clears the color attachment at index `attachment` of the render pass
+    /// descriptor.
+    Color { attachment: u32, value: wgt::Color },
+    /// Clears the aspects of the depth-stencil attachment that have a value.
+    DepthStencil {
+        depth: Option<f32>,
+        stencil: Option<u32>,
+    },
+}
+
+/// The point of the pipeline at which [`CommandEncoder::write_timestamp_at`]
+/// writes a timestamp, once all previous commands reached it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimestampStage {
+    /// Before any work of the previous commands starts.
+    TopOfPipe,
+    /// After the vertex shaders of the previous commands.
+    VertexShader,
+    /// After the fragment shaders of the previous commands.
+    FragmentShader,
+    /// After the compute shaders of the previous commands.
+    ComputeShader,
+    /// After all work of the previous commands completed.
+    BottomOfPipe,
+}
+
+/// A debug marker scope begun by [`CommandEncoder::debug_marker_scope`].
+///
+/// Ends the marker when dropped, so early returns can't leave it open.
+/// Commands are recorded through the guard, which dereferences to the
+/// encoder.
+#[derive(Debug)]
+pub struct DebugMarkerGuard<'a> {
+    encoder: &'a mut CommandEncoder,
+}
+
+/// A scaled copy between two texture regions, see [`CommandEncoder::blit_texture`].
+#[derive(Clone, Debug)]
+pub struct TextureBlit {
+    pub src_base: crate::TextureCopyBase,
+    pub src_size: crate::CopyExtent,
+    pub dst_base: crate::TextureCopyBase,
+    pub dst_size: crate::CopyExtent,
+}
+
+/// A queue family ownership transfer of a single resource.
+///
+/// The `release` barrier has to be recorded on a queue of the old family
+/// with [`CommandEncoder::release_ownership`], and the `acquire` barrier on a
+/// queue of the new family with [`CommandEncoder::acquire_ownership`]. The
+/// submission containing the acquire has to wait on a semaphore signaled by
+/// the one containing the release.
+#[derive(Clone, Copy, Debug)]
+pub struct OwnershipTransfer<B> {
+    pub release: B,
+    pub acquire: B,
+    pub src_stages: vk::PipelineStageFlags,
+    pub dst_stages: vk::PipelineStageFlags,
+}
+
+pub type BufferOwnershipTransfer = OwnershipTransfer<vk::BufferMemoryBarrier<'static>>;
+pub type TextureOwnershipTransfer = OwnershipTransfer<vk::ImageMemoryBarrier<'static>>;
+
+/// Opt-in tracker of the queue family owning each `VK_SHARING_MODE_EXCLUSIVE` resource.
+///
+/// `wgpu-hal` itself only ever uses a single queue, so nothing is tracked
+/// unless the user creates this with [`Device::create_queue_ownership_tracker`]
+/// and reports every access to a resource shared between queue families.
+#[derive(Debug)]
+pub struct QueueOwnershipTracker {
+    buffers: rustc_hash::FxHashMap<vk::Buffer, u32>,
+    textures: rustc_hash::FxHashMap<vk::Image, u32>,
+    texture_s8: bool,
+}
+
+/// The render pass state a secondary command buffer continues.
+///
+/// Obtained from [`CommandEncoder::render_pass_inheritance`] on the encoder
+/// that is going to execute the secondary command buffer.
+#[derive(Clone, Copy, Debug)]
+pub struct CommandBufferInheritance {
+    pub render_pass: vk::RenderPass,
+    pub subpass: u32,
+    pub framebuffer: vk::Framebuffer,
+    pub extent: wgt::Extent3d,
+}
+
 #[derive(Debug)]
 pub struct CommandBuffer {
     raw: vk::CommandBuffer,
+    /// If true, this can only be executed from another command buffer
+    /// via [`CommandEncoder::execute_bundles`].
+    secondary: bool,
+    /// If true, `raw` was provided to [`CommandEncoder::begin_encoding_with`]
+    /// and isn't recycled by the encoder.
+    external: bool,
+    /// Timeline semaphore value signaled by the submission of this.
+    signal: Option<(vk::Semaphore, crate::FenceValue)>,
 }
 
 impl crate::DynCommandBuffer for CommandBuffer {}
 
+/// A recording set aside by [`CommandEncoder::suspend_encoding`], to be
+/// continued with [`CommandEncoder::resume_encoding`].
+///
+/// Dropping it leaks the command buffer until the pool of the encoder is
+/// reset.
+#[derive(Debug)]
+pub struct SuspendedEncoding {
+    /// The command buffer, still in the recording state.
+    cmd_buf: CommandBuffer,
+    encoding: EncodingState,
+}
+
 #[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
 pub enum ShaderModule {
-    Raw(vk::ShaderModule),
+    Raw {
+        raw: vk::ShaderModule,
+        /// Entry points declared by the SPIR-V module, used to validate the
+        /// entry point names of pipeline stages.
+        entry_points: Vec<(naga::ShaderStage, String)>,
+    },
     Intermediate {
         naga_shader: crate::NagaShader,
         runtime_checks: bool,
@@ -848,6 +1579,42 @@ impl crate::DynShaderModule for ShaderModule {}
 #[derive(Debug)]
 pub struct RenderPipeline {
     raw: vk::Pipeline,
+    render_pass_signature: RenderPassSignature,
+    /// If true, the pipeline rasterizes lines with a dynamic width.
+    dynamic_line_width: bool,
+    /// Depth bias of the pipeline, if enabled. It's a dynamic state, which
+    /// is reset to these values whenever the pipeline is bound.
+    depth_bias: Option<wgt::DepthBiasState>,
+    /// Depth clamp enable of the pipeline, if it's a dynamic state. It's
+    /// reset to this value whenever the pipeline is bound.
+    depth_clamp: Option<bool>,
+    /// Primitive topology of the pipeline, if it's a dynamic state. It's
+    /// reset to this value whenever the pipeline is bound.
+    topology: Option<vk::PrimitiveTopology>,
+    /// Cull mode and front face of the pipeline, if they're dynamic states.
+    /// They're reset to these values whenever the pipeline is bound.
+    cull_mode: Option<(vk::CullModeFlags, vk::FrontFace)>,
+    /// If true, the pipeline tests depth bounds, which are a dynamic state.
+    dynamic_depth_bounds: bool,
+    /// If true, the pipeline uses custom sample locations, which are a
+    /// dynamic state.
+    dynamic_sample_locations: bool,
+    /// If true, the fragment shading rate is a dynamic state.
+    dynamic_fragment_shading_rate: bool,
+    /// Number of color attachments, if their write enables are a dynamic
+    /// state. They're all enabled whenever the pipeline is bound.
+    color_write_attachments: Option<u32>,
+    /// Vertex buffer layouts of the pipeline, if they're a dynamic state.
+    /// They're reset to these whenever the pipeline is bound.
+    vertex_input: Option<VertexInput>,
+}
+
+/// Vertex buffer bindings and attributes set with
+/// `VK_EXT_vertex_input_dynamic_state`.
+#[derive(Debug, Default)]
+struct VertexInput {
+    bindings: Vec<vk::VertexInputBindingDescription2EXT<'static>>,
+    attributes: Vec<vk::VertexInputAttributeDescription2EXT<'static>>,
 }
 
 impl crate::DynRenderPipeline for RenderPipeline {}
@@ -869,10 +1636,88 @@ impl crate::DynPipelineCache for PipelineCache {}
 #[derive(Debug)]
 pub struct QuerySet {
     raw: vk::QueryPool,
+    /// Number of queries in the pool.
+    count: u32,
+    result_kind: QueryResultKind,
+}
+
+impl QuerySet {
+    /// Checks that `range` is within the queries of the set.
+    fn check_range(&self, range: &std::ops::Range<u32>) {
+        if range.start > range.end || range.end > self.count {
+            hal_usage_error(format!(
+                "query range {range:?} is out of the bounds of a set of {} queries",
+                self.count
+            ));
+        }
+    }
+
+    /// Returns what the queries of this set write with
+    /// [`crate::CommandEncoder::copy_query_results`].
+    pub fn result_kind(&self) -> QueryResultKind {
+        self.result_kind
+    }
+}
+
+/// The results of the queries of a [`QuerySet`], each written as `u64`s.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryResultKind {
+    /// The number of samples that passed the depth and stencil tests.
+    Occlusion,
+    /// One counter per requested pipeline statistic.
+    PipelineStatistics { count: u32 },
+    /// A timestamp in ticks, see [`Device::decode_timestamp`].
+    Timestamp,
+}
+
+impl QueryResultKind {
+    /// Returns the size of the result of one query, in bytes.
+    pub fn size(&self) -> wgt::BufferAddress {
+        let values = match *self {
+            Self::Occlusion | Self::Timestamp => 1,
+            Self::PipelineStatistics { count } => count,
+        };
+        values as wgt::BufferAddress * mem::size_of::<u64>() as wgt::BufferAddress
+    }
+}
+
+/// How [`CommandEncoder::copy_query_results_with`] treats queries whose
+/// results aren't available yet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QueryResultMode {
+    /// Wait for every query to finish, so that all results are exact.
+    #[default]
+    Wait,
+    /// Don't wait: unfinished queries write an intermediate value, which may
+    /// be stale or zero.
+    ///
+    /// Each result is followed by one more `u64`, non-zero if the result is
+    /// final. Only those results should be trusted, and the stride must
+    /// leave room for the extra value.
+    ///
+    /// Not valid for timestamp queries.
+    Partial,
+    /// Don't wait: only finished queries write their result.
+    ///
+    /// Each result is followed by one more `u64`, non-zero if the result was
+    /// written, so that a result of an occlusion query takes 16 bytes laid
+    /// out as `[value, available]`. Unavailable results are left untouched
+    /// and can be skipped, and the stride must leave room for the extra
+    /// value.
+    Availability,
 }
 
 impl crate::DynQuerySet for QuerySet {}
 
+/// A Vulkan event, for splitting barriers with
+/// [`CommandEncoder::set_event`] and [`CommandEncoder::wait_events`].
+///
+/// Created by [`Device::create_event`].
+#[derive(Debug)]
+pub struct Event {
+    raw: vk::Event,
+}
+
 /// The [`Api::Fence`] type for [`vulkan::Api`].
 ///
 /// This is an `enum` because there are two possible implementations of
@@ -1114,7 +1959,16 @@ impl crate::Queue for Queue {
 
         let vk_cmd_buffers = command_buffers
             .iter()
-            .map(|cmd| cmd.raw)
+            .map(|cmd| {
+                if cmd.secondary {
+                    hal_usage_error("secondary command buffers can't be submitted to a queue");
+                }
+                if let Some((raw, value)) = cmd.signal {
+                    signal_semaphores.push(raw);
+                    signal_values.push(value);
+                }
+                cmd.raw
+            })
             .collect::<Vec<_>>();
 
         let mut vk_info = vk::SubmitInfo::default().command_buffers(&vk_cmd_buffers);
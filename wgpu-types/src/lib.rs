@@ -952,6 +952,14 @@ bitflags::bitflags! {
         ///
         /// This is a native only feature.
         const SHADER_INT64_ATOMIC_ALL_OPS = 1 << 61;
+        /// Allows the use of [`AdvancedBlendState`] in a [`BlendState`], for
+        /// blend equations such as multiply, screen and overlay.
+        ///
+        /// Supported platforms:
+        /// - Vulkan (with VK_EXT_blend_operation_advanced and coherent operations)
+        ///
+        /// This is a native only feature.
+        const BLEND_OPERATION_ADVANCED = 1 << 62;
     }
 }
 
@@ -2082,6 +2090,73 @@ impl Default for BlendComponent {
     }
 }
 
+/// Advanced blend equation, see [`AdvancedBlendState`].
+///
+/// These are the blend modes of the `KHR_blend_equation_advanced` family of
+/// extensions, see the Vulkan or OpenGL spec for the exact equations.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum AdvancedBlendOperation {
+    /// Src * Dst
+    Multiply = 0,
+    /// Src + Dst - Src * Dst
+    Screen = 1,
+    /// Multiply or screen, depending on Dst
+    Overlay = 2,
+    /// min(Src, Dst)
+    Darken = 3,
+    /// max(Src, Dst)
+    Lighten = 4,
+    /// Brightens Dst to reflect Src
+    ColorDodge = 5,
+    /// Darkens Dst to reflect Src
+    ColorBurn = 6,
+    /// Multiply or screen, depending on Src
+    HardLight = 7,
+    /// Darken or lighten, depending on Src
+    SoftLight = 8,
+    /// |Src - Dst|
+    Difference = 9,
+    /// Src + Dst - 2 * Src * Dst
+    Exclusion = 10,
+}
+
+/// How the coverage of the source and destination is assumed to overlap
+/// in advanced blending.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum BlendOverlap {
+    /// The coverage of source and destination is uncorrelated.
+    #[default]
+    Uncorrelated = 0,
+    /// The source and destination coverage are considered to have minimal overlap.
+    Disjoint = 1,
+    /// The source and destination coverage are considered to have maximal overlap.
+    Conjoint = 2,
+}
+
+/// Describes an advanced blend equation of a [`BlendState`].
+///
+/// Requires [`Features::BLEND_OPERATION_ADVANCED`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct AdvancedBlendState {
+    /// The blend equation, applied to both color and alpha.
+    pub operation: AdvancedBlendOperation,
+    /// Whether the color output of the fragment shader is premultiplied by its alpha.
+    pub src_premultiplied: bool,
+    /// Whether the color stored in the target is premultiplied by its alpha.
+    pub dst_premultiplied: bool,
+    /// How the coverage of the source and destination overlap.
+    pub overlap: BlendOverlap,
+}
+
 /// Describe the blend state of a render pipeline,
 /// within [`ColorTargetState`].
 ///
@@ -2098,13 +2173,45 @@ pub struct BlendState {
     pub color: BlendComponent,
     /// Alpha equation.
     pub alpha: BlendComponent,
+    /// Advanced blend equation, replacing both `color` and `alpha` if set.
+    ///
+    /// All color targets using advanced blending within a pipeline must agree
+    /// on [`AdvancedBlendState::src_premultiplied`],
+    /// [`AdvancedBlendState::dst_premultiplied`] and
+    /// [`AdvancedBlendState::overlap`]. Devices without independent advanced
+    /// blending also require the same [`AdvancedBlendState::operation`].
+    ///
+    /// Requires [`Features::BLEND_OPERATION_ADVANCED`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub advanced: Option<AdvancedBlendState>,
 }
 
 impl BlendState {
+    /// Creates a blend state from separate color and alpha equations.
+    pub const fn new(color: BlendComponent, alpha: BlendComponent) -> Self {
+        Self {
+            color,
+            alpha,
+            advanced: None,
+        }
+    }
+
+    /// Creates a blend state using the advanced blend equation `advanced`.
+    ///
+    /// Requires [`Features::BLEND_OPERATION_ADVANCED`].
+    pub const fn advanced(advanced: AdvancedBlendState) -> Self {
+        Self {
+            color: BlendComponent::REPLACE,
+            alpha: BlendComponent::REPLACE,
+            advanced: Some(advanced),
+        }
+    }
+
     /// Blend mode that does no color blending, just overwrites the output with the contents of the shader.
     pub const REPLACE: Self = Self {
         color: BlendComponent::REPLACE,
         alpha: BlendComponent::REPLACE,
+        advanced: None,
     };
 
     /// Blend mode that does standard alpha blending with non-premultiplied alpha.
@@ -2115,12 +2222,14 @@ impl BlendState {
             operation: BlendOperation::Add,
         },
         alpha: BlendComponent::OVER,
+        advanced: None,
     };
 
     /// Blend mode that does standard alpha blending with premultiplied alpha.
     pub const PREMULTIPLIED_ALPHA_BLENDING: Self = Self {
         color: BlendComponent::OVER,
         alpha: BlendComponent::OVER,
+        advanced: None,
     };
 }
 